@@ -0,0 +1,93 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use anyhow::Context;
+use anyhow::Result;
+use nix::sched::sched_setaffinity;
+use nix::sched::CpuSet;
+use nix::unistd::Pid;
+
+/// Parse a CPU list like "0-7,16" into the sorted CPU numbers it contains
+pub fn parse_cpu_list(cpu_list: &str) -> Result<Vec<usize>> {
+    let parse_cpu = |cpu: &str| {
+        cpu.trim()
+            .parse::<usize>()
+            .with_context(|| format!("Invalid CPU {cpu:?} in CPU list {cpu_list:?}"))
+    };
+
+    let mut cpus = vec![];
+    for item in cpu_list.split(',') {
+        match item.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (parse_cpu(first)?, parse_cpu(last)?);
+                if first > last {
+                    anyhow::bail!("Invalid CPU range {item:?} in CPU list {cpu_list:?}");
+                }
+                cpus.extend(first..=last);
+            }
+            None => cpus.push(parse_cpu(item)?),
+        }
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+/// Build the CPU set for sched_setaffinity from the CPU numbers
+pub fn to_cpu_set(cpus: &[usize]) -> Result<CpuSet> {
+    let mut cpu_set = CpuSet::new();
+    for cpu in cpus {
+        cpu_set
+            .set(*cpu)
+            .with_context(|| format!("CPU {cpu} exceeds the supported number of CPUs"))?;
+    }
+    Ok(cpu_set)
+}
+
+/// Pin the process to the given CPUs
+pub fn set_cpu_affinity(pid: u32, cpus: &[usize]) -> Result<()> {
+    let cpu_set = to_cpu_set(cpus)?;
+    sched_setaffinity(Pid::from_raw(pid as i32), &cpu_set)
+        .with_context(|| format!("Failed to set CPU affinity of {pid}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::affinity::*;
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0-3").unwrap(), vec![0, 1, 2, 3]);
+        assert_eq!(parse_cpu_list("0,2,4").unwrap(), vec![0, 2, 4]);
+        assert_eq!(
+            parse_cpu_list("16,0-2, 8-9").unwrap(),
+            vec![0, 1, 2, 8, 9, 16]
+        );
+        assert_eq!(parse_cpu_list("3,1-3").unwrap(), vec![1, 2, 3]);
+        assert_eq!(parse_cpu_list("5-5").unwrap(), vec![5]);
+    }
+
+    #[test]
+    fn test_parse_cpu_list_malformed() {
+        for cpu_list in ["", "0,", "a", "1-", "-3", "0-3-5", "4-2", "0;1", "-1"] {
+            assert!(parse_cpu_list(cpu_list).is_err(), "{cpu_list:?}");
+        }
+        assert_eq!(
+            parse_cpu_list("4-2").unwrap_err().to_string(),
+            "Invalid CPU range \"4-2\" in CPU list \"4-2\""
+        );
+    }
+
+    #[test]
+    fn test_to_cpu_set() {
+        let cpu_set = to_cpu_set(&[0, 3]).unwrap();
+        assert!(cpu_set.is_set(0).unwrap());
+        assert!(!cpu_set.is_set(1).unwrap());
+        assert!(cpu_set.is_set(3).unwrap());
+        assert!(to_cpu_set(&[CpuSet::count()]).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::SchedMode;
+
+impl Config {
+    /// Parse the mode name, either a built-in mode name or number or one of the configured
+    /// aliases, the names ignoring case
+    pub fn resolve_mode(&self, mode_name: &str) -> Result<SchedMode> {
+        if let Ok(sched_mode) = SchedMode::parse_loose(mode_name) {
+            return Ok(sched_mode);
+        }
+        self.aliases
+            .iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(mode_name))
+            .map(|(_, sched_mode)| sched_mode.clone())
+            .ok_or_else(|| anyhow::anyhow!("Unknown scheduler mode {mode_name}"))
+    }
+
+    /// Check that no alias redefines a built-in mode name as a different mode
+    pub fn check_aliases(&self) -> Vec<String> {
+        let mut errors = vec![];
+        for (alias, sched_mode) in &self.aliases {
+            let Ok(builtin_mode) = alias.parse::<SchedMode>() else {
+                continue;
+            };
+            if builtin_mode != *sched_mode {
+                errors.push(format!(
+                    "alias {alias} can't redefine the built-in mode {builtin_mode} as {sched_mode}"
+                ));
+            }
+        }
+        errors.sort();
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alias::*;
+    use crate::config::parse_config;
+
+    #[test]
+    fn test_resolve_alias() {
+        let config = parse_config(
+            r#"
+[aliases]
+battery = "PowerSave"
+performance = "Gaming"
+"#,
+        );
+
+        assert_eq!(
+            config.resolve_mode("battery").unwrap(),
+            SchedMode::PowerSave
+        );
+        assert_eq!(
+            config.resolve_mode("Performance").unwrap(),
+            SchedMode::Gaming
+        );
+        // the built-in names and numbers keep working
+        assert_eq!(config.resolve_mode("gaming").unwrap(), SchedMode::Gaming);
+        assert_eq!(config.resolve_mode("2").unwrap(), SchedMode::PowerSave);
+        assert_eq!(
+            config.resolve_mode("low-latency").unwrap(),
+            SchedMode::LowLatency
+        );
+        assert_eq!(
+            config.resolve_mode("turbo").unwrap_err().to_string(),
+            "Unknown scheduler mode turbo"
+        );
+        assert!(config.check_aliases().is_empty());
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_alias_redefines_builtin() {
+        let config = parse_config(
+            r#"
+[aliases]
+gaming = "PowerSave"
+Server = "Server"
+"#,
+        );
+
+        assert_eq!(
+            config.check_aliases(),
+            vec!["alias gaming can't redefine the built-in mode gaming as powersave"]
+        );
+        assert!(config.validate().is_err());
+        // the built-in mode wins
+        assert_eq!(config.resolve_mode("gaming").unwrap(), SchedMode::Gaming);
+    }
+}
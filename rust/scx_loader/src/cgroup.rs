@@ -0,0 +1,236 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::ffi::CString;
+use std::io;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::get_name_from_scx;
+use crate::oom::ChildIo;
+use crate::SupportedSched;
+
+/// Directory of the cgroup hierarchy relative to sysfs
+const CGROUP_SYSFS_DIR: &str = "fs/cgroup";
+
+/// cgroup the scheduler process is placed in
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchedCgroup {
+    /// cgroup.procs file of the cgroup
+    pub procs_path: PathBuf,
+    /// Fail the start if the process can't be placed in the cgroup
+    pub required: bool,
+}
+
+/// Check that the cgroup path stays below the cgroup hierarchy
+pub fn validate_cgroup_path(cgroup_path: &str) -> Result<()> {
+    let relative_path = Path::new(cgroup_path.trim_start_matches('/'));
+    if relative_path.as_os_str().is_empty() {
+        anyhow::bail!("must not be empty");
+    }
+    if relative_path
+        .components()
+        .any(|component| component == Component::ParentDir)
+    {
+        anyhow::bail!("{cgroup_path} must not contain ..");
+    }
+    if cgroup_path.contains('\0') {
+        anyhow::bail!("{cgroup_path:?} must not contain NUL");
+    }
+    Ok(())
+}
+
+impl Config {
+    /// Get the cgroup the scheduler is placed in, the cgroup of the scheduler overrides the
+    /// global one. An invalid path is reported by `validate`
+    pub fn get_cgroup(&self, scx_sched: &SupportedSched) -> Option<SchedCgroup> {
+        let sched_config = self.scheds.get(get_name_from_scx(scx_sched));
+        let cgroup_path = sched_config
+            .and_then(|sched_config| sched_config.cgroup.as_ref())
+            .or(self.cgroup.as_ref())?;
+        let required = sched_config
+            .and_then(|sched_config| sched_config.cgroup_required)
+            .or(self.cgroup_required)
+            .unwrap_or(false);
+        Some(SchedCgroup {
+            procs_path: self
+                .get_sysfs_path()
+                .join(CGROUP_SYSFS_DIR)
+                .join(cgroup_path.trim_start_matches('/'))
+                .join("cgroup.procs"),
+            required,
+        })
+    }
+}
+
+/// Placement of the scheduler process in its cgroup, the path and the failure message are
+/// prepared before forking like those of `OomScoreAdj`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CgroupPlacement {
+    procs_path: CString,
+    required: bool,
+    failure_message: Vec<u8>,
+}
+
+impl CgroupPlacement {
+    pub fn new(cgroup: &SchedCgroup) -> Result<Self> {
+        let procs_path = cgroup.procs_path.to_string_lossy();
+        Ok(Self {
+            procs_path: CString::new(procs_path.as_bytes())
+                .with_context(|| format!("Invalid cgroup path {procs_path:?}"))?,
+            required: cgroup.required,
+            failure_message: format!("scx_loader: Failed to place the scheduler in {procs_path}\n")
+                .into_bytes(),
+        })
+    }
+
+    /// Move the child into the cgroup before exec. A failed move is reported like a failed
+    /// `OomScoreAdj::apply_in_child`, and only aborts the exec if the cgroup is required
+    pub fn apply_in_child(&self, child_io: &impl ChildIo) -> io::Result<()> {
+        let Err(err) = child_io.write_cgroup_procs(&self.procs_path) else {
+            return Ok(());
+        };
+        child_io.write_stderr(&self.failure_message);
+        match self.required {
+            true => Err(err),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cgroup::*;
+    use crate::oom::RecordingChildIo;
+
+    const PROCS_PATH: &str = "/sys/fs/cgroup/scx.slice/cgroup.procs";
+
+    fn placement(required: bool) -> CgroupPlacement {
+        CgroupPlacement::new(&SchedCgroup {
+            procs_path: PathBuf::from(PROCS_PATH),
+            required,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_get_cgroup() {
+        let config: Config = toml::from_str(
+            r#"
+cgroup = "/scx.slice"
+
+[scheds.scx_lavd]
+cgroup = "scx.slice/lavd"
+cgroup_required = true
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.get_cgroup(&SupportedSched::Lavd),
+            Some(SchedCgroup {
+                procs_path: PathBuf::from("/sys/fs/cgroup/scx.slice/lavd/cgroup.procs"),
+                required: true,
+            })
+        );
+        assert_eq!(
+            config.get_cgroup(&SupportedSched::Rusty),
+            Some(SchedCgroup {
+                procs_path: PathBuf::from(PROCS_PATH),
+                required: false,
+            })
+        );
+        assert_eq!(Config::default().get_cgroup(&SupportedSched::Lavd), None);
+
+        let config = Config {
+            sysfs_path: Some("/tmp/sys".to_owned()),
+            ..config
+        };
+        assert_eq!(
+            config
+                .get_cgroup(&SupportedSched::Rusty)
+                .unwrap()
+                .procs_path,
+            PathBuf::from("/tmp/sys/fs/cgroup/scx.slice/cgroup.procs")
+        );
+    }
+
+    #[test]
+    fn test_validate_cgroup_path() {
+        validate_cgroup_path("scx.slice/lavd").unwrap();
+        validate_cgroup_path("/scx.slice").unwrap();
+        assert_eq!(
+            validate_cgroup_path("/").unwrap_err().to_string(),
+            "must not be empty"
+        );
+        assert_eq!(
+            validate_cgroup_path("scx.slice/../..")
+                .unwrap_err()
+                .to_string(),
+            "scx.slice/../.. must not contain .."
+        );
+
+        let config: Config = toml::from_str(
+            r#"
+[scheds.scx_bpfland]
+cgroup = ""
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.validation_report().errors,
+            vec!["scx_bpfland cgroup: must not be empty"]
+        );
+    }
+
+    #[test]
+    fn test_optional_cgroup_failure() {
+        let child_io = RecordingChildIo::default();
+        placement(false).apply_in_child(&child_io).unwrap();
+        assert_eq!(
+            *child_io.writes.borrow(),
+            vec![("cgroup.procs", PROCS_PATH.to_owned())]
+        );
+
+        // the failure is reported, the start goes on
+        let child_io = RecordingChildIo {
+            fail_cgroup_procs: true,
+            ..Default::default()
+        };
+        placement(false).apply_in_child(&child_io).unwrap();
+        assert_eq!(
+            *child_io.writes.borrow(),
+            vec![
+                ("cgroup.procs", PROCS_PATH.to_owned()),
+                (
+                    "stderr",
+                    format!("scx_loader: Failed to place the scheduler in {PROCS_PATH}\n")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_required_cgroup_failure() {
+        let child_io = RecordingChildIo::default();
+        placement(true).apply_in_child(&child_io).unwrap();
+
+        // the failure is reported and fails the start
+        let child_io = RecordingChildIo {
+            fail_cgroup_procs: true,
+            ..Default::default()
+        };
+        let err = placement(true).apply_in_child(&child_io).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(child_io.writes.borrow().len(), 2);
+        assert_eq!(child_io.writes.borrow()[1].0, "stderr");
+    }
+}
@@ -0,0 +1,3661 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::hash_map::Entry;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::affinity;
+use crate::cgroup;
+use crate::error::ConfigError;
+use crate::flags;
+use crate::get_name_from_scx;
+use crate::hooks::Hooks;
+use crate::kernel::KernelVersion;
+use crate::lock;
+use crate::lock::LockKind;
+use crate::log_fields;
+use crate::log_fields::LogFields;
+use crate::oom;
+use crate::parse_scx_from_name;
+use crate::power::PowerConfig;
+use crate::priority::ProcessPriority;
+use crate::sched_bin;
+use crate::sched_env;
+use crate::schedule::ScheduleEntry;
+use crate::stop::StopBehavior;
+use crate::SchedMode;
+use crate::SupportedSched;
+use crate::SCHED_MODES;
+use crate::SUPPORTED_SCHEDS;
+
+/// Environment variable pointing to the config file, which bypasses the config search
+pub const CONFIG_ENV_VAR: &str = "SCX_LOADER_CONFIG";
+
+/// System-wide config locations, probed after the user config
+const SYSTEM_CONFIG_PATHS: &[&str] = &["/etc/scx_loader/config.toml", "/etc/scx_loader.toml"];
+
+/// Drop-in directory, its `*.toml` files are layered on top of the base config in lexical order
+const CONFIG_DROPIN_DIR: &str = "/etc/scx_loader/config.d";
+
+/// Mount path of sysfs when sysfs_path isn't set
+pub const DEFAULT_SYSFS_PATH: &str = "/sys";
+
+/// Default config shipped with the loader
+const DEFAULT_CONFIG: &str = include_str!("../configs/config.toml");
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub default_sched: Option<SupportedSched>,
+    /// Schedulers tried in order when the default_sched isn't installed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fallback_scheds: Vec<SupportedSched>,
+    pub default_mode: Option<SchedMode>,
+    /// Directories searched for the scheduler binaries before PATH
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub bin_dirs: Vec<String>,
+    /// Flags appended to the flags of every scheduler and mode
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub global_flags: Vec<String>,
+    /// Restart the scheduler when it exits unexpectedly
+    pub auto_restart: Option<bool>,
+    /// Restarts allowed within a minute before giving up, defaults to 3
+    pub restart_limit: Option<u32>,
+    /// Seconds between the checks that the scheduler is still attached, disabled if unset
+    pub health_check_interval: Option<u64>,
+    /// Seconds a started scheduler has to attach to sched_ext before it's reported as failed,
+    /// disabled if unset
+    pub startup_timeout: Option<u64>,
+    /// Nice value and realtime priority the scheduler process is run with
+    pub process_priority: Option<ProcessPriority>,
+    /// OOM score adjustment of the scheduler process, -1000 exempts it from the OOM killer
+    pub oom_score_adj: Option<i32>,
+    /// Directory the stdout and stderr of the schedulers are written to, one file per
+    /// scheduler. The schedulers inherit the streams of the loader if unset
+    pub log_output: Option<String>,
+    /// Size in bytes at which the scheduler log files are rotated, defaults to 10 MiB
+    pub log_output_max_size: Option<u64>,
+    /// cgroup the scheduler process is placed in, a path below /sys/fs/cgroup like "scx.slice"
+    pub cgroup: Option<String>,
+    /// Refuse to start the scheduler if it can't be placed in its cgroup, defaults to false so
+    /// a failure is only logged
+    pub cgroup_required: Option<bool>,
+    /// Reload the config when the config file changes
+    pub watch_config: Option<bool>,
+    /// Scheduler switches kept in the history, defaults to 32
+    pub history_size: Option<usize>,
+    /// Mirror the scheduler switches to the log
+    pub log_history: Option<bool>,
+    /// Refuse to start a scheduler with flags its --help doesn't list, otherwise unknown flags
+    /// are passed through verbatim. Defaults to false
+    pub strict_flags: Option<bool>,
+    /// Milliseconds within which switch requests are coalesced, only the last one is applied.
+    /// Disabled if unset
+    pub switch_debounce_ms: Option<u64>,
+    /// Start the next scheduler while the previous one is still attached and only stop the
+    /// previous one once the next one took over, for kernels which allow replacing the attached
+    /// scheduler. Defaults to false, which stops the previous scheduler first
+    pub switch_handoff: Option<bool>,
+    /// Stop the scheduler when the loader shuts down or leave it running, defaults to
+    /// StopScheduler
+    pub stop_behavior: Option<StopBehavior>,
+    /// Mount path of sysfs the sched_ext and power supply state is read from, defaults to /sys
+    pub sysfs_path: Option<String>,
+    /// Commands run before starting and after stopping every scheduler
+    pub hooks: Option<Hooks>,
+    /// Time windows in which a scheduler is switched to automatically
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub schedule: Vec<ScheduleEntry>,
+    /// Modes switched to when the power source changes
+    pub power: Option<PowerConfig>,
+    /// Modes used while the process name or cgroup path, a glob pattern, is in the foreground
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub process_modes: HashMap<String, SchedMode>,
+    /// Scheduler each mode selects, e.g. scx_tickless for powersave_mode, instead of switching
+    /// the mode of the running scheduler
+    pub mode_scheds: Option<ModeScheds>,
+    /// Additional names of the modes, e.g. "battery" for PowerSave
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub aliases: HashMap<String, SchedMode>,
+    pub scheds: HashMap<String, Sched>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Sched {
+    /// Path to the scheduler binary, declares a custom scheduler which isn't supported out of
+    /// the box. Must not be set for the supported schedulers
+    pub path: Option<String>,
+    /// Offer the scheduler for selection, defaults to true. Disabled schedulers keep their
+    /// config but are hidden from the list of schedulers and skipped as fallback
+    pub enabled: Option<bool>,
+    /// Mode used for this scheduler, overrides the global default_mode
+    pub default_mode: Option<SchedMode>,
+    /// Flags of each mode, either a list or a single string split like a shell does
+    #[serde(default, deserialize_with = "flags::deserialize_flags")]
+    pub auto_mode: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "flags::deserialize_flags")]
+    pub gaming_mode: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "flags::deserialize_flags")]
+    pub lowlatency_mode: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "flags::deserialize_flags")]
+    pub powersave_mode: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "flags::deserialize_flags")]
+    pub server_mode: Option<Vec<String>>,
+    /// Flags appended to the flags of each mode instead of replacing them
+    pub extra_flags: Option<ExtraFlags>,
+    /// Named flag sets beyond the fixed sched modes
+    pub profiles: Option<HashMap<String, Vec<String>>>,
+    /// Human readable description of each mode, e.g. for a settings UI
+    pub descriptions: Option<ModeDescriptions>,
+    /// CPUs the scheduler process is pinned to in each mode
+    pub cpu_affinity: Option<ModeCpuAffinity>,
+    /// Files with additional flags of each mode, appended to the inline flags of the mode
+    pub flags_file: Option<ModeFlagsFiles>,
+    /// Commands run before starting and after stopping this scheduler
+    pub hooks: Option<Hooks>,
+    /// Oldest kernel the scheduler can be started on, e.g. "6.12", overrides the built-in
+    /// requirement
+    pub min_kernel: Option<String>,
+    /// OOM score adjustment of the scheduler process, overrides the global oom_score_adj
+    pub oom_score_adj: Option<i32>,
+    /// Restart the scheduler when a config reload changes its settings, defaults to true.
+    /// Otherwise the changes take effect with the next switch to it
+    pub restart_on_change: Option<bool>,
+    /// File the stdout and stderr of the scheduler are written to, overrides the global
+    /// log_output directory
+    pub log_output: Option<String>,
+    /// Environment variables set for the scheduler process, the values are expanded like the
+    /// flags
+    pub env: Option<HashMap<String, String>>,
+    /// Fewest CPUs the scheduler is offered and started with
+    pub min_cpus: Option<usize>,
+    /// Only offer and start the scheduler on hybrid CPUs, e.g. big.LITTLE
+    pub require_hybrid: Option<bool>,
+    /// cgroup the scheduler process is placed in, overrides the global cgroup
+    pub cgroup: Option<String>,
+    /// Overrides the global cgroup_required
+    pub cgroup_required: Option<bool>,
+}
+
+/// Flags appended per mode to either the configured or the default flags
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ExtraFlags {
+    pub auto_mode: Option<Vec<String>>,
+    pub gaming_mode: Option<Vec<String>>,
+    pub lowlatency_mode: Option<Vec<String>>,
+    pub powersave_mode: Option<Vec<String>>,
+    pub server_mode: Option<Vec<String>>,
+}
+
+/// File format of a config file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Get the format from the file extension, anything but `.json` is TOML
+    pub fn from_path(filepath: &str) -> Self {
+        match Path::new(filepath).extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => ConfigFormat::Json,
+            _ => ConfigFormat::Toml,
+        }
+    }
+}
+
+/// Description per mode, doesn't affect the flags passed to the scheduler
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ModeDescriptions {
+    pub auto_mode: Option<String>,
+    pub gaming_mode: Option<String>,
+    pub lowlatency_mode: Option<String>,
+    pub powersave_mode: Option<String>,
+    pub server_mode: Option<String>,
+}
+
+/// Scheduler per mode, the flags are the ones of the mode for that scheduler
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ModeScheds {
+    pub auto_mode: Option<SupportedSched>,
+    pub gaming_mode: Option<SupportedSched>,
+    pub lowlatency_mode: Option<SupportedSched>,
+    pub powersave_mode: Option<SupportedSched>,
+    pub server_mode: Option<SupportedSched>,
+}
+
+/// CPU list per mode like "0-7,16", see `affinity::parse_cpu_list`
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ModeCpuAffinity {
+    pub auto_mode: Option<String>,
+    pub gaming_mode: Option<String>,
+    pub lowlatency_mode: Option<String>,
+    pub powersave_mode: Option<String>,
+    pub server_mode: Option<String>,
+}
+
+/// Path of a flags file per mode, see `flags::read_flags_file`
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ModeFlagsFiles {
+    pub auto_mode: Option<String>,
+    pub gaming_mode: Option<String>,
+    pub lowlatency_mode: Option<String>,
+    pub powersave_mode: Option<String>,
+    pub server_mode: Option<String>,
+}
+
+/// Problems found in the config by `Config::validate`
+#[derive(Debug, Default, PartialEq)]
+pub struct ValidationReport {
+    /// Problems which make the config unusable
+    pub errors: Vec<String>,
+    /// Suspicious settings which don't prevent the config from being used
+    pub warnings: Vec<String>,
+}
+
+impl ValidationReport {
+    /// Get the exit code of a config check, warnings only fail the check in strict mode
+    pub fn exit_code(&self, strict: bool) -> i32 {
+        if !self.errors.is_empty() || (strict && !self.warnings.is_empty()) {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+impl Config {
+    /// Validate the flags of every configured mode.
+    ///
+    /// Warnings are logged, errors are returned as a single error listing all of them.
+    pub fn validate(&self) -> Result<()> {
+        let report = self.validation_report();
+        for warning in &report.warnings {
+            log_fields::log_with_fields(
+                log::Level::Warn,
+                &LogFields::event("validation_warning"),
+                format_args!("{warning}"),
+            );
+        }
+        if !report.errors.is_empty() {
+            anyhow::bail!("Invalid config: {}", report.errors.join("; "));
+        }
+        Ok(())
+    }
+
+    /// Get the names of the configured schedulers in sorted order
+    pub fn scheduler_names(&self) -> Vec<String> {
+        let mut sched_names: Vec<String> = self.scheds.keys().cloned().collect();
+        sched_names.sort_unstable();
+        sched_names
+    }
+
+    /// Get the mount path of sysfs
+    pub fn get_sysfs_path(&self) -> PathBuf {
+        PathBuf::from(self.sysfs_path.as_deref().unwrap_or(DEFAULT_SYSFS_PATH))
+    }
+
+    /// Check whether the scheduler with the given name is configured
+    pub fn has_scheduler(&self, sched_name: &str) -> bool {
+        self.scheds.contains_key(sched_name)
+    }
+
+    /// Get the names of the configured schedulers which aren't disabled, in sorted order
+    pub fn enabled_schedulers(&self) -> Vec<String> {
+        let mut sched_names = self.scheduler_names();
+        sched_names
+            .retain(|sched_name| self.is_sched_enabled(&SupportedSched::from(sched_name.clone())));
+        sched_names
+    }
+
+    /// Check whether the scheduler is enabled, schedulers without [scheds] entry are unless
+    /// they're opt-in, see `enabled_by_default`
+    pub fn is_sched_enabled(&self, scx_sched: &SupportedSched) -> bool {
+        self.scheds
+            .get(get_name_from_scx(scx_sched))
+            .and_then(|sched| sched.enabled)
+            .unwrap_or_else(|| enabled_by_default(scx_sched))
+    }
+
+    /// Check whether any scheduler has the profile with the given name
+    pub fn has_profile(&self, profile_name: &str) -> bool {
+        self.scheds.values().any(|sched_config| {
+            sched_config
+                .profiles
+                .as_ref()
+                .is_some_and(|profiles| profiles.contains_key(profile_name))
+        })
+    }
+
+    /// Get warnings for settings which are valid but likely don't do what was intended, e.g. a
+    /// default_sched without [scheds] entry silently runs with the built-in flags
+    pub fn check_consistency(&self) -> Vec<String> {
+        let mut warnings = vec![];
+        let default_sched = self
+            .default_sched
+            .iter()
+            .map(|scx_sched| ("default_sched", scx_sched));
+        let fallback_scheds = self
+            .fallback_scheds
+            .iter()
+            .map(|scx_sched| ("fallback_scheds", scx_sched));
+        for (field_name, scx_sched) in default_sched.chain(fallback_scheds) {
+            let sched_name = get_name_from_scx(scx_sched);
+            if !self.has_scheduler(sched_name) {
+                warnings.push(format!("{field_name} {sched_name} has no [scheds] entry"));
+            }
+        }
+        for sched_name in self.scheduler_names() {
+            let Some(SchedMode::Custom(profile_name)) = &self.scheds[&sched_name].default_mode
+            else {
+                continue;
+            };
+            let scx_sched = SupportedSched::from(sched_name.clone());
+            if get_scx_flags_for_profile(self, &scx_sched, profile_name).is_none() {
+                warnings.push(format!(
+                    "{sched_name} default_mode {profile_name} is neither a mode nor a profile, the flags of Auto are used"
+                ));
+            }
+        }
+        for entry in &self.schedule {
+            let Some(SchedMode::Custom(profile_name)) = &entry.mode else {
+                continue;
+            };
+            if get_scx_flags_for_profile(self, &entry.sched, profile_name).is_none() {
+                warnings.push(format!(
+                    "schedule {} mode {profile_name} is neither a mode nor a profile, the flags of Auto are used",
+                    entry.sched
+                ));
+            }
+        }
+        // the scheduler of these modes isn't known up front, any scheduler may have the profile
+        let mut global_modes: Vec<(String, &SchedMode)> = vec![];
+        global_modes.extend(
+            self.default_mode
+                .iter()
+                .map(|sched_mode| ("default_mode".to_owned(), sched_mode)),
+        );
+        if let Some(power_config) = &self.power {
+            global_modes.extend(
+                power_config
+                    .on_battery_mode
+                    .iter()
+                    .map(|sched_mode| ("power on_battery_mode".to_owned(), sched_mode)),
+            );
+            global_modes.extend(
+                power_config
+                    .on_ac_mode
+                    .iter()
+                    .map(|sched_mode| ("power on_ac_mode".to_owned(), sched_mode)),
+            );
+        }
+        let mut patterns: Vec<&String> = self.process_modes.keys().collect();
+        patterns.sort_unstable();
+        for pattern in patterns {
+            global_modes.push((
+                format!("process_modes {pattern}"),
+                &self.process_modes[pattern],
+            ));
+        }
+        for (field_name, sched_mode) in global_modes {
+            let SchedMode::Custom(profile_name) = sched_mode else {
+                continue;
+            };
+            if !self.has_profile(profile_name) {
+                warnings.push(format!(
+                    "{field_name} {profile_name} is neither a mode nor a profile of any scheduler, the flags of Auto are used"
+                ));
+            }
+        }
+        warnings
+    }
+
+    /// Find the chain of default_sched and fallback_scheds up to the first scheduler tried
+    /// twice, e.g. ["scx_bpfland", "scx_rusty", "scx_bpfland"]
+    pub fn find_fallback_cycle(&self) -> Option<Vec<&str>> {
+        let mut chain: Vec<&str> = vec![];
+        for scx_sched in self.default_sched.iter().chain(&self.fallback_scheds) {
+            let sched_name = get_name_from_scx(scx_sched);
+            let repeated = chain.contains(&sched_name);
+            chain.push(sched_name);
+            if repeated {
+                return Some(chain);
+            }
+        }
+        None
+    }
+
+    /// Collect all problems found in the config without logging them
+    pub fn validation_report(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+
+        for sched_name in self.scheduler_names() {
+            let sched_config = &self.scheds[&sched_name];
+            let scx_sched = SupportedSched::from(sched_name.clone());
+            if sched_config
+                .path
+                .as_ref()
+                .is_some_and(|path| path.trim().is_empty())
+            {
+                report
+                    .errors
+                    .push(format!("{sched_name} path: must not be empty"));
+            }
+            if let Some(cgroup_path) = &sched_config.cgroup {
+                if let Err(err) = cgroup::validate_cgroup_path(cgroup_path) {
+                    report.errors.push(format!("{sched_name} cgroup: {err}"));
+                }
+            }
+            if let Some(min_kernel) = &sched_config.min_kernel {
+                if let Err(err) = min_kernel.parse::<KernelVersion>() {
+                    report
+                        .errors
+                        .push(format!("{sched_name} min_kernel: {err}"));
+                }
+            }
+            if let Some(oom_score_adj) = sched_config.oom_score_adj {
+                if let Err(err) = oom::validate_oom_score_adj(oom_score_adj) {
+                    report
+                        .errors
+                        .push(format!("{sched_name} oom_score_adj: {err}"));
+                }
+            }
+            let env_var_names = sched_config.env.iter().flat_map(HashMap::keys);
+            for var_name in env_var_names {
+                if let Err(err) = sched_env::validate_env_var_name(var_name) {
+                    report.errors.push(format!("{sched_name} env: {err}"));
+                }
+            }
+
+            for sched_mode in SCHED_MODES {
+                if let Some(flags_file) = extract_flags_file(sched_config, sched_mode) {
+                    if let Err(err) = flags::read_flags_file(flags_file) {
+                        report.errors.push(format!(
+                            "{sched_name} flags_file {}: {err:#}",
+                            get_mode_field_name(sched_mode)
+                        ));
+                    }
+                }
+                if let Err(err) = get_cpu_affinity(self, &scx_sched, sched_mode) {
+                    report.errors.push(format!(
+                        "{sched_name} cpu_affinity {}: {err}",
+                        get_mode_field_name(sched_mode)
+                    ));
+                }
+                let Some(scx_flags) = extract_scx_flags_from_config(sched_config, sched_mode)
+                else {
+                    continue;
+                };
+                let context = format!("{sched_name} {}", get_mode_field_name(sched_mode));
+                for error in check_scx_flags(&scx_sched, &scx_flags) {
+                    report.errors.push(format!("{context}: {error}"));
+                }
+                for warning in find_deprecated_flags(&scx_sched, &scx_flags) {
+                    report.warnings.push(format!("{context}: {warning}"));
+                }
+                for (flag, opposite_mode) in
+                    find_opposite_intent_flags(&scx_sched, sched_mode, &scx_flags)
+                {
+                    report.warnings.push(format!(
+                        "{context}: {flag} is a built-in flag of {}, which has the opposite intent",
+                        get_mode_field_name(&opposite_mode)
+                    ));
+                }
+                if let Err(err) = flags::convert_durations(&scx_flags) {
+                    report.errors.push(format!("{context}: {err}"));
+                }
+            }
+        }
+        if let Err(err) = flags::convert_durations(&self.global_flags) {
+            report.errors.push(format!("global_flags: {err}"));
+        }
+        if let Some(oom_score_adj) = self.oom_score_adj {
+            if let Err(err) = oom::validate_oom_score_adj(oom_score_adj) {
+                report.errors.push(format!("oom_score_adj: {err}"));
+            }
+        }
+        if let Some(cgroup_path) = &self.cgroup {
+            if let Err(err) = cgroup::validate_cgroup_path(cgroup_path) {
+                report.errors.push(format!("cgroup: {err}"));
+            }
+        }
+        if let Some(cycle) = self.find_fallback_cycle() {
+            report.errors.push(format!(
+                "fallback_scheds: {} is tried more than once ({})",
+                cycle[cycle.len() - 1],
+                cycle.join(" -> ")
+            ));
+        }
+        report.errors.extend(self.check_aliases());
+        report.warnings.extend(self.check_consistency());
+        report
+    }
+}
+
+/// Initialize config from the file set in `SCX_LOADER_CONFIG`, or from first found config path,
+/// overwise fallback to default config
+pub fn init_config() -> Result<Config> {
+    ConfigLoader::new().load()
+}
+
+/// Get the path of the config file loaded by `init_config`, None if the default config is used
+pub fn get_active_config_path() -> Option<String> {
+    ConfigLoader::new().resolve_path()
+}
+
+fn get_config_override() -> Option<String> {
+    env::var(CONFIG_ENV_VAR)
+        .ok()
+        .filter(|path| !path.is_empty())
+}
+
+/// Builder of where the config is loaded from. `ConfigLoader::new()` loads the config like
+/// `init_config` does, embedders can change the probed paths and lookups
+#[derive(Debug, Clone)]
+pub struct ConfigLoader {
+    /// Config file loaded instead of probing the search paths, it must exist
+    config_file: Option<String>,
+    /// Path set in `SCX_LOADER_CONFIG`, None if disabled or unset
+    env_config: Option<String>,
+    /// User config below `$XDG_CONFIG_HOME` or `~/.config` probed first, None if disabled
+    user_config: Option<String>,
+    search_paths: Vec<String>,
+    dropin_dir: Option<PathBuf>,
+}
+
+impl Default for ConfigLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConfigLoader {
+    pub fn new() -> Self {
+        Self {
+            config_file: None,
+            env_config: get_config_override(),
+            user_config: get_user_config_path(),
+            search_paths: SYSTEM_CONFIG_PATHS
+                .iter()
+                .map(|path| path.to_string())
+                .collect(),
+            dropin_dir: Some(PathBuf::from(CONFIG_DROPIN_DIR)),
+        }
+    }
+
+    /// Load this file, takes precedence over `SCX_LOADER_CONFIG` and the search paths
+    pub fn config_file(mut self, config_file: impl Into<String>) -> Self {
+        self.config_file = Some(config_file.into());
+        self
+    }
+
+    /// Replace the paths probed in order after the user config, the system-wide paths by default
+    pub fn search_paths<P: Into<String>>(
+        mut self,
+        search_paths: impl IntoIterator<Item = P>,
+    ) -> Self {
+        self.search_paths = search_paths.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Whether `SCX_LOADER_CONFIG` is honored, enabled by default
+    pub fn env_override(mut self, enabled: bool) -> Self {
+        self.env_config = if enabled { get_config_override() } else { None };
+        self
+    }
+
+    /// Whether the user config is probed before the search paths, enabled by default
+    pub fn xdg_lookup(mut self, enabled: bool) -> Self {
+        self.user_config = if enabled {
+            get_user_config_path()
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Directory of the drop-ins layered on top of the found config, None to skip drop-ins
+    pub fn dropin_dir(mut self, dropin_dir: Option<impl Into<PathBuf>>) -> Self {
+        self.dropin_dir = dropin_dir.map(Into::into);
+        self
+    }
+
+    /// Get the paths probed for the config in order
+    pub fn candidate_paths(&self) -> Vec<String> {
+        let mut candidate_paths: Vec<String> = self.user_config.iter().cloned().collect();
+        candidate_paths.extend(self.search_paths.iter().cloned());
+        candidate_paths
+    }
+
+    /// Get the path of the config file which would be loaded, None if the default config is used
+    pub fn resolve_path(&self) -> Option<String> {
+        self.config_file
+            .clone()
+            .or_else(|| self.env_config.clone())
+            .or_else(|| find_config_path(&self.candidate_paths()).ok())
+    }
+
+    /// Load the config from the resolved path with its drop-ins, otherwise the default config
+    pub fn load(&self) -> Result<Config> {
+        self.load_with_sources().map(|(config, _)| config)
+    }
+
+    /// Load the config like `load`, also returning the sources it was built from in the order
+    /// they were applied
+    pub fn load_with_sources(&self) -> Result<(Config, Vec<ConfigSource>)> {
+        // explicitly requested config must be loaded, never fallback to default config
+        if let Some(config_path) = &self.config_file {
+            let config = parse_config_file(config_path)
+                .with_context(|| format!("Failed to load config from {config_path}"))?;
+            return Ok((config, vec![ConfigSource::File(config_path.clone())]));
+        }
+        if let Some(config_path) = &self.env_config {
+            let config = parse_config_file(config_path).with_context(|| {
+                format!("Failed to load config from {CONFIG_ENV_VAR}={config_path}")
+            })?;
+            return Ok((config, vec![ConfigSource::EnvOverride(config_path.clone())]));
+        }
+
+        let config_path = find_config_path(&self.candidate_paths()).ok();
+        let (config, mut sources) = match &config_path {
+            Some(config_path) => (
+                parse_config_file(config_path)?,
+                vec![ConfigSource::File(config_path.clone())],
+            ),
+            None => (get_default_config(), vec![ConfigSource::BuiltIn]),
+        };
+        let config = match &self.dropin_dir {
+            Some(dropin_dir) => {
+                let (config, dropin_paths) = merge_config_dropins(config, dropin_dir)?;
+                sources.extend(dropin_paths.into_iter().map(ConfigSource::DropIn));
+                config
+            }
+            None => config,
+        };
+        log_fields::log_with_fields(
+            log::Level::Info,
+            &LogFields::event("config_load")
+                .with("path", config_path.as_deref().unwrap_or("default"))
+                .with("scheds", config.scheds.len()),
+            format_args!("loaded config"),
+        );
+        Ok((config, sources))
+    }
+
+    /// Load the config by layering every existing source on top of the previous ones with
+    /// `merge_configs`: the default config, the first found search path, the drop-ins, the user
+    /// config and finally the explicit config file or `SCX_LOADER_CONFIG`. Also returns the
+    /// sources in the order they were applied
+    pub fn load_layered(&self) -> Result<(Config, Vec<ConfigSource>)> {
+        let mut config = get_default_config();
+        let mut sources = vec![ConfigSource::BuiltIn];
+
+        if let Ok(system_path) = find_config_path(&self.search_paths) {
+            config = merge_configs(config, read_config_layer(&system_path)?);
+            sources.push(ConfigSource::File(system_path));
+        }
+        if let Some(dropin_dir) = &self.dropin_dir {
+            let (merged, dropin_paths) = merge_config_dropins(config, dropin_dir)?;
+            config = merged;
+            sources.extend(dropin_paths.into_iter().map(ConfigSource::DropIn));
+        }
+        if let Some(user_path) = self
+            .user_config
+            .as_ref()
+            .filter(|path| Path::new(path).is_file())
+        {
+            config = merge_configs(config, read_config_layer(user_path)?);
+            sources.push(ConfigSource::File(user_path.clone()));
+        }
+        // explicitly requested configs must exist
+        if let Some(config_path) = &self.config_file {
+            config = merge_configs(config, read_config_layer(config_path)?);
+            sources.push(ConfigSource::File(config_path.clone()));
+        } else if let Some(config_path) = &self.env_config {
+            let overlay = read_config_layer(config_path)
+                .with_context(|| format!("Failed to load {CONFIG_ENV_VAR}={config_path}"))?;
+            config = merge_configs(config, overlay);
+            sources.push(ConfigSource::EnvOverride(config_path.clone()));
+        }
+        // layers may refer to custom schedulers declared in another one
+        validate_sched_names(&config)?;
+        Ok((config, sources))
+    }
+}
+
+/// Read a config file layered by `load_layered`, its scheduler names are validated once merged
+fn read_config_layer(config_path: &str) -> Result<Config> {
+    let file_content = read_config_file(config_path)?;
+    let config = deserialize_config_content(&file_content, ConfigFormat::from_path(config_path))
+        .with_context(|| format!("Failed to load config from {config_path}"))?;
+    Ok(config)
+}
+
+/// Where a loaded config came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigSource {
+    /// The embedded default config, used when no config file is found and as the base layer
+    /// of `load_layered`
+    BuiltIn,
+    /// Config file given explicitly or found in the user config or the search paths
+    File(String),
+    /// Config file set in `SCX_LOADER_CONFIG`
+    EnvOverride(String),
+    /// Drop-in merged on top of the config
+    DropIn(PathBuf),
+}
+
+impl Config {
+    /// Load the config from all sources, the default config overlaid by the system-wide config,
+    /// the drop-ins, the user config and `SCX_LOADER_CONFIG`, see `ConfigLoader::load_layered`.
+    /// The sources are returned in the order they were applied, e.g. to log where the config
+    /// came from
+    pub fn from_env() -> Result<(Config, Vec<ConfigSource>)> {
+        ConfigLoader::new().load_layered()
+    }
+}
+
+/// Merge the overlay config on top of the base config.
+///
+/// Scalar fields are replaced when set in the overlay, scheds are merged per scheduler and per mode.
+pub fn merge_configs(base: Config, overlay: Config) -> Config {
+    let mut merged = base;
+    merged.default_sched = overlay.default_sched.or(merged.default_sched);
+    if !overlay.fallback_scheds.is_empty() {
+        merged.fallback_scheds = overlay.fallback_scheds;
+    }
+    merged.default_mode = overlay.default_mode.or(merged.default_mode);
+    merged.global_flags = flags::merge_flags(&merged.global_flags, &overlay.global_flags);
+    if !overlay.bin_dirs.is_empty() {
+        merged.bin_dirs = overlay.bin_dirs;
+    }
+    merged.auto_restart = overlay.auto_restart.or(merged.auto_restart);
+    merged.restart_limit = overlay.restart_limit.or(merged.restart_limit);
+    merged.power = overlay.power.or(merged.power);
+    merged.process_modes.extend(overlay.process_modes);
+    merged.aliases.extend(overlay.aliases);
+    if !overlay.schedule.is_empty() {
+        merged.schedule = overlay.schedule;
+    }
+    merged.health_check_interval = overlay
+        .health_check_interval
+        .or(merged.health_check_interval);
+    merged.startup_timeout = overlay.startup_timeout.or(merged.startup_timeout);
+    merged.process_priority = overlay.process_priority.or(merged.process_priority);
+    merged.oom_score_adj = overlay.oom_score_adj.or(merged.oom_score_adj);
+    merged.log_output = overlay.log_output.or(merged.log_output);
+    merged.log_output_max_size = overlay.log_output_max_size.or(merged.log_output_max_size);
+    merged.cgroup = overlay.cgroup.or(merged.cgroup);
+    merged.cgroup_required = overlay.cgroup_required.or(merged.cgroup_required);
+    merged.watch_config = overlay.watch_config.or(merged.watch_config);
+    merged.history_size = overlay.history_size.or(merged.history_size);
+    merged.log_history = overlay.log_history.or(merged.log_history);
+    merged.strict_flags = overlay.strict_flags.or(merged.strict_flags);
+    merged.switch_debounce_ms = overlay.switch_debounce_ms.or(merged.switch_debounce_ms);
+    merged.switch_handoff = overlay.switch_handoff.or(merged.switch_handoff);
+    merged.sysfs_path = overlay.sysfs_path.or(merged.sysfs_path);
+    merged.stop_behavior = overlay.stop_behavior.or(merged.stop_behavior);
+    if let Some(overlay_scheds) = overlay.mode_scheds {
+        let merged_scheds = merged.mode_scheds.get_or_insert_with(ModeScheds::default);
+        merged_scheds.auto_mode = overlay_scheds.auto_mode.or(merged_scheds.auto_mode.take());
+        merged_scheds.gaming_mode = overlay_scheds
+            .gaming_mode
+            .or(merged_scheds.gaming_mode.take());
+        merged_scheds.lowlatency_mode = overlay_scheds
+            .lowlatency_mode
+            .or(merged_scheds.lowlatency_mode.take());
+        merged_scheds.powersave_mode = overlay_scheds
+            .powersave_mode
+            .or(merged_scheds.powersave_mode.take());
+        merged_scheds.server_mode = overlay_scheds
+            .server_mode
+            .or(merged_scheds.server_mode.take());
+    }
+    merged.hooks = overlay.hooks.or(merged.hooks);
+
+    for (sched_name, overlay_sched) in overlay.scheds {
+        match merged.scheds.entry(sched_name) {
+            Entry::Occupied(mut entry) => merge_sched(entry.get_mut(), overlay_sched),
+            Entry::Vacant(entry) => {
+                entry.insert(overlay_sched);
+            }
+        }
+    }
+    merged
+}
+
+fn merge_sched(base: &mut Sched, overlay: Sched) {
+    base.path = overlay.path.or(base.path.take());
+    base.enabled = overlay.enabled.or(base.enabled);
+    base.default_mode = overlay.default_mode.or(base.default_mode.take());
+    base.auto_mode = overlay.auto_mode.or(base.auto_mode.take());
+    base.gaming_mode = overlay.gaming_mode.or(base.gaming_mode.take());
+    base.lowlatency_mode = overlay.lowlatency_mode.or(base.lowlatency_mode.take());
+    base.powersave_mode = overlay.powersave_mode.or(base.powersave_mode.take());
+    base.server_mode = overlay.server_mode.or(base.server_mode.take());
+    base.hooks = overlay.hooks.or(base.hooks.take());
+    base.min_kernel = overlay.min_kernel.or(base.min_kernel.take());
+    base.oom_score_adj = overlay.oom_score_adj.or(base.oom_score_adj);
+    base.restart_on_change = overlay.restart_on_change.or(base.restart_on_change);
+    base.log_output = overlay.log_output.or(base.log_output.take());
+    base.env = overlay.env.or(base.env.take());
+    base.min_cpus = overlay.min_cpus.or(base.min_cpus);
+    base.require_hybrid = overlay.require_hybrid.or(base.require_hybrid);
+    base.cgroup = overlay.cgroup.or(base.cgroup.take());
+    base.cgroup_required = overlay.cgroup_required.or(base.cgroup_required);
+    if let Some(overlay_files) = overlay.flags_file {
+        let base_files = base.flags_file.get_or_insert_with(ModeFlagsFiles::default);
+        base_files.auto_mode = overlay_files.auto_mode.or(base_files.auto_mode.take());
+        base_files.gaming_mode = overlay_files.gaming_mode.or(base_files.gaming_mode.take());
+        base_files.lowlatency_mode = overlay_files
+            .lowlatency_mode
+            .or(base_files.lowlatency_mode.take());
+        base_files.powersave_mode = overlay_files
+            .powersave_mode
+            .or(base_files.powersave_mode.take());
+        base_files.server_mode = overlay_files.server_mode.or(base_files.server_mode.take());
+    }
+    if let Some(overlay_extra) = overlay.extra_flags {
+        let base_extra = base.extra_flags.get_or_insert_with(ExtraFlags::default);
+        base_extra.auto_mode = overlay_extra.auto_mode.or(base_extra.auto_mode.take());
+        base_extra.gaming_mode = overlay_extra.gaming_mode.or(base_extra.gaming_mode.take());
+        base_extra.lowlatency_mode = overlay_extra
+            .lowlatency_mode
+            .or(base_extra.lowlatency_mode.take());
+        base_extra.powersave_mode = overlay_extra
+            .powersave_mode
+            .or(base_extra.powersave_mode.take());
+        base_extra.server_mode = overlay_extra.server_mode.or(base_extra.server_mode.take());
+    }
+    if let Some(overlay_descs) = overlay.descriptions {
+        let base_descs = base
+            .descriptions
+            .get_or_insert_with(ModeDescriptions::default);
+        base_descs.auto_mode = overlay_descs.auto_mode.or(base_descs.auto_mode.take());
+        base_descs.gaming_mode = overlay_descs.gaming_mode.or(base_descs.gaming_mode.take());
+        base_descs.lowlatency_mode = overlay_descs
+            .lowlatency_mode
+            .or(base_descs.lowlatency_mode.take());
+        base_descs.powersave_mode = overlay_descs
+            .powersave_mode
+            .or(base_descs.powersave_mode.take());
+        base_descs.server_mode = overlay_descs.server_mode.or(base_descs.server_mode.take());
+    }
+    if let Some(overlay_affinity) = overlay.cpu_affinity {
+        let base_affinity = base
+            .cpu_affinity
+            .get_or_insert_with(ModeCpuAffinity::default);
+        base_affinity.auto_mode = overlay_affinity
+            .auto_mode
+            .or(base_affinity.auto_mode.take());
+        base_affinity.gaming_mode = overlay_affinity
+            .gaming_mode
+            .or(base_affinity.gaming_mode.take());
+        base_affinity.lowlatency_mode = overlay_affinity
+            .lowlatency_mode
+            .or(base_affinity.lowlatency_mode.take());
+        base_affinity.powersave_mode = overlay_affinity
+            .powersave_mode
+            .or(base_affinity.powersave_mode.take());
+        base_affinity.server_mode = overlay_affinity
+            .server_mode
+            .or(base_affinity.server_mode.take());
+    }
+    if let Some(overlay_profiles) = overlay.profiles {
+        base.profiles
+            .get_or_insert_with(HashMap::new)
+            .extend(overlay_profiles);
+    }
+}
+
+/// Fold the drop-in configs from the given directory on top of the base config, also returning
+/// the paths of the merged drop-ins
+fn merge_config_dropins(base: Config, dropin_dir: &Path) -> Result<(Config, Vec<PathBuf>)> {
+    let mut config = base;
+    let mut merged_paths = vec![];
+    for dropin_path in get_config_dropin_paths(dropin_dir)? {
+        // locked like the config file, so a drop-in being written isn't read half done
+        let file_content = read_config_file(&dropin_path.to_string_lossy())?;
+        // empty drop-ins are allowed, e.g. to mask a drop-in shipped by the distribution
+        if file_content.trim().is_empty() {
+            continue;
+        }
+        // drop-ins may refer to custom schedulers declared elsewhere, validate the merged config
+        let overlay: Config = toml::from_str(&file_content)
+            .with_context(|| format!("Failed to parse config file {}", dropin_path.display()))?;
+        config = merge_configs(config, overlay);
+        merged_paths.push(dropin_path);
+    }
+    validate_sched_names(&config)?;
+    Ok((config, merged_paths))
+}
+
+/// Get the `*.toml` files of the drop-in directory sorted in lexical order
+fn get_config_dropin_paths(dropin_dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dropin_dir.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let mut dropin_paths = vec![];
+    for entry in fs::read_dir(dropin_dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "toml") {
+            dropin_paths.push(path);
+        }
+    }
+    dropin_paths.sort();
+    Ok(dropin_paths)
+}
+
+/// Read and parse the config file, holding a shared lock while reading so a concurrent
+/// write_config isn't read half done
+pub fn parse_config_file(filepath: &str) -> Result<Config, ConfigError> {
+    let file_content = read_config_file(filepath)?;
+    parse_config_content(&file_content, ConfigFormat::from_path(filepath))
+}
+
+fn read_config_file(filepath: &str) -> Result<String, ConfigError> {
+    let _lock = lock::lock_config(filepath, LockKind::Shared, lock::DEFAULT_LOCK_TIMEOUT).map_err(
+        |source| ConfigError::Read {
+            path: filepath.to_owned(),
+            source,
+        },
+    )?;
+    fs::read_to_string(filepath).map_err(|source| ConfigError::read(filepath, source))
+}
+
+/// Parse and validate the config file, failing to parse it is reported as an error and unknown
+/// fields as warnings
+pub fn validate_config_file(filepath: &str) -> ValidationReport {
+    let format = ConfigFormat::from_path(filepath);
+    let parsed = read_config_file(filepath).and_then(|file_content| {
+        let config = parse_config_content(&file_content, format)?;
+        let unknown_fields = find_unknown_fields(&file_content, format, &config)?;
+        Ok((config, unknown_fields))
+    });
+    match parsed {
+        Ok((config, unknown_fields)) => {
+            let mut report = config.validation_report();
+            report.warnings.extend(
+                unknown_fields
+                    .iter()
+                    .map(|field| format!("unknown field {field} is ignored")),
+            );
+            report
+        }
+        Err(parse_err) => ValidationReport {
+            errors: vec![format!("{parse_err:#}")],
+            ..Default::default()
+        },
+    }
+}
+
+/// Serialize the config into TOML
+pub fn serialize_config(config: &Config) -> Result<String> {
+    serialize_config_as(config, ConfigFormat::Toml)
+}
+
+/// Serialize the config into the given format
+pub fn serialize_config_as(config: &Config, format: ConfigFormat) -> Result<String> {
+    match format {
+        ConfigFormat::Toml => toml::to_string_pretty(config).context("Failed to serialize config"),
+        ConfigFormat::Json => {
+            serde_json::to_string_pretty(config).context("Failed to serialize config")
+        }
+    }
+}
+
+/// Write the config to the given path.
+///
+/// The config is written to a temporary file next to the target first and then renamed over it,
+/// so the target never contains a partially written config. An exclusive lock is held meanwhile,
+/// see `parse_config_file`.
+pub fn write_config(config: &Config, filepath: &str) -> Result<()> {
+    let file_content = serialize_config_as(config, ConfigFormat::from_path(filepath))?;
+    let tmp_filepath = format!("{filepath}.tmp");
+    let _lock = lock::lock_config(filepath, LockKind::Exclusive, lock::DEFAULT_LOCK_TIMEOUT)?;
+
+    let write_tmp_file = || -> std::io::Result<()> {
+        let mut tmp_file = fs::File::create(&tmp_filepath)?;
+        tmp_file.write_all(file_content.as_bytes())?;
+        tmp_file.sync_all()
+    };
+    if let Err(write_err) = write_tmp_file() {
+        let _ = fs::remove_file(&tmp_filepath);
+        return Err(write_err)
+            .with_context(|| format!("Failed to write config file {tmp_filepath}"));
+    }
+
+    fs::rename(&tmp_filepath, filepath)
+        .with_context(|| format!("Failed to replace config file {filepath}"))
+}
+
+/// Get the first existing config path, see `get_config_search_paths` for the search order
+pub fn get_config_path() -> Result<String> {
+    find_config_path(&get_config_search_paths())
+}
+
+/// Get the config paths in the order they are probed by `get_config_path`:
+///
+/// 1. `$XDG_CONFIG_HOME/scx_loader/config.toml`, or `~/.config/scx_loader/config.toml`
+///    when `XDG_CONFIG_HOME` is unset
+/// 2. `/etc/scx_loader/config.toml`
+/// 3. `/etc/scx_loader.toml`
+///
+/// The user config takes precedence over the system-wide locations.
+pub fn get_config_search_paths() -> Vec<String> {
+    ConfigLoader::new().candidate_paths()
+}
+
+#[cfg(test)]
+fn config_search_paths(xdg_config_home: Option<&str>, home: Option<&str>) -> Vec<String> {
+    let mut check_paths: Vec<String> = user_config_path(xdg_config_home, home)
+        .into_iter()
+        .collect();
+    check_paths.extend(SYSTEM_CONFIG_PATHS.iter().map(|path| path.to_string()));
+    check_paths
+}
+
+/// Get the path of the user config from the environment
+fn get_user_config_path() -> Option<String> {
+    user_config_path(
+        env::var("XDG_CONFIG_HOME").ok().as_deref(),
+        env::var("HOME").ok().as_deref(),
+    )
+}
+
+/// Get the path of the user config, None if neither XDG_CONFIG_HOME nor HOME is usable
+fn user_config_path(xdg_config_home: Option<&str>, home: Option<&str>) -> Option<String> {
+    // per XDG spec, empty or relative values must be ignored
+    let xdg_config_home = xdg_config_home.filter(|path| Path::new(path).is_absolute());
+    if let Some(xdg_config_home) = xdg_config_home {
+        Some(format!("{xdg_config_home}/scx_loader/config.toml"))
+    } else {
+        home.filter(|path| Path::new(path).is_absolute())
+            .map(|home| format!("{home}/.config/scx_loader/config.toml"))
+    }
+}
+
+fn find_config_path(check_paths: &[String]) -> Result<String> {
+    // paths with missing parent directories simply don't exist, so they are skipped as well
+    for check_path in check_paths {
+        if !Path::new(check_path).is_file() {
+            continue;
+        }
+        // we found config path
+        return Ok(check_path.clone());
+    }
+
+    anyhow::bail!("Failed to find config!");
+}
+
+fn parse_config_content(file_content: &str, format: ConfigFormat) -> Result<Config, ConfigError> {
+    let config = deserialize_config_content(file_content, format)?;
+    validate_sched_names(&config)?;
+    Ok(config)
+}
+
+/// Deserialize the config without validating the scheduler names
+fn deserialize_config_content(
+    file_content: &str,
+    format: ConfigFormat,
+) -> Result<Config, ConfigError> {
+    if file_content.is_empty() {
+        return Err(ConfigError::EmptyFile);
+    }
+    match format {
+        ConfigFormat::Toml => toml::from_str(file_content).map_err(ConfigError::parse),
+        ConfigFormat::Json => serde_json::from_str(file_content).map_err(ConfigError::parse),
+    }
+}
+
+/// Parse the config like parse_config_content, but fail on unknown fields instead of ignoring
+/// them, e.g. misspelled modes
+pub fn parse_config_content_strict(
+    file_content: &str,
+    format: ConfigFormat,
+) -> Result<Config, ConfigError> {
+    let config = parse_config_content(file_content, format)?;
+    let unknown_fields = find_unknown_fields(file_content, format, &config)?;
+    if !unknown_fields.is_empty() {
+        return Err(ConfigError::UnknownFields(unknown_fields));
+    }
+    Ok(config)
+}
+
+/// Parse the TOML config of a test, without validating it
+#[cfg(test)]
+pub(crate) fn parse_config(config_str: &str) -> Config {
+    toml::from_str(config_str).expect("Failed to parse config")
+}
+
+/// Get the dotted paths of the fields in the config file which the parsed config doesn't know
+fn find_unknown_fields(
+    file_content: &str,
+    format: ConfigFormat,
+    config: &Config,
+) -> Result<Vec<String>, ConfigError> {
+    let raw_value = match format {
+        ConfigFormat::Toml => {
+            let toml_value: toml::Value =
+                toml::from_str(file_content).map_err(ConfigError::parse)?;
+            serde_json::to_value(toml_value).map_err(ConfigError::parse)?
+        }
+        ConfigFormat::Json => serde_json::from_str(file_content).map_err(ConfigError::parse)?,
+    };
+    let known_value = serde_json::to_value(config).map_err(ConfigError::parse)?;
+
+    let mut unknown_fields = vec![];
+    collect_unknown_fields(&raw_value, &known_value, "", &mut unknown_fields);
+    Ok(unknown_fields)
+}
+
+fn collect_unknown_fields(
+    raw_value: &serde_json::Value,
+    known_value: &serde_json::Value,
+    path: &str,
+    unknown_fields: &mut Vec<String>,
+) {
+    use serde_json::Value;
+
+    match (raw_value, known_value) {
+        (Value::Object(raw_fields), Value::Object(known_fields)) => {
+            for (field_name, raw_field) in raw_fields {
+                let field_path = match path {
+                    "" => field_name.clone(),
+                    _ => format!("{path}.{field_name}"),
+                };
+                match known_fields.get(field_name) {
+                    Some(known_field) => {
+                        collect_unknown_fields(raw_field, known_field, &field_path, unknown_fields)
+                    }
+                    // empty lists and tables are skipped when serializing
+                    None if is_empty_value(raw_field) => {}
+                    None => unknown_fields.push(field_path),
+                }
+            }
+        }
+        (Value::Array(raw_items), Value::Array(known_items)) => {
+            for (idx, (raw_item, known_item)) in raw_items.iter().zip(known_items).enumerate() {
+                collect_unknown_fields(
+                    raw_item,
+                    known_item,
+                    &format!("{path}[{idx}]"),
+                    unknown_fields,
+                );
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_empty_value(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Null => true,
+        serde_json::Value::Array(items) => items.is_empty(),
+        serde_json::Value::Object(fields) => fields.is_empty(),
+        _ => false,
+    }
+}
+
+/// Check that every [scheds] entry refers to a scheduler supported by the loader or declares a
+/// custom scheduler
+fn validate_sched_names(config: &Config) -> Result<(), ConfigError> {
+    let mut unknown_scheds = vec![];
+    for sched_name in config.scheduler_names() {
+        let is_custom = config.scheds[&sched_name].path.is_some();
+        let supported_sched = parse_scx_from_name(&sched_name);
+        let is_supported = supported_sched.is_some();
+        if let Some(scx_sched) = supported_sched.filter(|_| !is_custom) {
+            // entries are looked up by the full name, so the bare form would be ignored
+            let scx_name = get_name_from_scx(&scx_sched);
+            if scx_name != sched_name {
+                return Err(ConfigError::MisnamedScheduler {
+                    sched_name,
+                    scx_name: scx_name.to_owned(),
+                });
+            }
+        }
+        if is_custom && is_supported {
+            return Err(ConfigError::CustomSchedulerCollision(sched_name));
+        }
+        if !is_custom && !is_supported {
+            unknown_scheds.push(sched_name);
+        }
+    }
+
+    if !unknown_scheds.is_empty() {
+        return Err(ConfigError::UnknownScheduler(unknown_scheds));
+    }
+
+    if let Some(SupportedSched::Custom(sched_name)) = &config.default_sched {
+        if !is_custom_sched(config, sched_name) {
+            return Err(ConfigError::UnknownSchedulerReference {
+                field: "default_sched",
+                sched_name: sched_name.clone(),
+            });
+        }
+    }
+    for scx_sched in &config.fallback_scheds {
+        if let SupportedSched::Custom(sched_name) = scx_sched {
+            if !is_custom_sched(config, sched_name) {
+                return Err(ConfigError::UnknownSchedulerReference {
+                    field: "fallback_scheds",
+                    sched_name: sched_name.clone(),
+                });
+            }
+        }
+    }
+    if let Some(mode_scheds) = &config.mode_scheds {
+        let overrides = [
+            &mode_scheds.auto_mode,
+            &mode_scheds.gaming_mode,
+            &mode_scheds.lowlatency_mode,
+            &mode_scheds.powersave_mode,
+            &mode_scheds.server_mode,
+        ];
+        for sched in overrides.into_iter().flatten() {
+            if let SupportedSched::Custom(sched_name) = sched {
+                if !is_custom_sched(config, sched_name) {
+                    return Err(ConfigError::UnknownSchedulerReference {
+                        field: "mode_scheds",
+                        sched_name: sched_name.clone(),
+                    });
+                }
+            }
+        }
+    }
+    for entry in &config.schedule {
+        if let SupportedSched::Custom(sched_name) = &entry.sched {
+            if !is_custom_sched(config, sched_name) {
+                return Err(ConfigError::UnknownSchedulerReference {
+                    field: "schedule sched",
+                    sched_name: sched_name.clone(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check whether the given name refers to a custom scheduler declared in the config
+pub fn is_custom_sched(config: &Config, sched_name: &str) -> bool {
+    config
+        .scheds
+        .get(sched_name)
+        .is_some_and(|sched_config| sched_config.path.is_some())
+}
+
+/// Get the scheduler with the given name, either a supported or a custom scheduler from the
+/// config. Returns None for unknown names
+pub fn resolve_sched_name(config: &Config, sched_name: &str) -> Option<SupportedSched> {
+    parse_scx_from_name(sched_name).or_else(|| {
+        is_custom_sched(config, sched_name).then(|| SupportedSched::Custom(sched_name.to_owned()))
+    })
+}
+
+/// Get the binary to execute for the given scheduler
+pub fn get_scx_bin_path(config: &Config, scx_sched: &SupportedSched) -> String {
+    let scx_name = get_name_from_scx(scx_sched);
+    config
+        .scheds
+        .get(scx_name)
+        .and_then(|sched_config| sched_config.path.clone())
+        // supported schedulers are looked up in the bin_dirs, then in PATH
+        .unwrap_or_else(|| {
+            let bin_dirs: Vec<PathBuf> = config.bin_dirs.iter().map(PathBuf::from).collect();
+            match sched_bin::find_executable(scx_name, &bin_dirs) {
+                Some(bin_path) => bin_path.to_string_lossy().into_owned(),
+                None => scx_name.to_owned(),
+            }
+        })
+}
+
+/// Get the default config, parsed from the embedded `configs/config.toml`
+pub fn get_default_config() -> Config {
+    parse_config_content(DEFAULT_CONFIG, ConfigFormat::Toml)
+        .expect("Failed to parse the embedded default config")
+}
+
+/// Get the default config as TOML with every supported scheduler and mode filled in and
+/// comments explaining the options, as a starting point for writing a config
+pub fn export_default_config_toml() -> String {
+    let default_config = get_default_config();
+    let mut global_table =
+        toml::Table::try_from(&default_config).expect("Failed to serialize config");
+    global_table.remove("scheds");
+
+    let mut output = String::from(
+        "# scx_loader configuration, see README.md for all available options.\n\
+         #\n\
+         # default_sched is started when scx_loader is launched, in default_mode if set.\n\
+         # Modes are \"Auto\", \"Gaming\", \"LowLatency\", \"PowerSave\" and \"Server\".\n\n",
+    );
+    output.push_str(&global_table.to_string());
+
+    for scx_sched in SUPPORTED_SCHEDS {
+        let scx_name = get_name_from_scx(scx_sched);
+        let mut sched_config = default_config
+            .scheds
+            .get(scx_name)
+            .cloned()
+            .unwrap_or_default();
+        fill_default_flags(scx_sched, &mut sched_config);
+
+        // nest the scheduler under [scheds] so its sub tables get the right header
+        let sched_table = BTreeMap::from([("scheds", BTreeMap::from([(scx_name, sched_config)]))]);
+        output.push_str(&format!(
+            "\n# Flags passed to {scx_name} in each mode\n{}",
+            toml::to_string(&sched_table).expect("Failed to serialize config")
+        ));
+    }
+    output
+}
+
+/// Check whether the scheduler is offered without setting `enabled = true`. scx_chaos is opt-in
+/// as it's meant for testing and not for production
+pub fn enabled_by_default(scx_sched: &SupportedSched) -> bool {
+    *scx_sched != SupportedSched::Chaos
+}
+
+/// Get the scx flags for the given sched mode
+pub fn get_scx_flags_for_mode(
+    config: &Config,
+    scx_sched: &SupportedSched,
+    sched_mode: SchedMode,
+) -> Vec<String> {
+    let scx_name = get_name_from_scx(scx_sched);
+    let sched_config = config.scheds.get(scx_name);
+    let mut scx_flags = sched_config
+        .and_then(|sched_config| extract_scx_flags_from_config(sched_config, &sched_mode))
+        // try to exact flags from config, otherwise fallback to hardcoded default
+        .unwrap_or_else(|| default_flags(scx_sched, sched_mode.clone()));
+
+    if let Some(extra_flags) = sched_config
+        .and_then(|sched_config| sched_config.extra_flags.as_ref())
+        .and_then(|extra_flags| extract_extra_flags(extra_flags, &sched_mode))
+    {
+        scx_flags = flags::merge_flags(&scx_flags, extra_flags);
+    }
+    scx_flags = flags::merge_flags(&scx_flags, &config.global_flags);
+    convert_scx_flags(flags::expand_flags(&scx_flags))
+}
+
+/// Get the flags of every built-in scheduler in every mode, ordered like SUPPORTED_SCHEDS and
+/// SCHED_MODES
+pub fn enumerate_all_flags(config: &Config) -> Vec<(SupportedSched, SchedMode, Vec<String>)> {
+    SUPPORTED_SCHEDS
+        .iter()
+        .flat_map(|scx_sched| {
+            SCHED_MODES.iter().map(move |sched_mode| {
+                (
+                    scx_sched.clone(),
+                    sched_mode.clone(),
+                    get_scx_flags_for_mode(config, scx_sched, sched_mode.clone()),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Check that a scheduler which is configured by its flags got any, scx_layered refuses to start
+/// without the layer spec otherwise, which is easy to miss
+pub fn check_required_flags(scx_sched: &SupportedSched, scx_flags: &[String]) -> Result<()> {
+    if *scx_sched == SupportedSched::Layered && scx_flags.is_empty() {
+        anyhow::bail!(
+            "scx_layered needs a layer spec, set the flags of the mode in [scheds.scx_layered], \
+             e.g. [\"f:/etc/scx_layered.json\"]"
+        );
+    }
+    Ok(())
+}
+
+/// Get the built-in flags of the given sched mode, used when the config doesn't set any
+pub fn default_flags(scx_sched: &SupportedSched, sched_mode: SchedMode) -> Vec<String> {
+    get_default_scx_flags_for_mode(scx_sched, sched_mode)
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+/// Special modes the scheduler has built-in flags for, the others run it like Auto
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct SchedCapabilities {
+    pub gaming: bool,
+    pub lowlatency: bool,
+    pub powersave: bool,
+}
+
+/// Describe which special modes the scheduler meaningfully supports, e.g. so a settings UI can
+/// hide the irrelevant ones. Custom schedulers have no built-in flags
+pub fn describe(scx_sched: &SupportedSched) -> SchedCapabilities {
+    let has_flags = |sched_mode| !get_default_scx_flags_for_mode(scx_sched, sched_mode).is_empty();
+    SchedCapabilities {
+        gaming: has_flags(SchedMode::Gaming),
+        lowlatency: has_flags(SchedMode::LowLatency),
+        powersave: has_flags(SchedMode::PowerSave),
+    }
+}
+
+/// Set the built-in flags of the modes the scheduler config doesn't set
+pub fn fill_default_flags(scx_sched: &SupportedSched, sched_config: &mut Sched) {
+    for sched_mode in SCHED_MODES {
+        let mode_flags = match sched_mode {
+            SchedMode::Gaming => &mut sched_config.gaming_mode,
+            SchedMode::LowLatency => &mut sched_config.lowlatency_mode,
+            SchedMode::PowerSave => &mut sched_config.powersave_mode,
+            SchedMode::Server => &mut sched_config.server_mode,
+            SchedMode::Auto => &mut sched_config.auto_mode,
+            SchedMode::Custom(_) => continue,
+        };
+        mode_flags.get_or_insert_with(|| default_flags(scx_sched, sched_mode.clone()));
+    }
+}
+
+/// Unset the modes whose flags are the built-in ones, the inverse of `fill_default_flags`. Modes
+/// with a flags file are kept, as the file is appended to the inline flags only
+pub fn strip_default_flags(scx_sched: &SupportedSched, sched_config: &mut Sched) {
+    for sched_mode in SCHED_MODES {
+        if extract_flags_file(sched_config, sched_mode).is_some() {
+            continue;
+        }
+        let mode_flags = match sched_mode {
+            SchedMode::Gaming => &mut sched_config.gaming_mode,
+            SchedMode::LowLatency => &mut sched_config.lowlatency_mode,
+            SchedMode::PowerSave => &mut sched_config.powersave_mode,
+            SchedMode::Server => &mut sched_config.server_mode,
+            SchedMode::Auto => &mut sched_config.auto_mode,
+            SchedMode::Custom(_) => continue,
+        };
+        if mode_flags.as_ref() == Some(&default_flags(scx_sched, sched_mode.clone())) {
+            *mode_flags = None;
+        }
+    }
+}
+
+/// Get the full command line the given scheduler would be started with in the given mode, i.e.
+/// the binary followed by the resolved flags
+pub fn build_command_line(
+    config: &Config,
+    scx_sched: &SupportedSched,
+    sched_mode: SchedMode,
+) -> Vec<String> {
+    let mut argv = vec![get_scx_bin_path(config, scx_sched)];
+    argv.extend(get_scx_flags_for_mode(config, scx_sched, sched_mode));
+    argv
+}
+
+/// Get the default mode for the given scheduler, preferring the per-scheduler default_mode over
+/// the global default_mode, otherwise falls back to Auto
+pub fn resolve_default_mode(config: &Config, scx_sched: &SupportedSched) -> SchedMode {
+    config
+        .scheds
+        .get(get_name_from_scx(scx_sched))
+        .and_then(|sched_config| sched_config.default_mode.clone())
+        .or_else(|| config.default_mode.clone())
+        .unwrap_or(SchedMode::Auto)
+}
+
+/// Get the scx flags for the given named profile, or None if the scheduler has no such profile
+pub fn get_scx_flags_for_profile(
+    config: &Config,
+    scx_sched: &SupportedSched,
+    profile_name: &str,
+) -> Option<Vec<String>> {
+    let sched_config = config.scheds.get(get_name_from_scx(scx_sched))?;
+    let scx_flags = sched_config.profiles.as_ref()?.get(profile_name)?;
+    let scx_flags = flags::merge_flags(scx_flags, &config.global_flags);
+    Some(convert_scx_flags(flags::expand_flags(&scx_flags)))
+}
+
+/// Get the scheduler the given sched mode selects, None if the mode keeps the scheduler
+pub fn get_mode_sched<'a>(
+    config: &'a Config,
+    sched_mode: &SchedMode,
+) -> Option<&'a SupportedSched> {
+    let mode_scheds = config.mode_scheds.as_ref()?;
+    let mode_sched = match sched_mode {
+        SchedMode::Gaming => &mode_scheds.gaming_mode,
+        SchedMode::LowLatency => &mode_scheds.lowlatency_mode,
+        SchedMode::PowerSave => &mode_scheds.powersave_mode,
+        SchedMode::Server => &mode_scheds.server_mode,
+        SchedMode::Auto => &mode_scheds.auto_mode,
+        SchedMode::Custom(_) => return None,
+    };
+    mode_sched.as_ref()
+}
+
+/// Get the configured description of the given sched mode
+pub fn get_mode_description<'a>(
+    config: &'a Config,
+    scx_sched: &SupportedSched,
+    sched_mode: &SchedMode,
+) -> Option<&'a str> {
+    let sched_config = config.scheds.get(get_name_from_scx(scx_sched))?;
+    let descriptions = sched_config.descriptions.as_ref()?;
+    let description = match sched_mode {
+        SchedMode::Gaming => &descriptions.gaming_mode,
+        SchedMode::LowLatency => &descriptions.lowlatency_mode,
+        SchedMode::PowerSave => &descriptions.powersave_mode,
+        SchedMode::Server => &descriptions.server_mode,
+        SchedMode::Auto => &descriptions.auto_mode,
+        SchedMode::Custom(_) => return None,
+    };
+    description.as_deref()
+}
+
+/// Get the CPUs the scheduler is pinned to in the given mode, None if it isn't pinned
+pub fn get_cpu_affinity(
+    config: &Config,
+    scx_sched: &SupportedSched,
+    sched_mode: &SchedMode,
+) -> Result<Option<Vec<usize>>> {
+    let Some(cpu_affinity) = config
+        .scheds
+        .get(get_name_from_scx(scx_sched))
+        .and_then(|sched_config| sched_config.cpu_affinity.as_ref())
+    else {
+        return Ok(None);
+    };
+    let cpu_list = match sched_mode {
+        SchedMode::Gaming => &cpu_affinity.gaming_mode,
+        SchedMode::LowLatency => &cpu_affinity.lowlatency_mode,
+        SchedMode::PowerSave => &cpu_affinity.powersave_mode,
+        SchedMode::Server => &cpu_affinity.server_mode,
+        SchedMode::Auto => &cpu_affinity.auto_mode,
+        SchedMode::Custom(_) => return Ok(None),
+    };
+    cpu_list
+        .as_deref()
+        .map(affinity::parse_cpu_list)
+        .transpose()
+}
+
+/// Extract the scx flags from config
+fn extract_scx_flags_from_config(
+    sched_config: &Sched,
+    sched_mode: &SchedMode,
+) -> Option<Vec<String>> {
+    let inline_flags = match sched_mode {
+        SchedMode::Gaming => sched_config.gaming_mode.clone(),
+        SchedMode::LowLatency => sched_config.lowlatency_mode.clone(),
+        SchedMode::PowerSave => sched_config.powersave_mode.clone(),
+        SchedMode::Server => sched_config.server_mode.clone(),
+        SchedMode::Auto => sched_config.auto_mode.clone(),
+        // custom modes are the named profiles
+        SchedMode::Custom(profile_name) => {
+            sched_config.profiles.as_ref()?.get(profile_name).cloned()
+        }
+    };
+    let Some(flags_file) = extract_flags_file(sched_config, sched_mode) else {
+        return inline_flags;
+    };
+
+    // a flags file configures the mode even without inline flags
+    let mut scx_flags = inline_flags.unwrap_or_default();
+    match flags::read_flags_file(flags_file) {
+        Ok(file_flags) => scx_flags.extend(file_flags),
+        Err(err) => log::error!("{err:#}"),
+    }
+    Some(scx_flags)
+}
+
+fn extract_flags_file<'a>(sched_config: &'a Sched, sched_mode: &SchedMode) -> Option<&'a str> {
+    let flags_files = sched_config.flags_file.as_ref()?;
+    let flags_file = match sched_mode {
+        SchedMode::Gaming => &flags_files.gaming_mode,
+        SchedMode::LowLatency => &flags_files.lowlatency_mode,
+        SchedMode::PowerSave => &flags_files.powersave_mode,
+        SchedMode::Server => &flags_files.server_mode,
+        SchedMode::Auto => &flags_files.auto_mode,
+        SchedMode::Custom(_) => return None,
+    };
+    flags_file.as_deref()
+}
+
+/// Convert the duration values of the scx flags into the unit of their option, invalid
+/// durations are reported by `Config::validate` and passed through as is
+fn convert_scx_flags(scx_flags: Vec<String>) -> Vec<String> {
+    match flags::convert_durations(&scx_flags) {
+        Ok(converted_flags) => converted_flags,
+        Err(err) => {
+            log::warn!("{err:#}");
+            scx_flags
+        }
+    }
+}
+
+/// Extract the extra flags of the given sched mode
+fn extract_extra_flags<'a>(
+    extra_flags: &'a ExtraFlags,
+    sched_mode: &SchedMode,
+) -> Option<&'a Vec<String>> {
+    match sched_mode {
+        SchedMode::Gaming => extra_flags.gaming_mode.as_ref(),
+        SchedMode::LowLatency => extra_flags.lowlatency_mode.as_ref(),
+        SchedMode::PowerSave => extra_flags.powersave_mode.as_ref(),
+        SchedMode::Server => extra_flags.server_mode.as_ref(),
+        SchedMode::Auto => extra_flags.auto_mode.as_ref(),
+        SchedMode::Custom(_) => None,
+    }
+}
+
+/// Get the config field name of the given sched mode
+fn get_mode_field_name(sched_mode: &SchedMode) -> &'static str {
+    match sched_mode {
+        SchedMode::Gaming => "gaming_mode",
+        SchedMode::LowLatency => "lowlatency_mode",
+        SchedMode::PowerSave => "powersave_mode",
+        SchedMode::Server => "server_mode",
+        SchedMode::Auto => "auto_mode",
+        SchedMode::Custom(_) => "profiles",
+    }
+}
+
+/// Get the modes with the opposite intent of the given mode, performance vs powersave
+fn get_opposite_modes(sched_mode: &SchedMode) -> &'static [SchedMode] {
+    match sched_mode {
+        SchedMode::PowerSave => &[SchedMode::Gaming, SchedMode::LowLatency, SchedMode::Server],
+        SchedMode::Gaming | SchedMode::LowLatency | SchedMode::Server => &[SchedMode::PowerSave],
+        SchedMode::Auto | SchedMode::Custom(_) => &[],
+    }
+}
+
+/// Find the configured flags which belong to a mode with the opposite intent, returned with
+/// that mode. Based on the built-in flags: a flag of the opposite mode belongs to it if the
+/// built-in flags of the given mode set the same option to another value, e.g. `-m performance`
+/// vs `-m powersave`, or contain the flag it conflicts with, e.g. `--performance` vs `--powersave`
+fn find_opposite_intent_flags(
+    scx_sched: &SupportedSched,
+    sched_mode: &SchedMode,
+    scx_flags: &[String],
+) -> Vec<(String, SchedMode)> {
+    let mode_defaults = default_flags(scx_sched, sched_mode.clone());
+    let mode_options = flags::split_options(&mode_defaults);
+    let conflicts_with_mode = |option: &str| {
+        get_conflicting_scx_flags(scx_sched)
+            .iter()
+            .filter_map(|&(flag, other_flag)| {
+                if option == flag {
+                    Some(other_flag)
+                } else if option == other_flag {
+                    Some(flag)
+                } else {
+                    None
+                }
+            })
+            .any(|conflicting| {
+                mode_options
+                    .iter()
+                    .any(|(option, _)| *option == conflicting)
+            })
+    };
+
+    let configured_options = flags::split_options(scx_flags);
+    let mut opposite_flags = vec![];
+    for opposite_mode in get_opposite_modes(sched_mode) {
+        let opposite_defaults = default_flags(scx_sched, opposite_mode.clone());
+        for (option, value) in flags::split_options(&opposite_defaults) {
+            let belongs_to_opposite = match value {
+                Some(value) => mode_options.iter().any(|(mode_option, mode_value)| {
+                    *mode_option == option && *mode_value != Some(value)
+                }),
+                None => conflicts_with_mode(option),
+            };
+            if !belongs_to_opposite || !configured_options.contains(&(option, value)) {
+                continue;
+            }
+            let flag = match value {
+                Some(value) => format!("{option} {value}"),
+                None => option.to_owned(),
+            };
+            if !opposite_flags
+                .iter()
+                .any(|(opposite_flag, _)| *opposite_flag == flag)
+            {
+                opposite_flags.push((flag, opposite_mode.clone()));
+            }
+        }
+    }
+    opposite_flags
+}
+
+/// Get the pairs of flags which contradict each other for the given scheduler
+fn get_conflicting_scx_flags(
+    scx_sched: &SupportedSched,
+) -> &'static [(&'static str, &'static str)] {
+    match scx_sched {
+        SupportedSched::Lavd => &[("--performance", "--powersave")],
+        // other schedulers select their profile with a single option, e.g. scx_bpfland -m,
+        // which is covered by the repeated option check
+        _ => &[],
+    }
+}
+
+/// Get the flags which the given scheduler deprecated or removed, with the flag replacing them
+/// if there is one. Add flags here when a scheduler release renames or drops them
+fn get_deprecated_scx_flags(
+    scx_sched: &SupportedSched,
+) -> &'static [(&'static str, Option<&'static str>)] {
+    match scx_sched {
+        SupportedSched::Bpfland => &[("--nvcsw-max-thresh", None)],
+        SupportedSched::Lavd => &[
+            ("--prefer-smt-core", Some("--cpu-pref-order")),
+            ("--prefer-little-core", Some("--cpu-pref-order")),
+        ],
+        _ => &[],
+    }
+}
+
+/// Get a warning for each deprecated flag in the scx flags of a single mode
+fn find_deprecated_flags(scx_sched: &SupportedSched, scx_flags: &[String]) -> Vec<String> {
+    let deprecated_flags = get_deprecated_scx_flags(scx_sched);
+    let mut warnings = vec![];
+    for (option, _) in flags::split_options(scx_flags) {
+        let Some((flag, replacement)) = deprecated_flags.iter().find(|(flag, _)| *flag == option)
+        else {
+            continue;
+        };
+        let warning = match replacement {
+            Some(replacement) => format!("{flag} is deprecated, use {replacement}"),
+            None => format!("{flag} is deprecated and no longer has an effect"),
+        };
+        if !warnings.contains(&warning) {
+            warnings.push(warning);
+        }
+    }
+    warnings
+}
+
+/// Check the scx flags of a single mode for duplicated and conflicting flags
+fn check_scx_flags(scx_sched: &SupportedSched, scx_flags: &[String]) -> Vec<String> {
+    let mut errors = vec![];
+
+    let options = flags::split_options(scx_flags);
+    for (idx, (option, value)) in options.iter().enumerate() {
+        let Some((_, prev_value)) = options[..idx].iter().find(|(prev, _)| prev == option) else {
+            continue;
+        };
+        match (prev_value, value) {
+            (Some(prev_value), Some(value)) if prev_value != value => errors.push(format!(
+                "conflicting flags {option} {prev_value} and {option} {value}"
+            )),
+            _ => errors.push(format!("duplicate flag {option}")),
+        }
+    }
+
+    for (flag, other_flag) in get_conflicting_scx_flags(scx_sched) {
+        let has_option = |flag: &str| options.iter().any(|(option, _)| *option == flag);
+        if has_option(flag) && has_option(other_flag) {
+            errors.push(format!("conflicting flags {flag} and {other_flag}"));
+        }
+    }
+    errors
+}
+
+/// Get the default scx flags for the given sched mode
+fn get_default_scx_flags_for_mode(scx_sched: &SupportedSched, sched_mode: SchedMode) -> Vec<&str> {
+    match scx_sched {
+        SupportedSched::Bpfland => match sched_mode {
+            SchedMode::Gaming => vec!["-c", "0", "-k", "-m", "performance"],
+            SchedMode::LowLatency => vec!["--lowlatency"],
+            SchedMode::PowerSave => vec!["-m", "powersave"],
+            SchedMode::Auto | SchedMode::Server | SchedMode::Custom(_) => vec![],
+        },
+        SupportedSched::Lavd => match sched_mode {
+            SchedMode::Gaming | SchedMode::LowLatency | SchedMode::Server => vec!["--performance"],
+            SchedMode::PowerSave => vec!["--powersave"],
+            // NOTE: potentially adding --auto in future
+            SchedMode::Auto | SchedMode::Custom(_) => vec![],
+        },
+        SupportedSched::Flash => match sched_mode {
+            SchedMode::Gaming | SchedMode::Server => vec!["-m", "all"],
+            SchedMode::LowLatency => vec!["-m", "performance", "-w", "-C", "0"],
+            SchedMode::PowerSave => vec![
+                "-m",
+                "powersave",
+                "-I",
+                "10000",
+                "-t",
+                "10000",
+                "-s",
+                "10000",
+                "-S",
+                "1000",
+            ],
+            SchedMode::Auto | SchedMode::Custom(_) => vec![],
+        },
+        SupportedSched::P2dq => match sched_mode {
+            SchedMode::Gaming => vec!["--task-slice", "true", "-f", "--sched-mode", "performance"],
+            SchedMode::LowLatency => vec!["-y", "-f", "--task-slice", "true"],
+            SchedMode::PowerSave => vec!["--sched-mode", "efficiency"],
+            SchedMode::Server => vec!["--sched-mode", "performance"],
+            SchedMode::Auto | SchedMode::Custom(_) => vec![],
+        },
+        SupportedSched::Cosmos => match sched_mode {
+            SchedMode::Gaming => vec!["-m", "performance", "-c", "0", "-p", "0"],
+            SchedMode::LowLatency => {
+                vec!["-m", "performance", "-c", "0", "-p", "0", "-s", "5000"]
+            }
+            SchedMode::PowerSave => vec!["-m", "powersave", "-p", "5000"],
+            SchedMode::Server => vec!["-m", "performance"],
+            SchedMode::Auto | SchedMode::Custom(_) => vec![],
+        },
+        // scx_tickless targets power efficiency, it has no dedicated latency tuning
+        SupportedSched::Tickless => match sched_mode {
+            SchedMode::PowerSave => vec!["-f", "50", "-p"],
+            SchedMode::Auto | SchedMode::Server | SchedMode::Custom(_) => vec!["-f", "100"],
+            SchedMode::Gaming | SchedMode::LowLatency => vec![],
+        },
+        // scx_nest has no performance or powersave switch, it's tuned by the idle core search and
+        // the size of the reserve nest
+        SupportedSched::Nest => match sched_mode {
+            SchedMode::Gaming => vec!["-I"],
+            SchedMode::LowLatency => vec!["-I", "-s", "10000"],
+            SchedMode::PowerSave => vec!["-m", "2"],
+            SchedMode::Auto | SchedMode::Server | SchedMode::Custom(_) => vec![],
+        },
+        // scx_flatcg schedules by the cgroup weights in every mode, the modes only change the
+        // time slice
+        SupportedSched::Flatcg => match sched_mode {
+            SchedMode::Gaming => vec!["-s", "5000"],
+            SchedMode::LowLatency => vec!["-s", "2000"],
+            SchedMode::Server => vec!["-s", "40000"],
+            SchedMode::Auto | SchedMode::PowerSave | SchedMode::Custom(_) => vec![],
+        },
+        // scx_central is a demo of central scheduling, it has no tuning for the modes
+        SupportedSched::Central => vec![],
+        // scx_simple is a baseline for testing, it only has the FIFO switch
+        SupportedSched::Simple => vec![],
+        // scx_chaos perturbs scheduling on purpose, which perturbations to inject is set in the
+        // config, e.g. "--random-delay-frequency 0.1"
+        SupportedSched::Chaos => vec![],
+        // scx_layered behavior is defined by the layer spec, which must be passed by the user
+        // config, e.g. as "f:/etc/scx_layered.json", see `check_required_flags`
+        SupportedSched::Layered => vec![],
+        // shorter slices for interactive tasks, less frequent load balancing to save power
+        SupportedSched::Rusty => match sched_mode {
+            SchedMode::Gaming => vec!["-u", "5000", "-o", "500", "-k"],
+            SchedMode::LowLatency => vec!["-u", "2000", "-o", "250", "-k"],
+            SchedMode::PowerSave => vec!["-i", "4.0", "-I", "0.5"],
+            SchedMode::Auto | SchedMode::Server | SchedMode::Custom(_) => vec![],
+        },
+        // scx_rustland doesn't support any of these modes
+        SupportedSched::Rustland => vec![],
+        // custom schedulers get their flags only from the config
+        SupportedSched::Custom(_) => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use crate::config::*;
+    use crate::SUPPORTED_SCHEDS;
+
+    /// Get Sched object for configuration object
+    fn get_default_sched_for_config(scx_sched: &SupportedSched) -> Sched {
+        let get_flags = |sched_mode| {
+            Some(
+                get_default_scx_flags_for_mode(scx_sched, sched_mode)
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+            )
+        };
+
+        Sched {
+            path: None,
+            enabled: None,
+            default_mode: None,
+            auto_mode: get_flags(SchedMode::Auto),
+            gaming_mode: get_flags(SchedMode::Gaming),
+            lowlatency_mode: get_flags(SchedMode::LowLatency),
+            powersave_mode: get_flags(SchedMode::PowerSave),
+            server_mode: get_flags(SchedMode::Server),
+            extra_flags: None,
+            profiles: None,
+            descriptions: None,
+            cpu_affinity: None,
+            flags_file: None,
+            hooks: None,
+            min_kernel: None,
+            oom_score_adj: None,
+            restart_on_change: None,
+            log_output: None,
+            env: None,
+            min_cpus: None,
+            require_hybrid: None,
+            cgroup: None,
+            cgroup_required: None,
+        }
+    }
+
+    /// The default config as it was built in code before it was embedded
+    fn get_hand_built_default_config() -> Config {
+        Config {
+            default_mode: Some(SchedMode::Auto),
+            scheds: SUPPORTED_SCHEDS
+                .iter()
+                .map(|scx_sched| {
+                    (
+                        get_name_from_scx(scx_sched).to_owned(),
+                        get_default_sched_for_config(scx_sched),
+                    )
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn touch_config(path: &Path) -> String {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "default_mode = \"Auto\"\n").unwrap();
+        path.to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn test_default_config() {
+        let config_str = r#"
+default_mode = "Auto"
+
+[scheds.scx_bpfland]
+auto_mode = []
+gaming_mode = ["-c", "0", "-k", "-m", "performance"]
+lowlatency_mode = ["--lowlatency"]
+powersave_mode = ["-m", "powersave"]
+server_mode = []
+
+[scheds.scx_rusty]
+auto_mode = []
+gaming_mode = ["-u", "5000", "-o", "500", "-k"]
+lowlatency_mode = ["-u", "2000", "-o", "250", "-k"]
+powersave_mode = ["-i", "4.0", "-I", "0.5"]
+server_mode = []
+
+[scheds.scx_lavd]
+auto_mode = []
+gaming_mode = ["--performance"]
+lowlatency_mode = ["--performance"]
+powersave_mode = ["--powersave"]
+server_mode = ["--performance"]
+
+[scheds.scx_rustland]
+auto_mode = []
+gaming_mode = []
+lowlatency_mode = []
+powersave_mode = []
+server_mode = []
+
+[scheds.scx_layered]
+auto_mode = []
+gaming_mode = []
+lowlatency_mode = []
+powersave_mode = []
+server_mode = []
+
+[scheds.scx_flash]
+auto_mode = []
+gaming_mode = ["-m", "all"]
+lowlatency_mode = ["-m", "performance", "-w", "-C", "0"]
+powersave_mode = ["-m", "powersave", "-I", "10000", "-t", "10000", "-s", "10000", "-S", "1000"]
+server_mode = ["-m", "all"]
+
+[scheds.scx_p2dq]
+auto_mode = []
+gaming_mode = ["--task-slice", "true", "-f", "--sched-mode", "performance"]
+lowlatency_mode = ["-y", "-f", "--task-slice", "true"]
+powersave_mode = ["--sched-mode", "efficiency"]
+server_mode = ["--sched-mode", "performance"]
+
+[scheds.scx_tickless]
+auto_mode = ["-f", "100"]
+gaming_mode = []
+lowlatency_mode = []
+powersave_mode = ["-f", "50", "-p"]
+server_mode = ["-f", "100"]
+
+[scheds.scx_nest]
+auto_mode = []
+gaming_mode = ["-I"]
+lowlatency_mode = ["-I", "-s", "10000"]
+powersave_mode = ["-m", "2"]
+server_mode = []
+
+[scheds.scx_central]
+auto_mode = []
+gaming_mode = []
+lowlatency_mode = []
+powersave_mode = []
+server_mode = []
+
+[scheds.scx_cosmos]
+auto_mode = []
+gaming_mode = ["-m", "performance", "-c", "0", "-p", "0"]
+lowlatency_mode = ["-m", "performance", "-c", "0", "-p", "0", "-s", "5000"]
+powersave_mode = ["-m", "powersave", "-p", "5000"]
+server_mode = ["-m", "performance"]
+
+[scheds.scx_flatcg]
+auto_mode = []
+gaming_mode = ["-s", "5000"]
+lowlatency_mode = ["-s", "2000"]
+powersave_mode = []
+server_mode = ["-s", "40000"]
+
+[scheds.scx_simple]
+auto_mode = []
+gaming_mode = []
+lowlatency_mode = []
+powersave_mode = []
+server_mode = []
+
+[scheds.scx_chaos]
+auto_mode = []
+gaming_mode = []
+lowlatency_mode = []
+powersave_mode = []
+server_mode = []
+"#;
+
+        let parsed_config =
+            parse_config_content(config_str, ConfigFormat::Toml).expect("Failed to parse config");
+        let expected_config = get_default_config();
+
+        assert_eq!(parsed_config, expected_config);
+    }
+
+    #[test]
+    fn test_embedded_default_config() {
+        assert_eq!(get_default_config(), get_hand_built_default_config());
+    }
+
+    #[test]
+    fn test_scheduler_names() {
+        let config_str = r#"
+[scheds.scx_rusty]
+auto_mode = []
+
+[scheds.scx_bpfland]
+auto_mode = []
+
+[scheds.scx_lavd]
+auto_mode = []
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            config.scheduler_names(),
+            vec!["scx_bpfland", "scx_lavd", "scx_rusty"]
+        );
+        assert!(config.has_scheduler("scx_lavd"));
+        assert!(!config.has_scheduler("scx_flash"));
+        assert!(Config::default().scheduler_names().is_empty());
+    }
+
+    #[test]
+    fn test_check_consistency() {
+        let config_str = r#"
+default_sched = "scx_lavd"
+fallback_scheds = ["scx_bpfland", "scx_rusty"]
+
+[scheds.scx_bpfland]
+auto_mode = []
+"#;
+
+        let mut config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            config.check_consistency(),
+            vec![
+                "default_sched scx_lavd has no [scheds] entry",
+                "fallback_scheds scx_rusty has no [scheds] entry",
+            ]
+        );
+        assert_eq!(
+            config.validation_report().warnings,
+            config.check_consistency()
+        );
+
+        config.default_sched = Some(SupportedSched::Bpfland);
+        config.fallback_scheds.clear();
+        assert!(config.check_consistency().is_empty());
+        assert!(get_default_config().check_consistency().is_empty());
+    }
+
+    /// Load the config like with `SCX_LOADER_CONFIG` set to the path
+    fn load_with_env_config(config_path: &Path) -> Result<Config> {
+        ConfigLoader {
+            env_config: Some(config_path.to_str().unwrap().to_owned()),
+            ..ConfigLoader::new()
+        }
+        .load()
+    }
+
+    #[test]
+    fn test_config_env_override() {
+        let tmp = tempfile::tempdir().unwrap();
+        let config_path = tmp.path().join("config.toml");
+        fs::write(&config_path, "default_sched = \"scx_lavd\"\n").unwrap();
+
+        let config = load_with_env_config(&config_path).unwrap();
+        assert_eq!(config.default_sched, Some(SupportedSched::Lavd));
+        assert!(config.scheds.is_empty());
+    }
+
+    #[test]
+    fn test_print_effective_config() {
+        let tmp = tempfile::tempdir().unwrap();
+        let config_path = tmp.path().join("config.toml");
+        fs::write(
+            &config_path,
+            "default_mode = \"Gaming\"\n\n[scheds.scx_lavd]\ngaming_mode = [\"--performance\"]\n",
+        )
+        .unwrap();
+
+        let config = load_with_env_config(&config_path).unwrap();
+        let serialized = serialize_config(&config).unwrap();
+        assert!(serialized.contains("default_mode = \"Gaming\""));
+        assert!(serialized.contains("[scheds.scx_lavd]"));
+    }
+
+    #[test]
+    fn test_config_env_override_missing_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let config_path = tmp.path().join("missing.toml");
+
+        let err = load_with_env_config(&config_path).unwrap_err();
+        assert!(err.to_string().contains(CONFIG_ENV_VAR));
+    }
+
+    #[test]
+    fn test_export_default_config_toml() {
+        let exported = export_default_config_toml();
+        assert!(exported.starts_with("# scx_loader configuration"));
+        assert!(
+            exported.contains("# Flags passed to scx_cosmos in each mode\n[scheds.scx_cosmos]\n")
+        );
+
+        let config = parse_config_content_strict(&exported, ConfigFormat::Toml).unwrap();
+        assert_eq!(config, get_default_config());
+        config.validate().unwrap();
+        for scx_sched in SUPPORTED_SCHEDS {
+            let sched_config = &config.scheds[get_name_from_scx(scx_sched)];
+            for sched_mode in SCHED_MODES {
+                assert!(extract_scx_flags_from_config(sched_config, sched_mode).is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn test_default_flags() {
+        for (scx_sched, sched_mode) in [
+            (SupportedSched::Bpfland, SchedMode::Gaming),
+            (SupportedSched::Lavd, SchedMode::PowerSave),
+            (SupportedSched::Flash, SchedMode::LowLatency),
+            (SupportedSched::Rustland, SchedMode::Auto),
+        ] {
+            assert_eq!(
+                default_flags(&scx_sched, sched_mode.clone()),
+                get_default_scx_flags_for_mode(&scx_sched, sched_mode)
+            );
+        }
+        assert_eq!(
+            default_flags(&SupportedSched::Lavd, SchedMode::PowerSave),
+            vec!["--powersave"]
+        );
+        // custom schedulers have no built-in flags
+        assert!(default_flags(
+            &SupportedSched::Custom("scx_custom".to_owned()),
+            SchedMode::Gaming
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_get_scx_flags_for_mode_fallback() {
+        let config = Config::default();
+
+        for sched_mode in SCHED_MODES {
+            assert!(
+                get_scx_flags_for_mode(&config, &SupportedSched::Rustland, sched_mode.clone())
+                    .is_empty()
+            );
+        }
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Bpfland, SchedMode::PowerSave),
+            vec!["-m", "powersave"]
+        );
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Rusty, SchedMode::Gaming),
+            vec!["-u", "5000", "-o", "500", "-k"]
+        );
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Rusty, SchedMode::LowLatency),
+            vec!["-u", "2000", "-o", "250", "-k"]
+        );
+        assert!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Rusty, SchedMode::Auto).is_empty()
+        );
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Tickless, SchedMode::PowerSave),
+            vec!["-f", "50", "-p"]
+        );
+        assert!(get_scx_flags_for_mode(&config, &SupportedSched::Nest, SchedMode::Auto).is_empty());
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Cosmos, SchedMode::Gaming),
+            vec!["-m", "performance", "-c", "0", "-p", "0"]
+        );
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Nest, SchedMode::Gaming),
+            vec!["-I"]
+        );
+    }
+
+    #[test]
+    fn test_describe() {
+        // scx_rustland has no built-in flags for the special modes
+        assert_eq!(
+            describe(&SupportedSched::Rustland),
+            SchedCapabilities::default()
+        );
+        assert_eq!(
+            describe(&SupportedSched::Rusty),
+            SchedCapabilities {
+                gaming: true,
+                lowlatency: true,
+                powersave: true,
+            }
+        );
+        assert_eq!(
+            describe(&SupportedSched::Bpfland),
+            SchedCapabilities {
+                gaming: true,
+                lowlatency: true,
+                powersave: true,
+            }
+        );
+        assert_eq!(
+            describe(&SupportedSched::Custom("scx_custom".to_owned())),
+            SchedCapabilities::default()
+        );
+    }
+
+    #[test]
+    fn test_enumerate_all_flags() {
+        let config_str = r#"
+[scheds.scx_lavd]
+gaming_mode = ["--custom"]
+"#;
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        let all_flags = enumerate_all_flags(&config);
+        assert_eq!(all_flags.len(), SUPPORTED_SCHEDS.len() * SCHED_MODES.len());
+
+        assert_eq!(
+            all_flags[0],
+            (
+                SupportedSched::Bpfland,
+                SchedMode::Auto,
+                get_scx_flags_for_mode(&config, &SupportedSched::Bpfland, SchedMode::Auto)
+            )
+        );
+        assert!(all_flags.contains(&(
+            SupportedSched::Lavd,
+            SchedMode::Gaming,
+            vec!["--custom".to_owned()]
+        )));
+        assert!(all_flags.contains(&(
+            SupportedSched::Flatcg,
+            SchedMode::Server,
+            vec!["-s".to_owned(), "40000".to_owned()]
+        )));
+    }
+
+    #[test]
+    fn test_server_mode() {
+        let config_str = r#"
+[scheds.scx_bpfland]
+server_mode = ["-s", "20000"]
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Bpfland, SchedMode::Server),
+            vec!["-s", "20000"]
+        );
+
+        let config = Config::default();
+        let expected_flags: &[(SupportedSched, &[&str])] = &[
+            (SupportedSched::Bpfland, &[]),
+            (SupportedSched::Rusty, &[]),
+            (SupportedSched::Lavd, &["--performance"]),
+            (SupportedSched::Rustland, &[]),
+            (SupportedSched::Layered, &[]),
+            (SupportedSched::Flash, &["-m", "all"]),
+            (SupportedSched::P2dq, &["--sched-mode", "performance"]),
+            (SupportedSched::Tickless, &["-f", "100"]),
+            (SupportedSched::Nest, &[]),
+            (SupportedSched::Central, &[]),
+            (SupportedSched::Cosmos, &["-m", "performance"]),
+            (SupportedSched::Flatcg, &["-s", "40000"]),
+            (SupportedSched::Simple, &[]),
+            (SupportedSched::Chaos, &[]),
+        ];
+        assert_eq!(expected_flags.len(), SUPPORTED_SCHEDS.len());
+        for (scx_sched, flags) in expected_flags {
+            assert_eq!(
+                get_scx_flags_for_mode(&config, scx_sched, SchedMode::Server),
+                *flags,
+                "{scx_sched}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_single_string_flags() {
+        let config_str = r#"
+[scheds.scx_bpfland]
+gaming_mode = "-m performance --primary-domain 'turbo'"
+powersave_mode = ["-m", "powersave"]
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Bpfland, SchedMode::Gaming),
+            vec!["-m", "performance", "--primary-domain", "turbo"]
+        );
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Bpfland, SchedMode::PowerSave),
+            vec!["-m", "powersave"]
+        );
+        assert!(parse_config_content_strict(config_str, ConfigFormat::Toml).is_ok());
+
+        let config_str = "[scheds.scx_bpfland]\ngaming_mode = \"-m 'performance\"\n";
+        let err = parse_config_content(config_str, ConfigFormat::Toml).unwrap_err();
+        assert!(format!("{err:#}").contains("Unbalanced single quote in flags"));
+    }
+
+    #[test]
+    fn test_flags_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let flags_path = tmp.path().join("layered.flags");
+        fs::write(&flags_path, "-s 10000\n-v\n").unwrap();
+
+        let config_str = format!(
+            r#"
+[scheds.scx_layered]
+gaming_mode = ["--run-example"]
+
+[scheds.scx_layered.flags_file]
+gaming_mode = "{0}"
+server_mode = "{0}"
+"#,
+            flags_path.display()
+        );
+        let config = parse_config_content(&config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Layered, SchedMode::Gaming),
+            vec!["--run-example", "-s", "10000", "-v"]
+        );
+        // the file replaces the built-in flags
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Layered, SchedMode::Server),
+            vec!["-s", "10000", "-v"]
+        );
+        assert!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Layered, SchedMode::PowerSave)
+                .is_empty()
+        );
+        assert!(config.validation_report().errors.is_empty());
+
+        let missing_path = tmp.path().join("missing.flags");
+        let config_str = format!(
+            "[scheds.scx_layered.flags_file]\ngaming_mode = \"{}\"\n",
+            missing_path.display()
+        );
+        let config = parse_config_content(&config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            config.validation_report().errors,
+            vec![format!(
+                "scx_layered flags_file gaming_mode: Failed to read flags file {}: No such file or directory (os error 2)",
+                missing_path.display()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_cpu_affinity() {
+        let config_str = r#"
+[scheds.scx_lavd.cpu_affinity]
+gaming_mode = "0-7,16"
+powersave_mode = "0-1"
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            get_cpu_affinity(&config, &SupportedSched::Lavd, &SchedMode::Gaming).unwrap(),
+            Some(vec![0, 1, 2, 3, 4, 5, 6, 7, 16])
+        );
+        assert_eq!(
+            get_cpu_affinity(&config, &SupportedSched::Lavd, &SchedMode::Auto).unwrap(),
+            None
+        );
+        assert_eq!(
+            get_cpu_affinity(&config, &SupportedSched::Bpfland, &SchedMode::Gaming).unwrap(),
+            None
+        );
+        assert!(config.validation_report().errors.is_empty());
+
+        let config_str = r#"
+[scheds.scx_lavd.cpu_affinity]
+gaming_mode = "7-0"
+"#;
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            config.validation_report().errors,
+            vec![
+                "scx_lavd cpu_affinity gaming_mode: Invalid CPU range \"7-0\" in CPU list \"7-0\""
+            ]
+        );
+    }
+
+    #[test]
+    fn test_enabled_schedulers() {
+        let config_str = r#"
+[scheds.scx_bpfland]
+gaming_mode = ["-k"]
+
+[scheds.scx_lavd]
+enabled = false
+gaming_mode = ["--performance"]
+
+[scheds.scx_rusty]
+enabled = true
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            config.enabled_schedulers(),
+            vec!["scx_bpfland", "scx_rusty"]
+        );
+        assert!(config.has_scheduler("scx_lavd"));
+        assert!(!config.is_sched_enabled(&SupportedSched::Lavd));
+        assert!(config.is_sched_enabled(&SupportedSched::Flash));
+        // the flags of disabled schedulers are still available
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Lavd, SchedMode::Gaming),
+            vec!["--performance"]
+        );
+    }
+
+    #[test]
+    fn test_central_config() {
+        let config_str = r#"
+[scheds.scx_central]
+lowlatency_mode = ["-s", "5000"]
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Central, SchedMode::LowLatency),
+            vec!["-s", "5000"]
+        );
+        assert!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Central, SchedMode::Gaming).is_empty()
+        );
+        assert!(get_default_config().has_scheduler("scx_central"));
+    }
+
+    #[test]
+    fn test_flatcg_config() {
+        let config_str = r#"
+[scheds.scx_flatcg]
+gaming_mode = ["-s", "3000", "-f"]
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Flatcg, SchedMode::Gaming),
+            vec!["-s", "3000", "-f"]
+        );
+        // the modes left out fall back to the built-in flags
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Flatcg, SchedMode::LowLatency),
+            vec!["-s", "2000"]
+        );
+        assert!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Flatcg, SchedMode::Auto).is_empty()
+        );
+        assert_eq!(
+            get_scx_flags_for_mode(
+                &Config::default(),
+                &SupportedSched::Flatcg,
+                SchedMode::Gaming
+            ),
+            vec!["-s", "5000"]
+        );
+        assert!(get_default_config().has_scheduler("scx_flatcg"));
+    }
+
+    #[test]
+    fn test_simple_fallback() {
+        let config = Config::default();
+        for sched_mode in SCHED_MODES {
+            assert!(
+                get_scx_flags_for_mode(&config, &SupportedSched::Simple, sched_mode.clone())
+                    .is_empty()
+            );
+        }
+        assert_eq!(
+            get_scx_flags_for_mode(
+                &config,
+                &SupportedSched::Simple,
+                SchedMode::Custom("benchmark".to_owned())
+            ),
+            Vec::<String>::new()
+        );
+        assert!(get_default_config().has_scheduler("scx_simple"));
+    }
+
+    #[test]
+    fn test_chaos_opt_in() {
+        let config = get_default_config();
+        assert!(config.has_scheduler("scx_chaos"));
+        assert!(!config.is_sched_enabled(&SupportedSched::Chaos));
+        assert!(!config
+            .enabled_schedulers()
+            .contains(&"scx_chaos".to_owned()));
+        assert!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Chaos, SchedMode::Gaming).is_empty()
+        );
+
+        let config_str = r#"
+[scheds.scx_chaos]
+enabled = true
+auto_mode = "--random-delay-frequency 0.1 --random-delay-min-us 100 --random-delay-max-us 500"
+"#;
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        config.validate().unwrap();
+        assert!(config.is_sched_enabled(&SupportedSched::Chaos));
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Chaos, SchedMode::Auto),
+            vec![
+                "--random-delay-frequency",
+                "0.1",
+                "--random-delay-min-us",
+                "100",
+                "--random-delay-max-us",
+                "500"
+            ]
+        );
+        assert!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Chaos, SchedMode::PowerSave)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_get_scx_flags_for_mode_verbatim() {
+        let config_str = r#"
+[scheds.scx_layered]
+gaming_mode = ["--config", "/etc/layered.json"]
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Layered, SchedMode::Gaming),
+            vec!["--config", "/etc/layered.json"]
+        );
+        // without a layer spec it refuses to start instead of running a demo config
+        let auto_flags = get_scx_flags_for_mode(&config, &SupportedSched::Layered, SchedMode::Auto);
+        assert!(auto_flags.is_empty());
+        assert_eq!(
+            check_required_flags(&SupportedSched::Layered, &auto_flags)
+                .unwrap_err()
+                .to_string(),
+            "scx_layered needs a layer spec, set the flags of the mode in [scheds.scx_layered], \
+             e.g. [\"f:/etc/scx_layered.json\"]"
+        );
+        check_required_flags(
+            &SupportedSched::Layered,
+            &get_scx_flags_for_mode(&config, &SupportedSched::Layered, SchedMode::Gaming),
+        )
+        .unwrap();
+        check_required_flags(&SupportedSched::Bpfland, &[]).unwrap();
+    }
+
+    #[test]
+    fn test_get_scx_flags_for_mode_partial_sched() {
+        let config_str = r#"
+[scheds.scx_flash]
+auto_mode = []
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Flash, SchedMode::LowLatency),
+            vec!["-m", "performance", "-w", "-C", "0"]
+        );
+    }
+
+    #[test]
+    fn test_parse_sched_section() {
+        let config_str = r#"
+[scheds.scx_p2dq]
+auto_mode = ["--sched-mode", "default"]
+powersave_mode = ["--sched-mode", "efficiency", "--idle-resume-us", "0"]
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        let p2dq = &config.scheds["scx_p2dq"];
+        assert_eq!(
+            p2dq.auto_mode,
+            Some(vec!["--sched-mode".to_owned(), "default".to_owned()])
+        );
+        assert_eq!(p2dq.gaming_mode, None);
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::P2dq, SchedMode::PowerSave),
+            vec!["--sched-mode", "efficiency", "--idle-resume-us", "0"]
+        );
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::P2dq, SchedMode::Gaming),
+            vec!["--task-slice", "true", "-f", "--sched-mode", "performance"]
+        );
+    }
+
+    #[test]
+    fn test_custom_sched() {
+        let config_str = r#"
+default_sched = "scx_mysched"
+
+[scheds.scx_mysched]
+path = "/opt/scx/bin/scx_mysched"
+auto_mode = ["--auto"]
+gaming_mode = ["--gaming"]
+lowlatency_mode = ["--lowlatency"]
+powersave_mode = ["--powersave"]
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        let scx_sched = SupportedSched::Custom("scx_mysched".to_owned());
+        assert_eq!(config.default_sched, Some(scx_sched.clone()));
+        assert_eq!(
+            get_scx_bin_path(&config, &scx_sched),
+            "/opt/scx/bin/scx_mysched"
+        );
+        assert_eq!(get_scx_bin_path(&config, &SupportedSched::Lavd), "scx_lavd");
+
+        for (sched_mode, expected_flag) in [
+            (SchedMode::Auto, "--auto"),
+            (SchedMode::Gaming, "--gaming"),
+            (SchedMode::LowLatency, "--lowlatency"),
+            (SchedMode::PowerSave, "--powersave"),
+        ] {
+            assert_eq!(
+                get_scx_flags_for_mode(&config, &scx_sched, sched_mode),
+                vec![expected_flag]
+            );
+        }
+    }
+
+    #[test]
+    fn test_custom_sched_collision() {
+        let config_str = r#"
+[scheds.scx_lavd]
+path = "/opt/scx/bin/scx_lavd"
+"#;
+
+        let err = parse_config_content(config_str, ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("collides"));
+
+        let err = parse_config_content("default_sched = \"scx_missing\"\n", ConfigFormat::Toml)
+            .unwrap_err();
+        assert!(err.to_string().contains("scx_missing"));
+    }
+
+    #[test]
+    fn test_resolve_sched_name() {
+        let mut config = get_default_config();
+        config.scheds.insert(
+            "scx_mysched".to_owned(),
+            Sched {
+                path: Some("/opt/scx/bin/scx_mysched".to_owned()),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(
+            resolve_sched_name(&config, "scx_lavd"),
+            Some(SupportedSched::Lavd)
+        );
+        assert_eq!(
+            resolve_sched_name(&config, "scx_mysched"),
+            Some(SupportedSched::Custom("scx_mysched".to_owned()))
+        );
+        assert_eq!(resolve_sched_name(&config, "scx_missing"), None);
+    }
+
+    #[test]
+    fn test_resolve_default_mode() {
+        let config_str = r#"
+default_mode = "PowerSave"
+
+[scheds.scx_lavd]
+default_mode = "Gaming"
+
+[scheds.scx_rusty]
+auto_mode = []
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            resolve_default_mode(&config, &SupportedSched::Lavd),
+            SchedMode::Gaming
+        );
+        assert_eq!(
+            resolve_default_mode(&config, &SupportedSched::Rusty),
+            SchedMode::PowerSave
+        );
+        assert_eq!(
+            resolve_default_mode(&Config::default(), &SupportedSched::Lavd),
+            SchedMode::Auto
+        );
+    }
+
+    #[test]
+    fn test_extra_flags() {
+        let config_str = r#"
+[scheds.scx_bpfland.extra_flags]
+gaming_mode = ["--verbose", "-k"]
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Bpfland, SchedMode::Gaming),
+            vec!["-c", "0", "-k", "-m", "performance", "--verbose"]
+        );
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Bpfland, SchedMode::PowerSave),
+            vec!["-m", "powersave"]
+        );
+    }
+
+    #[test]
+    fn test_mode_descriptions() {
+        let config_str = r#"
+[scheds.scx_bpfland]
+gaming_mode = ["-m", "performance"]
+
+[scheds.scx_bpfland.descriptions]
+gaming_mode = "Prioritize interactive tasks"
+powersave_mode = "Prefer power efficient cores"
+
+[scheds.scx_lavd]
+auto_mode = []
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            get_mode_description(&config, &SupportedSched::Bpfland, &SchedMode::Gaming),
+            Some("Prioritize interactive tasks")
+        );
+        assert_eq!(
+            get_mode_description(&config, &SupportedSched::Bpfland, &SchedMode::Auto),
+            None
+        );
+        assert_eq!(
+            get_mode_description(&config, &SupportedSched::Lavd, &SchedMode::Gaming),
+            None
+        );
+
+        let serialized = serialize_config(&config).unwrap();
+        assert_eq!(
+            parse_config_content(&serialized, ConfigFormat::Toml).unwrap(),
+            config
+        );
+    }
+
+    #[test]
+    fn test_build_command_line() {
+        let mut config = get_default_config();
+        config.global_flags = vec!["-v".to_owned()];
+        assert_eq!(
+            build_command_line(&config, &SupportedSched::Bpfland, SchedMode::Gaming),
+            vec!["scx_bpfland", "-c", "0", "-k", "-m", "performance", "-v"]
+        );
+
+        config.scheds.insert(
+            "scx_mysched".to_owned(),
+            Sched {
+                path: Some("/opt/scx/bin/scx_mysched".to_owned()),
+                gaming_mode: Some(vec!["--performance".to_owned()]),
+                ..Default::default()
+            },
+        );
+        let scx_sched = SupportedSched::Custom("scx_mysched".to_owned());
+        assert_eq!(
+            build_command_line(&config, &scx_sched, SchedMode::Gaming),
+            vec!["/opt/scx/bin/scx_mysched", "--performance", "-v"]
+        );
+    }
+
+    #[test]
+    fn test_global_flags() {
+        let config_str = r#"
+global_flags = ["-v"]
+
+[scheds.scx_lavd]
+gaming_mode = ["--performance", "-v"]
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Lavd, SchedMode::Gaming),
+            vec!["--performance", "-v"]
+        );
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Lavd, SchedMode::PowerSave),
+            vec!["--powersave", "-v"]
+        );
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Bpfland, SchedMode::Auto),
+            vec!["-v"]
+        );
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Bpfland, SchedMode::LowLatency),
+            vec!["--lowlatency", "-v"]
+        );
+    }
+
+    #[test]
+    fn test_profiles() {
+        let config_str = r#"
+[scheds.scx_bpfland]
+gaming_mode = ["-k"]
+
+[scheds.scx_bpfland.profiles]
+streaming = ["-m", "performance"]
+compile = ["-s", "20000"]
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            get_scx_flags_for_profile(&config, &SupportedSched::Bpfland, "streaming"),
+            Some(vec!["-m".to_owned(), "performance".to_owned()])
+        );
+        assert_eq!(
+            get_scx_flags_for_profile(&config, &SupportedSched::Bpfland, "compile"),
+            Some(vec!["-s".to_owned(), "20000".to_owned()])
+        );
+        assert_eq!(
+            get_scx_flags_for_profile(&config, &SupportedSched::Bpfland, "battery"),
+            None
+        );
+        assert_eq!(
+            get_scx_flags_for_profile(&config, &SupportedSched::Lavd, "streaming"),
+            None
+        );
+        // fixed modes keep working
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Bpfland, SchedMode::Gaming),
+            vec!["-k"]
+        );
+    }
+
+    #[test]
+    fn test_opposite_intent_flags() {
+        let config_str = r#"
+[scheds.scx_bpfland]
+powersave_mode = ["-m", "performance"]
+gaming_mode = ["-k", "-m", "powersave"]
+
+[scheds.scx_lavd]
+powersave_mode = ["--performance"]
+
+[scheds.scx_flash]
+powersave_mode = ["-m", "powersave", "-k"]
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        let report = config.validation_report();
+        assert_eq!(
+            report.warnings,
+            vec![
+                "scx_bpfland gaming_mode: -m powersave is a built-in flag of powersave_mode, which has the opposite intent",
+                "scx_bpfland powersave_mode: -m performance is a built-in flag of gaming_mode, which has the opposite intent",
+                "scx_lavd powersave_mode: --performance is a built-in flag of gaming_mode, which has the opposite intent",
+            ]
+        );
+        assert!(report.errors.is_empty());
+        assert!(config.validate().is_ok());
+
+        // the built-in flags don't contradict themselves
+        assert!(get_default_config().validation_report().warnings.is_empty());
+    }
+
+    #[test]
+    fn test_deprecated_flags() {
+        let config_str = r#"
+[scheds.scx_bpfland]
+gaming_mode = ["-m", "performance", "--nvcsw-max-thresh", "10"]
+
+[scheds.scx_lavd]
+powersave_mode = ["--powersave", "--prefer-little-core"]
+lowlatency_mode = ["--performance"]
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        let report = config.validation_report();
+        assert_eq!(
+            report.warnings,
+            vec![
+                "scx_bpfland gaming_mode: --nvcsw-max-thresh is deprecated and no longer has an effect",
+                "scx_lavd powersave_mode: --prefer-little-core is deprecated, use --cpu-pref-order",
+            ]
+        );
+        assert!(report.errors.is_empty());
+        assert!(config.validate().is_ok());
+
+        // current flags don't warn
+        assert!(find_deprecated_flags(
+            &SupportedSched::Lavd,
+            &["--cpu-pref-order".to_owned(), "0-3".to_owned()]
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_custom_mode() {
+        let config_str = r#"
+[scheds.scx_bpfland]
+default_mode = "compile"
+
+[scheds.scx_bpfland.profiles]
+compile = ["-s", "20000"]
+
+[scheds.scx_lavd]
+default_mode = "Compile"
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        let compile_mode = SchedMode::Custom("compile".to_owned());
+        assert_eq!(
+            resolve_default_mode(&config, &SupportedSched::Bpfland),
+            compile_mode
+        );
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Bpfland, compile_mode.clone()),
+            vec!["-s", "20000"]
+        );
+        // schedulers without the profile fall back to the flags of Auto
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Flash, compile_mode),
+            get_scx_flags_for_mode(&config, &SupportedSched::Flash, SchedMode::Auto)
+        );
+        assert_eq!(
+            config.validation_report().warnings,
+            vec!["scx_lavd default_mode Compile is neither a mode nor a profile, the flags of Auto are used"]
+        );
+    }
+
+    #[test]
+    fn test_unknown_custom_modes() {
+        let config_str = r#"
+default_mode = "Gamig"
+process_modes = { "cargo" = "compile", "make" = "Compile" }
+
+[power]
+on_battery_mode = "compile"
+on_ac_mode = "Auto"
+
+[[schedule]]
+start = "09:00"
+end = "17:00"
+sched = "scx_lavd"
+mode = "compile"
+
+[scheds.scx_bpfland.profiles]
+compile = ["-s", "20000"]
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert!(config.has_profile("compile"));
+        assert_eq!(
+            config.check_consistency(),
+            vec![
+                "schedule scx_lavd mode compile is neither a mode nor a profile, the flags of Auto are used",
+                "default_mode Gamig is neither a mode nor a profile of any scheduler, the flags of Auto are used",
+                "process_modes make Compile is neither a mode nor a profile of any scheduler, the flags of Auto are used",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_default_config() {
+        let config = get_default_config();
+        assert_eq!(config.validation_report(), ValidationReport::default());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_duplicate_flags() {
+        let config_str = r#"
+[scheds.scx_bpfland]
+gaming_mode = ["-k", "-c", "0", "-k"]
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            config.validation_report().errors,
+            vec!["scx_bpfland gaming_mode: duplicate flag -k"]
+        );
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_conflicting_flags() {
+        let config_str = r#"
+[scheds.scx_bpfland]
+gaming_mode = ["-m", "performance", "-m", "powersave"]
+
+[scheds.scx_lavd]
+powersave_mode = ["--performance", "--powersave"]
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            config.validation_report().errors,
+            vec![
+                "scx_bpfland gaming_mode: conflicting flags -m performance and -m powersave",
+                "scx_lavd powersave_mode: conflicting flags --performance and --powersave",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_duration_flags() {
+        let config_str = r#"
+[scheds.scx_bpfland]
+gaming_mode = ["--slice-us", "5ms", "-m", "performance"]
+lowlatency_mode = ["-s", "5fortnights"]
+powersave_mode = ["--slice-us", "5fortnights"]
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            get_scx_flags_for_mode(&config, &SupportedSched::Bpfland, SchedMode::Gaming),
+            vec!["--slice-us", "5000", "-m", "performance"]
+        );
+        // -s isn't known to take a duration, its value is passed through
+        assert_eq!(
+            config.validation_report().errors,
+            vec![
+                "scx_bpfland powersave_mode: --slice-us: Unknown duration unit fortnights in \
+                 5fortnights, expected us, ms or s"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_config_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let config_path = tmp.path().join("config.toml");
+        let config_path = config_path.to_str().unwrap();
+
+        write_config(&get_default_config(), config_path).unwrap();
+        let report = validate_config_file(config_path);
+        assert_eq!(report, ValidationReport::default());
+        assert_eq!(report.exit_code(true), 0);
+
+        fs::write(
+            config_path,
+            "[scheds.scx_lavd]\ngaming_mode = [\"--performance\", \"--powersave\"]\n",
+        )
+        .unwrap();
+        assert_eq!(validate_config_file(config_path).exit_code(false), 1);
+
+        fs::write(config_path, "default_sched = \"scx_lavd\"\n").unwrap();
+        let report = validate_config_file(config_path);
+        assert!(report.errors.is_empty());
+        assert_eq!(report.exit_code(false), 0);
+        assert_eq!(report.exit_code(true), 1);
+
+        let report = validate_config_file(&format!("{config_path}.missing"));
+        assert_eq!(report.exit_code(false), 1);
+    }
+
+    #[test]
+    fn test_strict_unknown_fields() {
+        let config_str = r#"
+fallback_scheds = []
+
+[scheds.scx_bpfland]
+gamming_mode = ["-k"]
+powersave_mode = ["-m", "powersave"]
+
+[[schedule]]
+start = "22:00"
+end = "07:00"
+sched = "scx_lavd"
+mod = "PowerSave"
+"#;
+
+        let config = parse_config_content(config_str, ConfigFormat::Toml).unwrap();
+        assert_eq!(config.scheds["scx_bpfland"].gaming_mode, None);
+
+        let err = parse_config_content_strict(config_str, ConfigFormat::Toml).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown fields in config: scheds.scx_bpfland.gamming_mode, schedule[0].mod"
+        );
+
+        let json_str = r#"{"scheds": {"scx_lavd": {"gaming_mode": ["--performance"]}}}"#;
+        assert!(parse_config_content_strict(json_str, ConfigFormat::Json).is_ok());
+        let json_str = r#"{"default_mod": "Gaming"}"#;
+        assert!(parse_config_content_strict(json_str, ConfigFormat::Json).is_err());
+        assert!(parse_config_content(json_str, ConfigFormat::Json).is_ok());
+
+        let tmp = tempfile::tempdir().unwrap();
+        let config_path = tmp.path().join("config.toml");
+        fs::write(&config_path, config_str).unwrap();
+        let report = validate_config_file(config_path.to_str().unwrap());
+        assert!(report.errors.is_empty());
+        assert!(report
+            .warnings
+            .contains(&"unknown field scheds.scx_bpfland.gamming_mode is ignored".to_owned()));
+        assert_eq!(report.exit_code(true), 1);
+    }
+
+    #[test]
+    fn test_config_error_variants() {
+        let parse = |config_str| parse_config_content(config_str, ConfigFormat::Toml).unwrap_err();
+
+        assert!(matches!(parse(""), ConfigError::EmptyFile));
+        assert!(matches!(
+            parse("default_mode = [\n"),
+            ConfigError::ParseError(_)
+        ));
+        assert!(matches!(
+            parse("[scheds.scx_bpflandd]\n"),
+            ConfigError::UnknownScheduler(sched_names) if sched_names == ["scx_bpflandd"]
+        ));
+        assert!(matches!(
+            parse("[scheds.lavd]\n"),
+            ConfigError::MisnamedScheduler { sched_name, .. } if sched_name == "lavd"
+        ));
+        assert!(matches!(
+            parse("[scheds.scx_lavd]\npath = \"/opt/scx_lavd\"\n"),
+            ConfigError::CustomSchedulerCollision(_)
+        ));
+        assert!(matches!(
+            parse("default_sched = \"scx_missing\"\n"),
+            ConfigError::UnknownSchedulerReference {
+                field: "default_sched",
+                ..
+            }
+        ));
+        assert!(matches!(
+            parse("[mode_scheds]\ngaming_mode = \"scx_unknown\"\n"),
+            ConfigError::UnknownSchedulerReference {
+                field: "mode_scheds",
+                ..
+            }
+        ));
+        // a typo would otherwise be skipped as not installed
+        assert!(matches!(
+            parse("fallback_scheds = [\"scx_bpfland\", \"scx_lvad\"]\n"),
+            ConfigError::UnknownSchedulerReference {
+                field: "fallback_scheds",
+                sched_name,
+            } if sched_name == "scx_lvad"
+        ));
+        assert!(matches!(
+            parse_config_content_strict("default_mod = \"Gaming\"\n", ConfigFormat::Toml),
+            Err(ConfigError::UnknownFields(fields)) if fields == ["default_mod"]
+        ));
+
+        let tmp = tempfile::tempdir().unwrap();
+        let config_path = tmp.path().join("missing.toml");
+        let err = parse_config_file(config_path.to_str().unwrap()).unwrap_err();
+        assert!(matches!(err, ConfigError::FileNotFound(_)));
+
+        // the variant survives the conversion into anyhow::Error
+        let err = anyhow::Error::from(parse(""));
+        assert!(matches!(
+            err.downcast_ref::<ConfigError>(),
+            Some(ConfigError::EmptyFile)
+        ));
+        assert_eq!(err.to_string(), "The config file is empty!");
+    }
+
+    #[test]
+    fn test_unknown_sched_name() {
+        let config_str = r#"
+[scheds.scx_bpflandd]
+gaming_mode = ["-k"]
+"#;
+
+        let err = parse_config_content(config_str, ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("scx_bpflandd"));
+
+        let err = parse_config_content("[scheds.bpfland]\n", ConfigFormat::Toml).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Scheduler bpfland in [scheds] must be named scx_bpfland"
+        );
+
+        let config =
+            parse_config_content("default_sched = \"lavd\"\n", ConfigFormat::Toml).unwrap();
+        assert_eq!(config.default_sched, Some(SupportedSched::Lavd));
+    }
+
+    #[test]
+    fn test_write_config_roundtrip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let config_path = tmp.path().join("config.toml");
+        let config_path = config_path.to_str().unwrap();
+
+        write_config(&get_default_config(), config_path).expect("Failed to write config");
+        assert!(!Path::new(&format!("{config_path}.tmp")).exists());
+
+        let parsed_config = parse_config_file(config_path).expect("Failed to parse config");
+        assert_eq!(parsed_config, get_default_config());
+    }
+
+    #[test]
+    fn test_json_config() {
+        let toml_config = r#"
+default_sched = "scx_lavd"
+default_mode = "Gaming"
+
+[scheds.scx_lavd]
+gaming_mode = ["--performance"]
+powersave_mode = ["--powersave"]
+"#;
+        let json_config = r#"
+{
+    "default_sched": "scx_lavd",
+    "default_mode": "Gaming",
+    "scheds": {
+        "scx_lavd": {
+            "gaming_mode": ["--performance"],
+            "powersave_mode": ["--powersave"]
+        }
+    }
+}
+"#;
+
+        assert_eq!(
+            parse_config_content(json_config, ConfigFormat::Json).unwrap(),
+            parse_config_content(toml_config, ConfigFormat::Toml).unwrap()
+        );
+        assert_eq!(
+            ConfigFormat::from_path("/etc/scx_loader.json"),
+            ConfigFormat::Json
+        );
+        assert_eq!(
+            ConfigFormat::from_path("/etc/scx_loader.toml"),
+            ConfigFormat::Toml
+        );
+        assert_eq!(
+            ConfigFormat::from_path("/etc/scx_loader.conf"),
+            ConfigFormat::Toml
+        );
+    }
+
+    #[test]
+    fn test_write_json_config_roundtrip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let config_path = tmp.path().join("config.json");
+        let config_path = config_path.to_str().unwrap();
+
+        write_config(&get_default_config(), config_path).expect("Failed to write config");
+        assert!(fs::read_to_string(config_path).unwrap().starts_with('{'));
+
+        let parsed_config = parse_config_file(config_path).expect("Failed to parse config");
+        assert_eq!(parsed_config, get_default_config());
+    }
+
+    #[test]
+    fn test_merge_configs() {
+        let overlay = parse_config_content(
+            r#"
+default_sched = "scx_lavd"
+
+[scheds.scx_lavd]
+gaming_mode = ["--performance", "--no-core-compaction"]
+"#,
+            ConfigFormat::Toml,
+        )
+        .unwrap();
+
+        let merged = merge_configs(get_default_config(), overlay);
+        assert_eq!(merged.default_sched, Some(SupportedSched::Lavd));
+        assert_eq!(merged.default_mode, Some(SchedMode::Auto));
+
+        let lavd = &merged.scheds["scx_lavd"];
+        assert_eq!(
+            lavd.gaming_mode,
+            Some(vec![
+                "--performance".to_owned(),
+                "--no-core-compaction".to_owned()
+            ])
+        );
+        // other modes are kept intact from the base
+        assert_eq!(lavd.auto_mode, Some(vec![]));
+        assert_eq!(lavd.lowlatency_mode, Some(vec!["--performance".to_owned()]));
+        assert_eq!(lavd.powersave_mode, Some(vec!["--powersave".to_owned()]));
+        assert_eq!(
+            merged.scheds["scx_bpfland"],
+            get_default_config().scheds["scx_bpfland"]
+        );
+    }
+
+    #[test]
+    fn test_merge_config_dropins() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dropin_dir = tmp.path();
+        fs::write(
+            dropin_dir.join("10-mode.toml"),
+            "default_mode = \"Gaming\"\n",
+        )
+        .unwrap();
+        fs::write(dropin_dir.join("20-empty.toml"), "").unwrap();
+        fs::write(
+            dropin_dir.join("30-mode.toml"),
+            "default_mode = \"PowerSave\"\n",
+        )
+        .unwrap();
+        fs::write(dropin_dir.join("README"), "not a config").unwrap();
+
+        let (config, merged_paths) =
+            merge_config_dropins(get_default_config(), dropin_dir).unwrap();
+        assert_eq!(config.default_mode, Some(SchedMode::PowerSave));
+        // empty drop-ins aren't merged
+        assert_eq!(
+            merged_paths,
+            vec![
+                dropin_dir.join("10-mode.toml"),
+                dropin_dir.join("30-mode.toml")
+            ]
+        );
+
+        // missing drop-in directory leaves the base config untouched
+        let (config, merged_paths) =
+            merge_config_dropins(get_default_config(), &dropin_dir.join("missing")).unwrap();
+        assert_eq!(config, get_default_config());
+        assert!(merged_paths.is_empty());
+    }
+
+    #[test]
+    fn test_config_search_paths_order() {
+        assert_eq!(
+            config_search_paths(Some("/xdg"), Some("/home/user")),
+            vec![
+                "/xdg/scx_loader/config.toml",
+                "/etc/scx_loader/config.toml",
+                "/etc/scx_loader.toml",
+            ]
+        );
+        assert_eq!(
+            config_search_paths(None, Some("/home/user"))[0],
+            "/home/user/.config/scx_loader/config.toml"
+        );
+        // empty XDG_CONFIG_HOME falls back to HOME
+        assert_eq!(
+            config_search_paths(Some(""), Some("/home/user"))[0],
+            "/home/user/.config/scx_loader/config.toml"
+        );
+        assert_eq!(config_search_paths(None, None), SYSTEM_CONFIG_PATHS);
+    }
+
+    #[test]
+    fn test_config_loader_search_paths() {
+        let tmp = tempfile::tempdir().unwrap();
+        let first_path = tmp.path().join("first.toml");
+        let second_path = tmp.path().join("second.toml");
+        let loader = ConfigLoader::new()
+            .env_override(false)
+            .xdg_lookup(false)
+            .dropin_dir(None::<PathBuf>)
+            .search_paths([first_path.to_str().unwrap(), second_path.to_str().unwrap()]);
+        assert_eq!(
+            loader.candidate_paths(),
+            vec![first_path.to_str().unwrap(), second_path.to_str().unwrap()]
+        );
+
+        // nothing exists, the default config is used
+        assert_eq!(loader.resolve_path(), None);
+        assert_eq!(loader.load().unwrap(), get_default_config());
+
+        fs::write(&second_path, "default_sched = \"scx_lavd\"\n").unwrap();
+        assert_eq!(loader.resolve_path().as_deref(), second_path.to_str());
+        assert_eq!(
+            loader.load().unwrap().default_sched,
+            Some(SupportedSched::Lavd)
+        );
+
+        // the first existing path wins
+        fs::write(&first_path, "default_sched = \"scx_flash\"\n").unwrap();
+        assert_eq!(loader.resolve_path().as_deref(), first_path.to_str());
+        assert_eq!(
+            loader.load().unwrap().default_sched,
+            Some(SupportedSched::Flash)
+        );
+
+        // an explicit config file takes precedence and must exist
+        let missing_path = tmp.path().join("missing.toml");
+        let loader = loader.config_file(missing_path.to_str().unwrap());
+        assert_eq!(loader.resolve_path().as_deref(), missing_path.to_str());
+        assert!(loader.load().is_err());
+    }
+
+    #[test]
+    fn test_config_loader_sources() {
+        let tmp = tempfile::tempdir().unwrap();
+        let user_path = tmp.path().join("user.toml");
+        let system_path = tmp.path().join("system.toml");
+        let dropin_dir = tmp.path().join("conf.d");
+        let loader = ConfigLoader::new()
+            .env_override(false)
+            .xdg_lookup(false)
+            .dropin_dir(Some(&dropin_dir))
+            .search_paths([user_path.to_str().unwrap(), system_path.to_str().unwrap()]);
+
+        let (config, sources) = loader.load_with_sources().unwrap();
+        assert_eq!(config, get_default_config());
+        assert_eq!(sources, vec![ConfigSource::BuiltIn]);
+
+        fs::write(&system_path, "default_sched = \"scx_lavd\"\n").unwrap();
+        fs::create_dir_all(&dropin_dir).unwrap();
+        fs::write(
+            dropin_dir.join("10-mode.toml"),
+            "default_mode = \"Gaming\"\n",
+        )
+        .unwrap();
+        let (config, sources) = loader.load_with_sources().unwrap();
+        assert_eq!(config.default_mode, Some(SchedMode::Gaming));
+        assert_eq!(
+            sources,
+            vec![
+                ConfigSource::File(system_path.to_str().unwrap().to_owned()),
+                ConfigSource::DropIn(dropin_dir.join("10-mode.toml")),
+            ]
+        );
+
+        // the user config shadows the system-wide one, drop-ins still apply
+        fs::write(&user_path, "default_sched = \"scx_flash\"\n").unwrap();
+        let (_, sources) = loader.load_with_sources().unwrap();
+        assert_eq!(
+            sources[0],
+            ConfigSource::File(user_path.to_str().unwrap().to_owned())
+        );
+        assert_eq!(sources.len(), 2);
+
+        // the env override is used on its own
+        let loader = ConfigLoader {
+            env_config: Some(system_path.to_str().unwrap().to_owned()),
+            ..loader
+        };
+        let (config, sources) = loader.load_with_sources().unwrap();
+        assert_eq!(config.default_sched, Some(SupportedSched::Lavd));
+        assert_eq!(
+            sources,
+            vec![ConfigSource::EnvOverride(
+                system_path.to_str().unwrap().to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_config_loader_layered() {
+        let tmp = tempfile::tempdir().unwrap();
+        let user_path = tmp.path().join("user.toml");
+        let system_path = tmp.path().join("system.toml");
+        let env_path = tmp.path().join("env.toml");
+        let dropin_dir = tmp.path().join("conf.d");
+        let loader = ConfigLoader {
+            user_config: Some(user_path.to_str().unwrap().to_owned()),
+            env_config: None,
+            ..ConfigLoader::new()
+                .dropin_dir(Some(&dropin_dir))
+                .search_paths([system_path.to_str().unwrap()])
+        };
+
+        let (config, sources) = loader.load_layered().unwrap();
+        assert_eq!(config, get_default_config());
+        assert_eq!(sources, vec![ConfigSource::BuiltIn]);
+
+        fs::write(
+            &system_path,
+            "default_sched = \"scx_lavd\"\ndefault_mode = \"Gaming\"\n\n\
+             [scheds.scx_mine]\npath = \"/opt/scx_mine\"\n",
+        )
+        .unwrap();
+        fs::create_dir_all(&dropin_dir).unwrap();
+        fs::write(dropin_dir.join("10-restart.toml"), "auto_restart = true\n").unwrap();
+        // the user config overlays the system-wide one and may refer to its custom scheduler
+        fs::write(
+            &user_path,
+            "default_sched = \"scx_mine\"\nauto_restart = false\n",
+        )
+        .unwrap();
+        let (config, sources) = loader.load_layered().unwrap();
+        assert_eq!(
+            sources,
+            vec![
+                ConfigSource::BuiltIn,
+                ConfigSource::File(system_path.to_str().unwrap().to_owned()),
+                ConfigSource::DropIn(dropin_dir.join("10-restart.toml")),
+                ConfigSource::File(user_path.to_str().unwrap().to_owned()),
+            ]
+        );
+        assert_eq!(
+            config.default_sched,
+            Some(SupportedSched::from("scx_mine".to_owned()))
+        );
+        assert_eq!(config.auto_restart, Some(false));
+        assert_eq!(config.default_mode, Some(SchedMode::Gaming));
+        // the built-in flags are the base layer
+        assert_eq!(
+            config.scheds["scx_lavd"],
+            get_default_config().scheds["scx_lavd"]
+        );
+
+        // the env override is the last layer
+        fs::write(&env_path, "default_mode = \"PowerSave\"\n").unwrap();
+        let loader = ConfigLoader {
+            env_config: Some(env_path.to_str().unwrap().to_owned()),
+            ..loader
+        };
+        let (config, sources) = loader.load_layered().unwrap();
+        assert_eq!(config.default_mode, Some(SchedMode::PowerSave));
+        assert_eq!(
+            config.default_sched,
+            Some(SupportedSched::from("scx_mine".to_owned()))
+        );
+        assert_eq!(
+            sources.last(),
+            Some(&ConfigSource::EnvOverride(
+                env_path.to_str().unwrap().to_owned()
+            ))
+        );
+        assert_eq!(sources.len(), 5);
+    }
+
+    #[test]
+    fn test_empty_path_rejected() {
+        let config =
+            parse_config_content("[scheds.scx_mine]\npath = \"  \"\n", ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            config.validation_report().errors,
+            vec!["scx_mine path: must not be empty"]
+        );
+    }
+
+    #[test]
+    fn test_fallback_cycle_rejected() {
+        let mut config = parse_config_content(
+            r#"
+default_sched = "scx_bpfland"
+fallback_scheds = ["scx_rusty", "scx_bpfland"]
+"#,
+            ConfigFormat::Toml,
+        )
+        .unwrap();
+        assert_eq!(
+            config.find_fallback_cycle(),
+            Some(vec!["scx_bpfland", "scx_rusty", "scx_bpfland"])
+        );
+        assert!(config.validation_report().errors.contains(
+            &"fallback_scheds: scx_bpfland is tried more than once (scx_bpfland -> scx_rusty -> scx_bpfland)"
+                .to_owned()
+        ));
+        assert!(config.validate().is_err());
+
+        config.fallback_scheds = vec![SupportedSched::Rusty, SupportedSched::Lavd];
+        assert_eq!(config.find_fallback_cycle(), None);
+    }
+
+    #[test]
+    fn test_find_config_path() {
+        let tmp = tempfile::tempdir().unwrap();
+        let user_path = tmp.path().join("xdg/scx_loader/config.toml");
+        let system_path = tmp.path().join("etc/scx_loader/config.toml");
+        let check_paths = vec![
+            user_path.to_str().unwrap().to_owned(),
+            system_path.to_str().unwrap().to_owned(),
+        ];
+
+        // neither exists, parent directories are missing as well
+        assert!(find_config_path(&check_paths).is_err());
+
+        // only system path exists
+        let system_path = touch_config(&system_path);
+        assert_eq!(find_config_path(&check_paths).unwrap(), system_path);
+
+        // both exist, user config wins
+        let user_path = touch_config(&user_path);
+        assert_eq!(find_config_path(&check_paths).unwrap(), user_path);
+
+        // only user path exists
+        fs::remove_file(&system_path).unwrap();
+        assert_eq!(find_config_path(&check_paths).unwrap(), user_path);
+    }
+}
@@ -0,0 +1,127 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use anyhow::Context;
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::config::Config;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+impl Config {
+    /// Get a hash of the config which is equal for equal configs, independent of the order of
+    /// the maps and stable across restarts, so clients can cheaply detect config changes
+    pub fn config_hash(&self) -> Result<String> {
+        let value = serde_json::to_value(self).context("Failed to serialize config")?;
+        let mut canonical = String::new();
+        write_canonical(&value, &mut canonical);
+        Ok(format!("{:016x}", fnv1a(canonical.as_bytes())))
+    }
+}
+
+/// Write the value as JSON with the keys of every object sorted
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_unstable_by_key(|(key, _)| *key);
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(value, out);
+            }
+            out.push('}');
+        }
+        Value::Array(values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(value, out);
+            }
+            out.push(']');
+        }
+        _ => out.push_str(&value.to_string()),
+    }
+}
+
+/// 64-bit FNV-1a, unlike `DefaultHasher` its output is guaranteed to never change
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::get_default_config;
+    use crate::config::parse_config;
+    use crate::config_hash::*;
+
+    #[test]
+    fn test_equal_configs_hash_equally() {
+        let config = parse_config(
+            r#"
+default_sched = "scx_lavd"
+
+[scheds.scx_lavd]
+gaming_mode = ["--performance"]
+
+[scheds.scx_bpfland]
+auto_mode = []
+
+[scheds.scx_bpfland.env]
+A = "1"
+B = "2"
+"#,
+        );
+        let reordered = parse_config(
+            r#"
+default_sched = "scx_lavd"
+
+[scheds.scx_bpfland]
+auto_mode = []
+
+[scheds.scx_bpfland.env]
+B = "2"
+A = "1"
+
+[scheds.scx_lavd]
+gaming_mode = "--performance"
+"#,
+        );
+        let hash = config.config_hash().unwrap();
+        assert_eq!(hash.len(), 16);
+        assert_eq!(hash, reordered.config_hash().unwrap());
+        assert_eq!(hash, config.clone().config_hash().unwrap());
+        assert_eq!(
+            get_default_config().config_hash().unwrap(),
+            get_default_config().config_hash().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_changed_config_hashes_differently() {
+        let config = get_default_config();
+        let mut changed = config.clone();
+        changed.scheds.remove("scx_lavd");
+        assert_ne!(
+            config.config_hash().unwrap(),
+            changed.config_hash().unwrap()
+        );
+
+        assert_eq!(fnv1a(b""), FNV_OFFSET_BASIS);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+    }
+}
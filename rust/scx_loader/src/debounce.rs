@@ -0,0 +1,128 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::config::Config;
+
+/// Collapses the requests within a window into the last one: the first request opens the window,
+/// the ones following it within the window replace it and the last one is taken once the window
+/// is over
+#[derive(Debug)]
+pub struct Debouncer<T> {
+    window: Duration,
+    pending: Option<(T, Instant)>,
+}
+
+impl<T> Debouncer<T> {
+    /// Create the debouncer, a zero window disables it
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: None,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.window.is_zero()
+    }
+
+    /// Change the window, a pending request is still taken at the end of its window
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+
+    /// Queue the request, returning the pending request it replaced
+    pub fn push(&mut self, request: T, now: Instant) -> Option<T> {
+        match self.pending.take() {
+            Some((replaced, deadline)) => {
+                self.pending = Some((request, deadline));
+                Some(replaced)
+            }
+            None => {
+                self.pending = Some((request, now + self.window));
+                None
+            }
+        }
+    }
+
+    /// Get the end of the window of the pending request, None if no request is pending
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending.as_ref().map(|(_, deadline)| *deadline)
+    }
+
+    /// Take the pending request if its window is over
+    pub fn poll(&mut self, now: Instant) -> Option<T> {
+        if self.deadline()? > now {
+            return None;
+        }
+        self.pending.take().map(|(request, _)| request)
+    }
+
+    /// Drop the pending request, e.g. because a request which can't be coalesced with it
+    /// superseded it
+    pub fn cancel(&mut self) -> Option<T> {
+        self.pending.take().map(|(request, _)| request)
+    }
+}
+
+impl Config {
+    /// Get the window within which the switch requests are coalesced, zero if disabled
+    pub fn get_switch_debounce(&self) -> Duration {
+        Duration::from_millis(self.switch_debounce_ms.unwrap_or(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debounce::*;
+
+    const WINDOW: Duration = Duration::from_millis(500);
+
+    #[test]
+    fn test_burst_applies_last_request() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(WINDOW);
+        assert!(debouncer.is_enabled());
+
+        assert_eq!(debouncer.push("gaming", start), None);
+        assert_eq!(
+            debouncer.push("powersave", start + Duration::from_millis(100)),
+            Some("gaming")
+        );
+        assert_eq!(
+            debouncer.push("lowlatency", start + Duration::from_millis(300)),
+            Some("powersave")
+        );
+        // the window started with the first request
+        assert_eq!(debouncer.deadline(), Some(start + WINDOW));
+        assert_eq!(debouncer.poll(start + Duration::from_millis(400)), None);
+
+        assert_eq!(debouncer.poll(start + WINDOW), Some("lowlatency"));
+        assert_eq!(debouncer.poll(start + WINDOW), None);
+        assert_eq!(debouncer.deadline(), None);
+
+        // the next request opens a new window
+        let later = start + Duration::from_secs(2);
+        assert_eq!(debouncer.push("auto", later), None);
+        assert_eq!(debouncer.deadline(), Some(later + WINDOW));
+        assert_eq!(debouncer.cancel(), Some("auto"));
+        assert_eq!(debouncer.poll(later + WINDOW), None);
+    }
+
+    #[test]
+    fn test_switch_debounce_config() {
+        assert!(!Debouncer::<()>::new(Config::default().get_switch_debounce()).is_enabled());
+
+        let config = Config {
+            switch_debounce_ms: Some(250),
+            ..Default::default()
+        };
+        assert_eq!(config.get_switch_debounce(), Duration::from_millis(250));
+    }
+}
@@ -0,0 +1,290 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use crate::config;
+use crate::config::Config;
+use crate::config::Sched;
+use crate::get_name_from_scx;
+use crate::history::DEFAULT_HISTORY_SIZE;
+use crate::output::DEFAULT_LOG_OUTPUT_MAX_SIZE;
+use crate::restart::DEFAULT_RESTART_LIMIT;
+use crate::SchedMode;
+use crate::SupportedSched;
+use crate::SUPPORTED_SCHEDS;
+
+/// Changes between two configs, the scheduler entries are sorted by name
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ConfigDiff {
+    /// [scheds] entries only in the new config
+    pub added_scheds: Vec<String>,
+    /// [scheds] entries only in the old config
+    pub removed_scheds: Vec<String>,
+    /// [scheds] entries in both configs with different settings
+    pub modified_scheds: Vec<String>,
+    pub default_sched_changed: bool,
+    pub default_mode_changed: bool,
+    /// Any other setting changed, e.g. global_flags or the schedule
+    pub other_changed: bool,
+    /// A global setting the schedulers are launched with changed, e.g. global_flags or
+    /// oom_score_adj. Implies other_changed
+    pub launch_changed: bool,
+}
+
+impl ConfigDiff {
+    /// Check whether the configs are the same
+    pub fn is_empty(&self) -> bool {
+        *self == ConfigDiff::default()
+    }
+
+    /// Check whether the settings of the given scheduler changed, so it has to be restarted to
+    /// apply them
+    pub fn affects_sched(&self, sched_name: &str) -> bool {
+        let contains = |sched_names: &[String]| sched_names.iter().any(|name| name == sched_name);
+        contains(&self.added_scheds)
+            || contains(&self.removed_scheds)
+            || contains(&self.modified_scheds)
+    }
+}
+
+impl Config {
+    /// Get what changed from this config to the other one
+    pub fn diff(&self, other: &Config) -> ConfigDiff {
+        let mut diff = ConfigDiff::default();
+        for sched_name in self.scheduler_names() {
+            match other.scheds.get(&sched_name) {
+                Some(other_sched) if *other_sched != self.scheds[&sched_name] => {
+                    diff.modified_scheds.push(sched_name)
+                }
+                Some(_) => {}
+                None => diff.removed_scheds.push(sched_name),
+            }
+        }
+        diff.added_scheds = other
+            .scheduler_names()
+            .into_iter()
+            .filter(|sched_name| !self.has_scheduler(sched_name))
+            .collect();
+        diff.default_sched_changed = self.default_sched != other.default_sched;
+        diff.default_mode_changed = self.default_mode != other.default_mode;
+
+        // compare the remaining settings by blanking out the ones compared above
+        let strip = |config: &Config| Config {
+            default_sched: None,
+            default_mode: None,
+            scheds: Default::default(),
+            ..config.clone()
+        };
+        diff.other_changed = strip(self) != strip(other);
+        diff.launch_changed = launch_settings(self) != launch_settings(other);
+        diff
+    }
+
+    /// Check whether the config behaves like the built-in default config. Modes left out fall
+    /// back to their built-in flags and a missing default_mode to Auto, so they count as default
+    pub fn is_default(&self) -> bool {
+        self.with_fallbacks() == config::get_default_config().with_fallbacks()
+    }
+
+    /// Get the config with the fallbacks of the omitted settings filled in
+    fn with_fallbacks(&self) -> Config {
+        let mut config = self.clone();
+        config.default_mode.get_or_insert(SchedMode::Auto);
+        for scx_sched in SUPPORTED_SCHEDS {
+            let sched_config = config
+                .scheds
+                .entry(get_name_from_scx(scx_sched).to_owned())
+                .or_default();
+            config::fill_default_flags(scx_sched, sched_config);
+        }
+        config
+    }
+}
+
+/// Get the global settings which change how the running scheduler is launched, so it has to be
+/// restarted to apply them
+fn launch_settings(config: &Config) -> impl PartialEq + '_ {
+    (
+        (&config.global_flags, &config.bin_dirs, &config.hooks),
+        (&config.process_priority, config.oom_score_adj),
+        (&config.log_output, config.log_output_max_size),
+        (&config.cgroup, config.cgroup_required),
+        (config.strict_flags, &config.sysfs_path, &config.mode_scheds),
+        // decides whether the output is piped through the loader
+        config.stop_behavior,
+    )
+}
+
+/// Get the smallest config which behaves the same, i.e. without the settings equal to their
+/// defaults, so only the deviations from the defaults are saved
+pub fn minimize_config(config: &Config) -> Config {
+    let mut minimized = config.clone();
+    // unset the settings whose value is the one used when they're unset
+    fn strip<T: PartialEq>(setting: &mut Option<T>, default: T) {
+        if setting.as_ref() == Some(&default) {
+            *setting = None;
+        }
+    }
+    strip(&mut minimized.default_mode, SchedMode::Auto);
+    strip(&mut minimized.auto_restart, false);
+    strip(&mut minimized.restart_limit, DEFAULT_RESTART_LIMIT);
+    strip(&mut minimized.watch_config, false);
+    strip(&mut minimized.history_size, DEFAULT_HISTORY_SIZE);
+    strip(&mut minimized.log_history, false);
+    strip(&mut minimized.strict_flags, false);
+    strip(&mut minimized.switch_handoff, false);
+    strip(&mut minimized.cgroup_required, false);
+    strip(
+        &mut minimized.log_output_max_size,
+        DEFAULT_LOG_OUTPUT_MAX_SIZE,
+    );
+
+    minimized.scheds.retain(|sched_name, sched_config| {
+        let scx_sched = SupportedSched::from(sched_name.clone());
+        config::strip_default_flags(&scx_sched, sched_config);
+        strip(
+            &mut sched_config.enabled,
+            config::enabled_by_default(&scx_sched),
+        );
+        strip(&mut sched_config.restart_on_change, true);
+        *sched_config != Sched::default()
+    });
+    minimized
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::parse_config;
+    use crate::diff::*;
+
+    const BASE_CONFIG: &str = r#"
+default_mode = "Auto"
+
+[scheds.scx_bpfland]
+gaming_mode = ["-k"]
+
+[scheds.scx_lavd]
+gaming_mode = ["--performance"]
+"#;
+
+    #[test]
+    fn test_diff_no_changes() {
+        let config = parse_config(BASE_CONFIG);
+        let diff = config.diff(&config.clone());
+        assert!(diff.is_empty());
+        assert!(!diff.affects_sched("scx_bpfland"));
+    }
+
+    #[test]
+    fn test_diff_scheds() {
+        let old_config = parse_config(BASE_CONFIG);
+        let new_config = parse_config(
+            r#"
+default_mode = "Auto"
+
+[scheds.scx_bpfland]
+gaming_mode = ["-k", "-m", "performance"]
+
+[scheds.scx_rusty]
+gaming_mode = []
+"#,
+        );
+
+        let diff = old_config.diff(&new_config);
+        assert_eq!(diff.added_scheds, vec!["scx_rusty"]);
+        assert_eq!(diff.removed_scheds, vec!["scx_lavd"]);
+        assert_eq!(diff.modified_scheds, vec!["scx_bpfland"]);
+        assert!(!diff.default_sched_changed);
+        assert!(!diff.default_mode_changed);
+        assert!(!diff.other_changed);
+        assert!(diff.affects_sched("scx_bpfland"));
+        assert!(diff.affects_sched("scx_lavd"));
+        assert!(!diff.affects_sched("scx_flash"));
+
+        let diff = new_config.diff(&old_config);
+        assert_eq!(diff.added_scheds, vec!["scx_lavd"]);
+        assert_eq!(diff.removed_scheds, vec!["scx_rusty"]);
+    }
+
+    #[test]
+    fn test_is_default() {
+        assert!(config::get_default_config().is_default());
+
+        let mut config = config::get_default_config();
+        config.scheds.get_mut("scx_lavd").unwrap().gaming_mode = Some(vec![]);
+        assert!(!config.is_default());
+        let config = Config {
+            auto_restart: Some(true),
+            ..config::get_default_config()
+        };
+        assert!(!config.is_default());
+        assert!(!parse_config("default_mode = \"Gaming\"\n").is_default());
+
+        // the omitted modes and scheduler entries fall back to the defaults
+        assert!(Config::default().is_default());
+        assert!(parse_config("default_mode = \"Auto\"\n[scheds.scx_lavd]\n").is_default());
+        assert!(
+            parse_config("[scheds.scx_lavd]\ngaming_mode = [\"--performance\"]\n").is_default()
+        );
+    }
+
+    #[test]
+    fn test_diff_defaults() {
+        let old_config = parse_config(BASE_CONFIG);
+        let new_config = Config {
+            default_sched: Some(SupportedSched::Lavd),
+            default_mode: Some(SchedMode::Gaming),
+            global_flags: vec!["-v".to_owned()],
+            ..old_config.clone()
+        };
+
+        let diff = old_config.diff(&new_config);
+        assert!(diff.default_sched_changed);
+        assert!(diff.default_mode_changed);
+        assert!(diff.other_changed);
+        assert!(diff.launch_changed);
+        assert!(diff.modified_scheds.is_empty());
+
+        // the schedule only decides when to switch
+        let new_config = Config {
+            watch_config: Some(true),
+            ..old_config.clone()
+        };
+        let diff = old_config.diff(&new_config);
+        assert!(diff.other_changed);
+        assert!(!diff.launch_changed);
+        assert!(!diff.is_empty());
+    }
+
+    #[test]
+    fn test_minimize_default_config() {
+        let config = config::get_default_config();
+        let minimized = minimize_config(&config);
+        assert_eq!(minimized, Config::default());
+        assert_eq!(config::serialize_config(&minimized).unwrap(), "[scheds]\n");
+        assert!(minimized.is_default());
+    }
+
+    #[test]
+    fn test_minimize_config_with_override() {
+        let mut config = config::get_default_config();
+        config.auto_restart = Some(true);
+        let lavd_config = config.scheds.get_mut("scx_lavd").unwrap();
+        lavd_config.gaming_mode = Some(vec!["--performance".to_owned(), "-v".to_owned()]);
+        lavd_config.enabled = Some(true);
+
+        let minimized = minimize_config(&config);
+        assert_eq!(
+            config::serialize_config(&minimized).unwrap(),
+            "auto_restart = true\n\n[scheds.scx_lavd]\ngaming_mode = [\n    \"--performance\",\n    \"-v\",\n]\n"
+        );
+        // the minimized config resolves to the same flags
+        assert_eq!(
+            config::enumerate_all_flags(&minimized),
+            config::enumerate_all_flags(&config)
+        );
+    }
+}
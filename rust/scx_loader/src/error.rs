@@ -0,0 +1,100 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Failure to read or parse a config file, so callers can react to e.g. a missing file
+/// differently than to an invalid one. Converts into anyhow::Error like any other error
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file doesn't exist
+    FileNotFound(String),
+    /// The config file exists but couldn't be locked or read
+    Read { path: String, source: anyhow::Error },
+    /// The config file has no content
+    EmptyFile,
+    /// The content isn't valid TOML or JSON or doesn't match the structure of the config
+    ParseError(Box<dyn Error + Send + Sync>),
+    /// [scheds] entries which are neither supported nor custom schedulers
+    UnknownScheduler(Vec<String>),
+    /// A supported scheduler in [scheds] under another than its full name, e.g. "lavd"
+    MisnamedScheduler {
+        sched_name: String,
+        scx_name: String,
+    },
+    /// A custom scheduler named like a supported scheduler
+    CustomSchedulerCollision(String),
+    /// A field like default_sched naming a scheduler which is neither supported nor custom
+    UnknownSchedulerReference {
+        field: &'static str,
+        sched_name: String,
+    },
+    /// Fields the config doesn't know, only rejected when parsing strictly
+    UnknownFields(Vec<String>),
+}
+
+impl ConfigError {
+    /// Error of reading the config file at the given path
+    pub fn read(path: &str, source: io::Error) -> Self {
+        if source.kind() == io::ErrorKind::NotFound {
+            return Self::FileNotFound(path.to_owned());
+        }
+        Self::Read {
+            path: path.to_owned(),
+            source: source.into(),
+        }
+    }
+
+    pub fn parse(source: impl Error + Send + Sync + 'static) -> Self {
+        Self::ParseError(Box::new(source))
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::FileNotFound(path) => write!(f, "Config file {path} doesn't exist"),
+            Self::Read { path, .. } => write!(f, "Failed to read config file {path}"),
+            Self::EmptyFile => write!(f, "The config file is empty!"),
+            Self::ParseError(source) => write!(f, "{source}"),
+            Self::UnknownScheduler(sched_names) => write!(
+                f,
+                "Unknown scheduler(s) in [scheds]: {} (custom schedulers must set path)",
+                sched_names.join(", ")
+            ),
+            Self::MisnamedScheduler {
+                sched_name,
+                scx_name,
+            } => write!(
+                f,
+                "Scheduler {sched_name} in [scheds] must be named {scx_name}"
+            ),
+            Self::CustomSchedulerCollision(sched_name) => write!(
+                f,
+                "Custom scheduler {sched_name} collides with a supported scheduler"
+            ),
+            Self::UnknownSchedulerReference { field, sched_name } => write!(
+                f,
+                "{field} {sched_name} is neither a supported nor a custom scheduler"
+            ),
+            Self::UnknownFields(fields) => {
+                write!(f, "Unknown fields in config: {}", fields.join(", "))
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Read { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,751 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::process::Command;
+use std::process::Stdio;
+
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Deserializer;
+
+use crate::config;
+use crate::config::Config;
+use crate::SupportedSched;
+use crate::SCHED_MODES;
+
+/// Result of checking the configured flags against the ones advertised by the scheduler
+#[derive(Debug, PartialEq)]
+pub enum FlagCheck {
+    /// Every configured option is advertised by the scheduler
+    Valid,
+    /// Configured options which the scheduler doesn't advertise in its --help
+    UnknownFlags(Vec<String>),
+    /// The scheduler binary isn't installed, so the flags couldn't be checked
+    BinaryNotFound,
+}
+
+/// Check whether the flag is an option rather than a value, negative numbers are values
+fn is_option(flag: &str) -> bool {
+    flag.starts_with('-') && !flag[1..].starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Group the flags into options with their value, e.g. ["-c", "0", "-k"] becomes
+/// [["-c", "0"], ["-k"]]. Values which don't follow an option form a group on their own
+pub fn group_flags(flags: &[String]) -> Vec<&[String]> {
+    let mut groups = vec![];
+    let mut start = 0;
+    while start < flags.len() {
+        let flag = &flags[start];
+        let mut end = start + 1;
+        if is_option(flag) && !flag.contains('=') && flags.get(end).is_some_and(|f| !is_option(f)) {
+            end += 1;
+        }
+        groups.push(&flags[start..end]);
+        start = end;
+    }
+    groups
+}
+
+/// Split the flags into options with their optional value, e.g. ["-m", "performance"]
+/// becomes ("-m", Some("performance"))
+pub fn split_options(flags: &[String]) -> Vec<(&str, Option<&str>)> {
+    group_flags(flags)
+        .into_iter()
+        .filter(|group| is_option(&group[0]))
+        .map(|group| match group[0].split_once('=') {
+            Some((option, value)) => (option, Some(value)),
+            None => (group[0].as_str(), group.get(1).map(String::as_str)),
+        })
+        .collect()
+}
+
+/// Append the extra flags to the base flags.
+///
+/// Options already present with the same value are skipped, so the base flags stay first and
+/// the order of the remaining flags is preserved.
+pub fn merge_flags(base: &[String], extra: &[String]) -> Vec<String> {
+    let mut merged = base.to_vec();
+    for group in group_flags(extra) {
+        if !group_flags(&merged).contains(&group) {
+            merged.extend_from_slice(group);
+        }
+    }
+    merged
+}
+
+/// Check the flags of every mode of the given scheduler against the options listed by its --help.
+///
+/// This executes the scheduler binary, so it's never done as part of the config parsing.
+pub fn validate_flags_against_binary(
+    config: &Config,
+    scx_sched: &SupportedSched,
+) -> Result<FlagCheck> {
+    let sched_bin = config::get_scx_bin_path(config, scx_sched);
+    let Some(known_options) = read_help_options(&sched_bin)? else {
+        return Ok(FlagCheck::BinaryNotFound);
+    };
+
+    let mut scx_flags = vec![];
+    for sched_mode in SCHED_MODES {
+        scx_flags.extend(config::get_scx_flags_for_mode(
+            config,
+            scx_sched,
+            sched_mode.clone(),
+        ));
+    }
+
+    let unknown_flags = find_unknown_flags(&scx_flags, &known_options);
+    if unknown_flags.is_empty() {
+        Ok(FlagCheck::Valid)
+    } else {
+        Ok(FlagCheck::UnknownFlags(unknown_flags))
+    }
+}
+
+/// Get the options listed by the --help of the scheduler binary, None if it isn't installed
+fn read_help_options(sched_bin: &str) -> Result<Option<BTreeSet<String>>> {
+    let output = match Command::new(sched_bin)
+        .arg("--help")
+        .stdin(Stdio::null())
+        .output()
+    {
+        Ok(output) => output,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("Failed to run {sched_bin} --help")),
+    };
+
+    // some schedulers print the usage to stderr
+    let help_text = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Ok(Some(parse_help_options(&help_text)))
+}
+
+/// Reject the flags the scheduler is started with if strict_flags is set and some of them
+/// aren't known options, otherwise all flags are passed through verbatim
+pub fn check_strict_flags(
+    strict_flags: bool,
+    sched_bin: &str,
+    scx_flags: &[String],
+    known_options: &BTreeSet<String>,
+) -> Result<()> {
+    if !strict_flags {
+        return Ok(());
+    }
+    let unknown_flags = find_unknown_flags(scx_flags, known_options);
+    if !unknown_flags.is_empty() {
+        anyhow::bail!(
+            "{sched_bin} doesn't support the flags {}, refusing to start it as strict_flags is set",
+            unknown_flags.join(" ")
+        );
+    }
+    Ok(())
+}
+
+/// Check the flags against the --help of the scheduler binary if strict_flags is set. A missing
+/// binary isn't reported here, starting it fails anyway
+pub fn enforce_strict_flags(
+    strict_flags: bool,
+    sched_bin: &str,
+    scx_flags: &[String],
+) -> Result<()> {
+    if !strict_flags {
+        return Ok(());
+    }
+    match read_help_options(sched_bin)? {
+        Some(known_options) => check_strict_flags(true, sched_bin, scx_flags, &known_options),
+        None => Ok(()),
+    }
+}
+
+/// Parse the options listed in the help output, e.g. "  -c, --cpu <CPU>  Set the CPU" lists
+/// "-c" and "--cpu"
+pub fn parse_help_options(help_text: &str) -> BTreeSet<String> {
+    let mut options = BTreeSet::new();
+    for line in help_text.lines() {
+        let line = line.trim_start();
+        if !line.starts_with('-') {
+            continue;
+        }
+        for token in line.split(|c: char| c.is_whitespace() || c == ',') {
+            if token.is_empty() {
+                continue;
+            }
+            // the option list ends with the first value placeholder or description word
+            if !token.starts_with('-') {
+                break;
+            }
+            let option = token.split(['=', '[', '<']).next().unwrap_or(token);
+            if is_option(option) && option.len() > 1 && option != "--" {
+                options.insert(option.to_owned());
+            }
+        }
+    }
+    options
+}
+
+/// Get the configured options which aren't part of the known options, without duplicates
+pub fn find_unknown_flags(scx_flags: &[String], known_options: &BTreeSet<String>) -> Vec<String> {
+    let mut unknown_flags: Vec<String> = vec![];
+    for (option, _) in split_options(scx_flags) {
+        if !known_options.contains(option) && !unknown_flags.iter().any(|flag| flag == option) {
+            unknown_flags.push(option.to_owned());
+        }
+    }
+    unknown_flags
+}
+
+/// Split the flags written as a single string into arguments like a shell does, e.g.
+/// `--foo bar --baz 'a b'`. Single quotes keep everything literally, double quotes only allow
+/// escaping `"` and `\`, outside of quotes a backslash escapes any character
+pub fn split_flags(flags: &str) -> Result<Vec<String>> {
+    let mut args = vec![];
+    // None between arguments, so empty quotes still make an argument
+    let mut current_arg: Option<String> = None;
+    let mut chars = flags.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                let arg = current_arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => arg.push(c),
+                        None => anyhow::bail!("Unbalanced single quote in flags {flags:?}"),
+                    }
+                }
+            }
+            '"' => {
+                let arg = current_arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => arg.push(c),
+                            Some(c) => {
+                                arg.push('\\');
+                                arg.push(c);
+                            }
+                            None => anyhow::bail!("Unbalanced double quote in flags {flags:?}"),
+                        },
+                        Some(c) => arg.push(c),
+                        None => anyhow::bail!("Unbalanced double quote in flags {flags:?}"),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => current_arg.get_or_insert_with(String::new).push(c),
+                None => anyhow::bail!("Trailing backslash in flags {flags:?}"),
+            },
+            c if c.is_whitespace() => args.extend(current_arg.take()),
+            c => current_arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(current_arg);
+    Ok(args)
+}
+
+/// Deserialize the flags of a mode, either written as a list or as a single string split by
+/// `split_flags`
+pub fn deserialize_flags<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum FlagsForm {
+        List(Vec<String>),
+        Line(String),
+    }
+
+    match Option::<FlagsForm>::deserialize(deserializer)? {
+        Some(FlagsForm::List(flags)) => Ok(Some(flags)),
+        Some(FlagsForm::Line(flags)) => split_flags(&flags)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+/// Read the flags from the file, separated by spaces or newlines. Lines starting with `#` are
+/// comments
+pub fn read_flags_file(path: &str) -> Result<Vec<String>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read flags file {path}"))?;
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .flat_map(str::split_whitespace)
+        .map(String::from)
+        .collect())
+}
+
+/// Expand environment variables in every flag, see `expand_env_vars`
+pub fn expand_flags(flags: &[String]) -> Vec<String> {
+    flags.iter().map(|flag| expand_env_vars(flag)).collect()
+}
+
+/// Substitute `${VAR}` and `$VAR` with the value from the process environment.
+///
+/// Unset variables and dollar signs which don't start a variable reference are kept as is,
+/// `$$` is replaced with a literal `$`.
+pub fn expand_env_vars(flag: &str) -> String {
+    expand_vars_with(flag, |var_name| env::var(var_name).ok())
+}
+
+/// Substitute the variable references like `expand_env_vars`, looking the values up with the
+/// given function
+pub fn expand_vars_with(flag: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let is_var_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    let mut expanded = String::with_capacity(flag.len());
+    let mut rest = flag;
+    while let Some(dollar_pos) = rest.find('$') {
+        expanded.push_str(&rest[..dollar_pos]);
+        let after_dollar = &rest[dollar_pos + 1..];
+
+        // escaped dollar sign
+        if let Some(after_escape) = after_dollar.strip_prefix('$') {
+            expanded.push('$');
+            rest = after_escape;
+            continue;
+        }
+
+        // ${VAR} or $VAR, otherwise a literal dollar sign
+        let (var_name, reference_len) = if let Some(braced) = after_dollar.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) if end > 0 && braced[..end].chars().all(is_var_char) => {
+                    (&braced[..end], end + 2)
+                }
+                _ => ("", 0),
+            }
+        } else {
+            let end = after_dollar
+                .find(|c: char| !is_var_char(c))
+                .unwrap_or(after_dollar.len());
+            (&after_dollar[..end], end)
+        };
+
+        let reference = &rest[dollar_pos..dollar_pos + 1 + reference_len];
+        match lookup(var_name).filter(|_| !var_name.is_empty()) {
+            Some(value) => expanded.push_str(&value),
+            None => expanded.push_str(reference),
+        }
+        rest = &rest[dollar_pos + 1 + reference_len..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Parse a duration like "5ms" into microseconds. Returns None if the value isn't a number followed by a unit, e.g. "5" or "0x3f".
+///
+/// Supported units are us, ms and s, any other unit is rejected.
+pub fn parse_duration_us(value: &str) -> Result<Option<u64>> {
+    let unit_pos = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(unit_pos);
+    if number.is_empty()
+        || unit.is_empty()
+        || !unit.chars().all(|c| c.is_ascii_alphabetic())
+        || (number == "0" && unit.starts_with(['x', 'X']))
+    {
+        return Ok(None);
+    }
+
+    let multiplier = match unit {
+        "us" => 1,
+        "ms" => 1_000,
+        "s" => 1_000_000,
+        _ => anyhow::bail!("Unknown duration unit {unit} in {value}, expected us, ms or s"),
+    };
+    let number: u64 = number
+        .parse()
+        .with_context(|| format!("Invalid duration {value}"))?;
+    number
+        .checked_mul(multiplier)
+        .map(Some)
+        .with_context(|| format!("Duration {value} is too large"))
+}
+
+/// Units the time-based options take, named by a segment of their long option name
+const OPTION_UNITS: &[(&str, u64)] = &[("us", 1), ("ms", 1_000)];
+
+/// Get the unit the option takes durations in with its length in microseconds, e.g. us for
+/// --slice-us-min or ms for --interval-ms. None if the option isn't known to take a duration
+fn option_duration_unit(option: &str) -> Option<(&'static str, u64)> {
+    let option_name = option.strip_prefix("--")?;
+    option_name.split('-').find_map(|segment| {
+        OPTION_UNITS
+            .iter()
+            .find(|(unit, _)| *unit == segment)
+            .copied()
+    })
+}
+
+/// Convert the duration values of the options which take durations into the unit of the
+/// option, see `parse_duration_us`. Handles both separate values and `--opt=value`, the values
+/// of other options are passed through as is
+pub fn convert_durations(flags: &[String]) -> Result<Vec<String>> {
+    let mut converted_flags = Vec::with_capacity(flags.len());
+    let mut pending_option: Option<&str> = None;
+    for flag in flags {
+        let (option, prefix, value) = match flag.split_once('=') {
+            Some((option, value)) if is_option(option) => {
+                (option, &flag[..option.len() + 1], value)
+            }
+            _ if is_option(flag) => {
+                pending_option = Some(flag);
+                converted_flags.push(flag.clone());
+                continue;
+            }
+            _ => match pending_option.take() {
+                Some(option) => (option, "", flag.as_str()),
+                None => {
+                    converted_flags.push(flag.clone());
+                    continue;
+                }
+            },
+        };
+        pending_option = None;
+        let Some((unit, unit_us)) = option_duration_unit(option) else {
+            converted_flags.push(flag.clone());
+            continue;
+        };
+        let converted =
+            match parse_duration_us(value).map_err(|err| anyhow::anyhow!("{option}: {err}"))? {
+                Some(duration_us) if duration_us % unit_us != 0 => {
+                    anyhow::bail!("{option}: {value} isn't a whole number of {unit}")
+                }
+                Some(duration_us) => format!("{prefix}{}", duration_us / unit_us),
+                None => flag.clone(),
+            };
+        converted_flags.push(converted);
+    }
+    Ok(converted_flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::flags::*;
+
+    fn lookup(var_name: &str) -> Option<String> {
+        match var_name {
+            "HOME" => Some("/home/user".to_owned()),
+            _ => None,
+        }
+    }
+
+    fn to_flags(flags: &[&str]) -> Vec<String> {
+        flags.iter().map(|flag| flag.to_string()).collect()
+    }
+
+    #[test]
+    fn test_split_options() {
+        let flags = to_flags(&["-c", "0", "-k", "--slice=5", "-m", "performance", "-v"]);
+        assert_eq!(
+            split_options(&flags),
+            vec![
+                ("-c", Some("0")),
+                ("-k", None),
+                ("--slice", Some("5")),
+                ("-m", Some("performance")),
+                ("-v", None),
+            ]
+        );
+        assert_eq!(
+            split_options(&to_flags(&["-o", "-1"])),
+            vec![("-o", Some("-1"))]
+        );
+    }
+
+    #[test]
+    fn test_merge_flags() {
+        let base = to_flags(&["-c", "0", "-k", "-m", "performance"]);
+        assert_eq!(
+            merge_flags(&base, &to_flags(&["--verbose", "-k", "-c", "1"])),
+            to_flags(&["-c", "0", "-k", "-m", "performance", "--verbose", "-c", "1"])
+        );
+        assert_eq!(
+            merge_flags(&base, &to_flags(&["--verbose", "--verbose"])),
+            to_flags(&["-c", "0", "-k", "-m", "performance", "--verbose"])
+        );
+    }
+
+    #[test]
+    fn test_parse_help_options() {
+        let help_text = r#"
+Usage: scx_bpfland [OPTIONS]
+
+Options:
+  -s, --slice-us <SLICE_US>  Maximum scheduling slice duration in microseconds [default: 5000]
+  -k, --local-kthreads       Enable per-CPU kthreads prioritization
+      --lowlatency           Enable low-latency mode
+  -m, --primary-domain=<MASK>
+  -h, --help                 Print help (see more with '--help')
+"#;
+
+        let known_options = parse_help_options(help_text);
+        assert_eq!(
+            known_options.into_iter().collect::<Vec<_>>(),
+            vec![
+                "--help",
+                "--local-kthreads",
+                "--lowlatency",
+                "--primary-domain",
+                "--slice-us",
+                "-h",
+                "-k",
+                "-m",
+                "-s",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_unknown_flags() {
+        let known_options = BTreeSet::from(["--lowlatency".to_owned(), "-m".to_owned()]);
+        let scx_flags = to_flags(&["--lowlatancy", "-m", "performance", "--lowlatancy"]);
+        assert_eq!(
+            find_unknown_flags(&scx_flags, &known_options),
+            vec!["--lowlatancy"]
+        );
+        assert!(find_unknown_flags(&to_flags(&["--lowlatency"]), &known_options).is_empty());
+    }
+
+    #[test]
+    fn test_check_strict_flags() {
+        let known_options = BTreeSet::from(["-k".to_owned(), "--lowlatency".to_owned()]);
+        let scx_flags = to_flags(&["-k", "--experimental", "1"]);
+
+        // unknown flags are passed through by default
+        check_strict_flags(false, "scx_fake", &scx_flags, &known_options).unwrap();
+        assert_eq!(
+            check_strict_flags(true, "scx_fake", &scx_flags, &known_options)
+                .unwrap_err()
+                .to_string(),
+            "scx_fake doesn't support the flags --experimental, refusing to start it as \
+             strict_flags is set"
+        );
+        check_strict_flags(true, "scx_fake", &to_flags(&["-k"]), &known_options).unwrap();
+
+        let config: Config = toml::from_str("strict_flags = true").unwrap();
+        assert_eq!(config.strict_flags, Some(true));
+        assert_eq!(Config::default().strict_flags, None);
+    }
+
+    #[test]
+    fn test_validate_flags_against_binary() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let sched_bin = tmp.path().join("scx_fake");
+        std::fs::write(
+            &sched_bin,
+            "#!/bin/sh\necho '  -k, --local-kthreads  Enable kthreads'\necho '      --lowlatency'\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&sched_bin, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut config = Config::default();
+        config.scheds.insert(
+            "scx_fake".to_owned(),
+            config::Sched {
+                path: Some(sched_bin.to_str().unwrap().to_owned()),
+                gaming_mode: Some(to_flags(&["-k", "--lowlatancy"])),
+                lowlatency_mode: Some(to_flags(&["--lowlatency"])),
+                ..Default::default()
+            },
+        );
+
+        let scx_sched = SupportedSched::Custom("scx_fake".to_owned());
+        assert_eq!(
+            validate_flags_against_binary(&config, &scx_sched).unwrap(),
+            FlagCheck::UnknownFlags(vec!["--lowlatancy".to_owned()])
+        );
+    }
+
+    #[test]
+    fn test_validate_flags_binary_not_found() {
+        let mut config = Config::default();
+        config.scheds.insert(
+            "scx_missing".to_owned(),
+            config::Sched {
+                path: Some("/nonexistent/scx_missing".to_owned()),
+                ..Default::default()
+            },
+        );
+
+        let scx_sched = SupportedSched::Custom("scx_missing".to_owned());
+        assert_eq!(
+            validate_flags_against_binary(&config, &scx_sched).unwrap(),
+            FlagCheck::BinaryNotFound
+        );
+    }
+
+    #[test]
+    fn test_split_flags() {
+        assert_eq!(
+            split_flags("--foo bar --baz 'a b'").unwrap(),
+            vec!["--foo", "bar", "--baz", "a b"]
+        );
+        assert_eq!(
+            split_flags(r#"  -m "perf ormance"   -s\ x ''"#).unwrap(),
+            vec!["-m", "perf ormance", "-s x", ""]
+        );
+        assert_eq!(
+            split_flags(r#"--name "say \"hi\"" 'it\s' a"b"'c'"#).unwrap(),
+            vec!["--name", "say \"hi\"", "it\\s", "abc"]
+        );
+        assert_eq!(split_flags(r"a\ b\\c").unwrap(), vec!["a b\\c"]);
+        assert!(split_flags("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_split_flags_unbalanced() {
+        assert_eq!(
+            split_flags("--foo 'bar").unwrap_err().to_string(),
+            "Unbalanced single quote in flags \"--foo 'bar\""
+        );
+        assert!(split_flags(r#"--foo "bar"#).is_err());
+        assert!(split_flags(r#"--foo "bar\"#).is_err());
+        assert!(split_flags(r"--foo \").is_err());
+    }
+
+    #[test]
+    fn test_read_flags_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let flags_path = tmp.path().join("layered.flags");
+        fs::write(
+            &flags_path,
+            "# layers of the desktop\n--run-example\n  -s 10000 -v\n\n",
+        )
+        .unwrap();
+
+        let flags = read_flags_file(flags_path.to_str().unwrap()).unwrap();
+        assert_eq!(flags, vec!["--run-example", "-s", "10000", "-v"]);
+    }
+
+    #[test]
+    fn test_read_flags_file_missing() {
+        let err = read_flags_file("/nonexistent/scx_layered.flags").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Failed to read flags file /nonexistent/scx_layered.flags"
+        );
+    }
+
+    #[test]
+    fn test_expand_set_var() {
+        assert_eq!(
+            expand_vars_with("f:${HOME}/.config/layered.json", lookup),
+            "f:/home/user/.config/layered.json"
+        );
+        assert_eq!(expand_vars_with("$HOME/x", lookup), "/home/user/x");
+    }
+
+    #[test]
+    fn test_expand_unset_var() {
+        assert_eq!(expand_vars_with("${UNSET_VAR}/x", lookup), "${UNSET_VAR}/x");
+        assert_eq!(expand_vars_with("$UNSET_VAR", lookup), "$UNSET_VAR");
+    }
+
+    #[test]
+    fn test_expand_literal_dollar() {
+        assert_eq!(expand_vars_with("$$HOME", lookup), "$HOME");
+        assert_eq!(expand_vars_with("cost=5$", lookup), "cost=5$");
+        assert_eq!(expand_vars_with("${}", lookup), "${}");
+        assert_eq!(expand_vars_with("${HOME", lookup), "${HOME");
+        assert_eq!(expand_vars_with("$-x", lookup), "$-x");
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration_us("5ms").unwrap(), Some(5000));
+        assert_eq!(parse_duration_us("20us").unwrap(), Some(20));
+        assert_eq!(parse_duration_us("2s").unwrap(), Some(2_000_000));
+        assert_eq!(parse_duration_us("5000").unwrap(), None);
+        assert_eq!(parse_duration_us("0xff").unwrap(), None);
+        assert_eq!(parse_duration_us("performance").unwrap(), None);
+        assert!(parse_duration_us("5fortnights").is_err());
+    }
+
+    #[test]
+    fn test_convert_durations() {
+        let flags = to_flags(&[
+            "--slice-us",
+            "5ms",
+            "--slice-us-min=250us",
+            "--slice-max-us",
+            "2s",
+            "-m",
+            "performance",
+            "-c",
+            "0",
+        ]);
+        assert_eq!(
+            convert_durations(&flags).unwrap(),
+            to_flags(&[
+                "--slice-us",
+                "5000",
+                "--slice-us-min=250",
+                "--slice-max-us",
+                "2000000",
+                "-m",
+                "performance",
+                "-c",
+                "0"
+            ])
+        );
+        // converted into the unit of the option
+        assert_eq!(
+            convert_durations(&to_flags(&["--interval-ms", "5s", "--interval-ms=250"])).unwrap(),
+            to_flags(&["--interval-ms", "5000", "--interval-ms=250"])
+        );
+        assert_eq!(
+            format!(
+                "{:#}",
+                convert_durations(&to_flags(&["--interval-ms", "500us"])).unwrap_err()
+            ),
+            "--interval-ms: 500us isn't a whole number of ms"
+        );
+        assert_eq!(
+            format!(
+                "{:#}",
+                convert_durations(&to_flags(&["--slice-us", "5fortnights"])).unwrap_err()
+            ),
+            "--slice-us: Unknown duration unit fortnights in 5fortnights, expected us, ms or s"
+        );
+    }
+
+    #[test]
+    fn test_convert_durations_other_options() {
+        // options which aren't known to take a duration keep their values, whatever they look like
+        let flags = to_flags(&[
+            "-s",
+            "5ms",
+            "--cache-size",
+            "4k",
+            "--mask=0f",
+            "--mode",
+            "5s",
+        ]);
+        assert_eq!(convert_durations(&flags).unwrap(), flags);
+        assert_eq!(
+            convert_durations(&to_flags(&["5ms", "--status", "1s"])).unwrap(),
+            to_flags(&["5ms", "--status", "1s"])
+        );
+    }
+}
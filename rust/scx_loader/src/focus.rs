@@ -0,0 +1,153 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use crate::config;
+use crate::config::Config;
+use crate::SchedMode;
+use crate::SupportedSched;
+
+/// Check whether the name matches the glob pattern, `*` matches any sequence of characters and
+/// `?` a single character
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` in the pattern and the name position it's matched up to
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                // let the last `*` match one more character
+                Some((star_p, star_n)) => {
+                    backtrack = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Get the mode configured in process_modes for the process name or cgroup path, if any.
+///
+/// Exact matches win over glob patterns, of multiple matching patterns the longest one wins as
+/// it's the most specific.
+pub fn match_process_mode(config: &Config, proc_name: &str) -> Option<SchedMode> {
+    if let Some(sched_mode) = config.process_modes.get(proc_name) {
+        return Some(sched_mode.clone());
+    }
+
+    config
+        .process_modes
+        .iter()
+        .filter(|(pattern, _)| glob_match(pattern, proc_name))
+        .max_by(|(pattern, _), (other_pattern, _)| {
+            // of patterns with the same length the lexically first one wins
+            pattern
+                .len()
+                .cmp(&other_pattern.len())
+                .then_with(|| other_pattern.cmp(pattern))
+        })
+        .map(|(_, sched_mode)| sched_mode.clone())
+}
+
+/// Get the scx flags for the scheduler while the given process is in the foreground, falls back
+/// to the default mode of the scheduler if no process mode matches
+pub fn apply_for_process(
+    config: &Config,
+    scx_sched: &SupportedSched,
+    proc_name: &str,
+) -> Vec<String> {
+    let sched_mode = match_process_mode(config, proc_name)
+        .unwrap_or_else(|| config::resolve_default_mode(config, scx_sched));
+    config::get_scx_flags_for_mode(config, scx_sched, sched_mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::parse_config;
+    use crate::focus::*;
+
+    fn get_config() -> Config {
+        let config_str = r#"
+[process_modes]
+"cs2" = "Gaming"
+"steam_app_*" = "Gaming"
+"steam_app_1*" = "LowLatency"
+"*.slice/app-obs*" = "LowLatency"
+"#;
+        parse_config(config_str)
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("steam_app_*", "steam_app_570"));
+        assert!(glob_match("steam_app_*", "steam_app_"));
+        assert!(glob_match("*game*", "my_game_bin"));
+        assert!(glob_match("b?fland", "bpfland"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("steam_app_*", "steam"));
+        assert!(!glob_match("b?fland", "bfland"));
+        assert!(!glob_match("cs2", "cs22"));
+    }
+
+    #[test]
+    fn test_process_mode_exact_match() {
+        let config = get_config();
+        assert_eq!(match_process_mode(&config, "cs2"), Some(SchedMode::Gaming));
+        assert_eq!(
+            apply_for_process(&config, &SupportedSched::Bpfland, "cs2"),
+            vec!["-c", "0", "-k", "-m", "performance"]
+        );
+    }
+
+    #[test]
+    fn test_process_mode_glob_match() {
+        let config = get_config();
+        assert_eq!(
+            match_process_mode(&config, "steam_app_570"),
+            Some(SchedMode::Gaming)
+        );
+        // the longer pattern is more specific
+        assert_eq!(
+            match_process_mode(&config, "steam_app_1091500"),
+            Some(SchedMode::LowLatency)
+        );
+        assert_eq!(
+            match_process_mode(&config, "/user.slice/app-obs-1234.scope"),
+            Some(SchedMode::LowLatency)
+        );
+        assert_eq!(
+            apply_for_process(&config, &SupportedSched::Bpfland, "steam_app_1091500"),
+            vec!["--lowlatency"]
+        );
+    }
+
+    #[test]
+    fn test_process_mode_no_match() {
+        let mut config = get_config();
+        assert_eq!(match_process_mode(&config, "firefox"), None);
+        assert!(apply_for_process(&config, &SupportedSched::Bpfland, "firefox").is_empty());
+
+        config.default_mode = Some(SchedMode::PowerSave);
+        assert_eq!(
+            apply_for_process(&config, &SupportedSched::Bpfland, "firefox"),
+            vec!["-m", "powersave"]
+        );
+    }
+}
@@ -0,0 +1,320 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::VecDeque;
+
+use crate::health;
+use crate::state::SchedState;
+
+/// How the running scheduler is replaced by the requested one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SwitchPlan {
+    /// Start the next scheduler while the previous one is still attached and reap the previous
+    /// one after the next one attached. Only done if switch_handoff is set, as sched_ext usually
+    /// refuses to enable a scheduler while another one is attached
+    Handoff,
+    /// Stop the previous scheduler, if any, before starting the next one. This leaves the
+    /// kernel on the default scheduler in between
+    StopThenStart,
+}
+
+/// Step of replacing the running scheduler
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SwitchStep {
+    /// Stop the previous scheduler and run its poststop hooks, does nothing if already done
+    StopPrevious,
+    /// Run the prestart hooks of the next scheduler
+    Prestart,
+    /// Spawn the next scheduler
+    StartNext,
+    /// Wait for the next scheduler to take over sched_ext from the previous one
+    WaitForAttach,
+    /// Stop the next scheduler which failed to take over
+    StopNext,
+}
+
+/// Decide how to switch from the scheduler running with the given binary, if any, to the next
+/// one. A handoff is only done if enabled and between schedulers with known, different ops
+/// names, as otherwise it's impossible to tell the next scheduler took over
+pub fn plan_switch(running_bin: Option<&str>, next_bin: &str, handoff: bool) -> SwitchPlan {
+    let running_ops = running_bin
+        .filter(|_| handoff)
+        .and_then(health::sched_ops_prefix);
+    match (running_ops, health::sched_ops_prefix(next_bin)) {
+        (Some(running_ops), Some(next_ops)) if running_ops != next_ops => SwitchPlan::Handoff,
+        _ => SwitchPlan::StopThenStart,
+    }
+}
+
+/// Check whether the running scheduler, if any, already runs as requested, so the switch can be
+/// skipped instead of restarting it. Only the mode may differ, e.g. two modes with the same flags,
+/// any other setting like the environment requires a restart
+pub fn is_noop_switch(
+    running: Option<(&str, &SchedState)>,
+    next_bin: &str,
+    next_state: &SchedState,
+) -> bool {
+    running.is_some_and(|(running_bin, running_state)| {
+        running_bin == next_bin && running_state.launch_eq(next_state)
+    })
+}
+
+/// Remaining steps of a switch, updated as steps fail
+#[derive(Debug)]
+pub struct SwitchSteps {
+    steps: VecDeque<SwitchStep>,
+}
+
+impl SwitchSteps {
+    pub fn new(plan: SwitchPlan) -> Self {
+        let steps = match plan {
+            SwitchPlan::Handoff => vec![
+                SwitchStep::Prestart,
+                SwitchStep::StartNext,
+                SwitchStep::WaitForAttach,
+                SwitchStep::StopPrevious,
+            ],
+            SwitchPlan::StopThenStart => vec![
+                SwitchStep::StopPrevious,
+                SwitchStep::Prestart,
+                SwitchStep::StartNext,
+            ],
+        };
+        Self {
+            steps: steps.into(),
+        }
+    }
+
+    pub fn next_step(&mut self) -> Option<SwitchStep> {
+        self.steps.pop_front()
+    }
+
+    /// Replace the remaining steps after the given step failed. A next scheduler which didn't
+    /// take over is stopped and started again after the previous one is stopped, one which
+    /// couldn't be started leaves nothing running
+    pub fn failed(&mut self, step: SwitchStep) {
+        let fallback_steps: &[SwitchStep] = match step {
+            SwitchStep::Prestart | SwitchStep::StartNext => &[SwitchStep::StopPrevious],
+            SwitchStep::WaitForAttach => &[
+                SwitchStep::StopNext,
+                SwitchStep::StopPrevious,
+                SwitchStep::StartNext,
+            ],
+            // stopping is logged and never fails the switch
+            SwitchStep::StopPrevious | SwitchStep::StopNext => return,
+        };
+        self.steps = fallback_steps.iter().copied().collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handoff::*;
+    use crate::SchedMode;
+    use crate::SupportedSched;
+
+    /// Run the steps of the plan, failing the steps for which step_fails is true, and get the
+    /// steps done in order
+    fn simulate_switch(
+        plan: SwitchPlan,
+        mut step_fails: impl FnMut(SwitchStep) -> bool,
+    ) -> Vec<SwitchStep> {
+        let mut switch_steps = SwitchSteps::new(plan);
+        let mut done_steps = vec![];
+        while let Some(step) = switch_steps.next_step() {
+            done_steps.push(step);
+            if step_fails(step) {
+                switch_steps.failed(step);
+            }
+        }
+        done_steps
+    }
+
+    #[test]
+    fn test_plan_switch() {
+        assert_eq!(
+            plan_switch(Some("scx_bpfland"), "scx_lavd", true),
+            SwitchPlan::Handoff
+        );
+        assert_eq!(
+            plan_switch(Some("/usr/bin/scx_rusty"), "/usr/local/bin/scx_lavd", true),
+            SwitchPlan::Handoff
+        );
+        // stopping the previous scheduler first is the default
+        assert_eq!(
+            plan_switch(Some("scx_bpfland"), "scx_lavd", false),
+            SwitchPlan::StopThenStart
+        );
+        // nothing to hand off from
+        assert_eq!(
+            plan_switch(None, "scx_lavd", true),
+            SwitchPlan::StopThenStart
+        );
+        // the next scheduler can't be told apart from the previous one once attached
+        assert_eq!(
+            plan_switch(Some("scx_lavd"), "/usr/bin/scx_lavd", true),
+            SwitchPlan::StopThenStart
+        );
+        assert_eq!(
+            plan_switch(Some("scx_lavd"), "/opt/scx_custom", true),
+            SwitchPlan::StopThenStart
+        );
+        assert_eq!(
+            plan_switch(Some("/opt/scx_custom"), "scx_lavd", true),
+            SwitchPlan::StopThenStart
+        );
+    }
+
+    #[test]
+    fn test_noop_switch() {
+        let running_state = SchedState::new(
+            SupportedSched::Lavd,
+            Some(SchedMode::Gaming),
+            vec!["--performance".to_owned()],
+        );
+        let mut next_state = running_state.clone();
+        next_state.started_at = 1700000000;
+        assert!(is_noop_switch(
+            Some(("scx_lavd", &running_state)),
+            "scx_lavd",
+            &next_state
+        ));
+        // the modes share the flags, there is nothing to restart
+        next_state.mode = Some(SchedMode::LowLatency);
+        assert!(is_noop_switch(
+            Some(("scx_lavd", &running_state)),
+            "scx_lavd",
+            &next_state
+        ));
+
+        // nothing is running
+        assert!(!is_noop_switch(None, "scx_lavd", &next_state));
+    }
+
+    #[test]
+    fn test_switch_with_different_flags() {
+        let running_state = SchedState::new(
+            SupportedSched::Lavd,
+            Some(SchedMode::Gaming),
+            vec!["--performance".to_owned()],
+        );
+        let running = Some(("scx_lavd", &running_state));
+
+        let next_state = SchedState::new(
+            SupportedSched::Lavd,
+            Some(SchedMode::PowerSave),
+            vec!["--powersave".to_owned()],
+        );
+        assert!(!is_noop_switch(running, "scx_lavd", &next_state));
+        let mut next_state = running_state.clone();
+        next_state.cpu_affinity = Some(vec![0, 1]);
+        assert!(!is_noop_switch(running, "scx_lavd", &next_state));
+        assert!(!is_noop_switch(running, "/opt/scx_lavd", &running_state));
+        // a reload only changed the environment, the scheduler must be restarted to apply it
+        let mut next_state = running_state.clone();
+        next_state.env = vec![("LAVD_DEBUG".to_owned(), "1".to_owned())];
+        assert!(!is_noop_switch(running, "scx_lavd", &next_state));
+        let mut next_state = running_state.clone();
+        next_state.oom_score_adj = Some(-1000);
+        assert!(!is_noop_switch(running, "scx_lavd", &next_state));
+        let next_state = SchedState::new(
+            SupportedSched::Flash,
+            None,
+            vec!["--performance".to_owned()],
+        );
+        assert!(!is_noop_switch(running, "scx_lavd", &next_state));
+    }
+
+    #[test]
+    fn test_switch_order() {
+        // the previous scheduler is only reaped after the next one took over
+        assert_eq!(
+            simulate_switch(SwitchPlan::Handoff, |_| false),
+            vec![
+                SwitchStep::Prestart,
+                SwitchStep::StartNext,
+                SwitchStep::WaitForAttach,
+                SwitchStep::StopPrevious,
+            ]
+        );
+        assert_eq!(
+            simulate_switch(SwitchPlan::StopThenStart, |_| false),
+            vec![
+                SwitchStep::StopPrevious,
+                SwitchStep::Prestart,
+                SwitchStep::StartNext,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_handoff_fallback() {
+        // not attached, fall back to stopping the previous scheduler first
+        assert_eq!(
+            simulate_switch(SwitchPlan::Handoff, |step| step
+                == SwitchStep::WaitForAttach),
+            vec![
+                SwitchStep::Prestart,
+                SwitchStep::StartNext,
+                SwitchStep::WaitForAttach,
+                SwitchStep::StopNext,
+                SwitchStep::StopPrevious,
+                SwitchStep::StartNext,
+            ]
+        );
+        // the start after stopping fails too, nothing is left running
+        let mut starts = 0;
+        assert_eq!(
+            simulate_switch(SwitchPlan::Handoff, |step| {
+                step == SwitchStep::WaitForAttach || {
+                    starts += usize::from(step == SwitchStep::StartNext);
+                    starts == 2
+                }
+            }),
+            vec![
+                SwitchStep::Prestart,
+                SwitchStep::StartNext,
+                SwitchStep::WaitForAttach,
+                SwitchStep::StopNext,
+                SwitchStep::StopPrevious,
+                SwitchStep::StartNext,
+                SwitchStep::StopPrevious,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_handoff_start_failure() {
+        // the next scheduler never ran, the previous one is stopped as with stop-then-start
+        assert_eq!(
+            simulate_switch(SwitchPlan::Handoff, |step| step == SwitchStep::Prestart),
+            vec![SwitchStep::Prestart, SwitchStep::StopPrevious]
+        );
+        assert_eq!(
+            simulate_switch(SwitchPlan::Handoff, |step| step == SwitchStep::StartNext),
+            vec![
+                SwitchStep::Prestart,
+                SwitchStep::StartNext,
+                SwitchStep::StopPrevious,
+            ]
+        );
+        assert_eq!(
+            simulate_switch(SwitchPlan::StopThenStart, |step| step
+                == SwitchStep::Prestart),
+            vec![
+                SwitchStep::StopPrevious,
+                SwitchStep::Prestart,
+                SwitchStep::StopPrevious,
+            ]
+        );
+        // a failing stop doesn't change the plan
+        assert_eq!(
+            simulate_switch(SwitchPlan::Handoff, |step| step == SwitchStep::StopPrevious).len(),
+            4
+        );
+    }
+}
@@ -0,0 +1,454 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::time::Instant;
+
+use crate::config;
+use crate::get_name_from_scx;
+use crate::parse_scx_from_name;
+
+/// Directory of sched_ext, relative to the sysfs mount
+const SCHED_EXT_SYSFS_DIR: &str = "kernel/sched_ext";
+
+/// State of sched_ext, "enabled" while a BPF scheduler is attached
+const SCHED_EXT_STATE_FILE: &str = "state";
+
+/// Name of the ops of the attached BPF scheduler
+const SCHED_EXT_OPS_FILE: &str = "root/ops";
+
+/// How often the attach state is polled while waiting for a scheduler to attach
+const ATTACH_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time a started scheduler has to attach when startup_timeout isn't set
+pub const DEFAULT_ATTACH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Source of the state of sched_ext and the name of the attached scheduler
+pub trait SchedExtState {
+    /// Get the sched_ext state, e.g. "enabled" or "disabled"
+    fn state(&self) -> io::Result<String>;
+    /// Get the ops name of the attached scheduler, e.g. "lavd"
+    fn ops(&self) -> io::Result<String>;
+}
+
+/// Reads the sched_ext state from sysfs
+#[derive(Debug, Clone)]
+pub struct SysfsSchedExtState {
+    sched_ext_dir: PathBuf,
+}
+
+impl Default for SysfsSchedExtState {
+    fn default() -> Self {
+        Self::new(Path::new(config::DEFAULT_SYSFS_PATH))
+    }
+}
+
+impl SysfsSchedExtState {
+    /// Read the state of sched_ext in the sysfs mounted at the given path
+    pub fn new(sysfs_path: &Path) -> Self {
+        Self {
+            sched_ext_dir: sysfs_path.join(SCHED_EXT_SYSFS_DIR),
+        }
+    }
+}
+
+impl SchedExtState for SysfsSchedExtState {
+    fn state(&self) -> io::Result<String> {
+        read_sysfs_value(&self.sched_ext_dir.join(SCHED_EXT_STATE_FILE))
+    }
+
+    fn ops(&self) -> io::Result<String> {
+        read_sysfs_value(&self.sched_ext_dir.join(SCHED_EXT_OPS_FILE))
+    }
+}
+
+fn read_sysfs_value(path: &Path) -> io::Result<String> {
+    Ok(fs::read_to_string(path)?.trim().to_owned())
+}
+
+/// Get the name of the scheduler attached to sched_ext, e.g. "scx_lavd" for the ops
+/// "lavd_1.0.6_x86_64", None if none is attached. The ops name of a scheduler which isn't
+/// supported is returned as is
+pub fn read_active_scheduler(sysfs_path: &Path) -> Option<String> {
+    read_active_scheduler_from(&sysfs_path.join(SCHED_EXT_SYSFS_DIR))
+}
+
+/// Like `read_active_scheduler`, reading the sched_ext state from the given sched_ext directory
+pub fn read_active_scheduler_from(sysfs_dir: &Path) -> Option<String> {
+    if read_sysfs_value(&sysfs_dir.join(SCHED_EXT_STATE_FILE)).ok()? != "enabled" {
+        return None;
+    }
+    let ops = read_sysfs_value(&sysfs_dir.join(SCHED_EXT_OPS_FILE)).ok()?;
+    if ops.is_empty() {
+        return None;
+    }
+    // the supported schedulers append their version to the ops name
+    let ops_name = ops.split('_').next().unwrap_or(&ops);
+    match parse_scx_from_name(ops_name) {
+        Some(scx_sched) => Some(get_name_from_scx(&scx_sched).to_owned()),
+        None => Some(ops),
+    }
+}
+
+/// Change of the attachment of the running scheduler, reported by `HealthChecker::check`
+#[derive(Debug, PartialEq)]
+pub enum HealthEvent {
+    /// The scheduler attached after it was started
+    Attached,
+    /// The scheduler was attached before and no longer is
+    Detached,
+}
+
+/// Tracks whether the running scheduler is still attached to sched_ext
+#[derive(Debug, Default)]
+pub struct HealthChecker {
+    attached: bool,
+}
+
+impl HealthChecker {
+    /// Forget the previous state, e.g. when a new scheduler is started. A scheduler is only
+    /// reported as detached once it has been attached, so schedulers still loading don't count
+    pub fn reset(&mut self) {
+        self.attached = false;
+    }
+
+    /// Check whether the scheduler with the given binary is attached and report the change
+    /// since the last check, if any
+    pub fn check(&mut self, source: &impl SchedExtState, sched_bin: &str) -> Option<HealthEvent> {
+        let attached = is_sched_attached(source, sched_bin);
+        let event = match (self.attached, attached) {
+            (false, true) => Some(HealthEvent::Attached),
+            (true, false) => Some(HealthEvent::Detached),
+            _ => None,
+        };
+        self.attached = attached;
+        event
+    }
+}
+
+/// Check whether sched_ext is enabled with the scheduler of the given binary.
+///
+/// The ops of the supported schedulers are named after the scheduler, e.g. "bpfland_x" for
+/// scx_bpfland. The ops name of custom schedulers is unknown, so only the state is checked.
+pub fn is_sched_attached(source: &impl SchedExtState, sched_bin: &str) -> bool {
+    if !source.state().is_ok_and(|state| state == "enabled") {
+        return false;
+    }
+
+    let Some(ops_prefix) = sched_ops_prefix(sched_bin) else {
+        return true;
+    };
+    source.ops().is_ok_and(|ops| ops.starts_with(&ops_prefix))
+}
+
+/// Get the prefix of the ops name of the scheduler with the given binary, e.g. "bpfland" for
+/// scx_bpfland, None for custom schedulers
+pub fn sched_ops_prefix(sched_bin: &str) -> Option<String> {
+    let bin_name = Path::new(sched_bin)
+        .file_name()
+        .and_then(|bin_name| bin_name.to_str())
+        .unwrap_or(sched_bin);
+    let scx_sched = parse_scx_from_name(bin_name)?;
+    Some(
+        get_name_from_scx(&scx_sched)
+            .trim_start_matches("scx_")
+            .to_owned(),
+    )
+}
+
+/// Wait until the scheduler with the given binary is attached to sched_ext, fails if it doesn't
+/// attach within the timeout
+pub async fn wait_for_attach(
+    source: &impl SchedExtState,
+    sched_bin: &str,
+    timeout: Duration,
+) -> Result<()> {
+    wait_for_attach_while(source, sched_bin, timeout, || true).await
+}
+
+/// Wait like wait_for_attach, but fail as soon as is_running reports the scheduler exited, e.g.
+/// because sched_ext refused to enable it
+pub async fn wait_for_attach_while(
+    source: &impl SchedExtState,
+    sched_bin: &str,
+    timeout: Duration,
+    is_running: impl Fn() -> bool,
+) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if is_sched_attached(source, sched_bin) {
+            return Ok(());
+        }
+        if !is_running() {
+            anyhow::bail!("{sched_bin} exited before attaching to sched_ext");
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            anyhow::bail!("{sched_bin} didn't attach to sched_ext within {timeout:?}");
+        }
+        tokio::time::sleep(ATTACH_POLL_INTERVAL.min(deadline - now)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::cell::RefCell;
+    use std::time::Instant;
+
+    use crate::config::Config;
+    use crate::health::*;
+    use crate::restart::RestartDecision;
+    use crate::restart::RestartTracker;
+    use crate::restart::RESTART_WINDOW;
+
+    struct FakeSchedExtState {
+        state: RefCell<&'static str>,
+        ops: RefCell<&'static str>,
+    }
+
+    impl FakeSchedExtState {
+        fn new(state: &'static str, ops: &'static str) -> Self {
+            Self {
+                state: RefCell::new(state),
+                ops: RefCell::new(ops),
+            }
+        }
+
+        fn set(&self, state: &'static str, ops: &'static str) {
+            *self.state.borrow_mut() = state;
+            *self.ops.borrow_mut() = ops;
+        }
+    }
+
+    impl SchedExtState for FakeSchedExtState {
+        fn state(&self) -> io::Result<String> {
+            Ok(self.state.borrow().to_string())
+        }
+
+        fn ops(&self) -> io::Result<String> {
+            Ok(self.ops.borrow().to_string())
+        }
+    }
+
+    fn write_sysfs(sysfs_dir: &Path, state: &str, ops: Option<&str>) {
+        fs::write(sysfs_dir.join(SCHED_EXT_STATE_FILE), format!("{state}\n")).unwrap();
+        let ops_path = sysfs_dir.join(SCHED_EXT_OPS_FILE);
+        match ops {
+            Some(ops) => {
+                fs::create_dir_all(ops_path.parent().unwrap()).unwrap();
+                fs::write(ops_path, format!("{ops}\n")).unwrap();
+            }
+            None => {
+                let _ = fs::remove_dir_all(ops_path.parent().unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_sysfs_path() {
+        let tmp = tempfile::tempdir().unwrap();
+        let config = Config {
+            sysfs_path: Some(tmp.path().to_str().unwrap().to_owned()),
+            ..Default::default()
+        };
+        let sched_ext_dir = tmp.path().join("kernel/sched_ext");
+        fs::create_dir_all(&sched_ext_dir).unwrap();
+        write_sysfs(&sched_ext_dir, "enabled", Some("rusty_1.0.0"));
+
+        let sysfs_path = config.get_sysfs_path();
+        assert_eq!(
+            read_active_scheduler(&sysfs_path).as_deref(),
+            Some("scx_rusty")
+        );
+        let source = SysfsSchedExtState::new(&sysfs_path);
+        assert!(is_sched_attached(&source, "scx_rusty"));
+        assert!(!is_sched_attached(&source, "scx_lavd"));
+
+        // nothing is attached as far as the configured path tells
+        write_sysfs(&sched_ext_dir, "disabled", None);
+        assert_eq!(read_active_scheduler(&sysfs_path), None);
+        assert!(!is_sched_attached(&source, "scx_rusty"));
+
+        assert_eq!(
+            Config::default().get_sysfs_path(),
+            Path::new(config::DEFAULT_SYSFS_PATH)
+        );
+    }
+
+    #[test]
+    fn test_read_active_scheduler() {
+        let tmp = tempfile::tempdir().unwrap();
+        let sysfs_dir = tmp.path();
+
+        write_sysfs(
+            sysfs_dir,
+            "enabled",
+            Some("lavd_1.0.6_g1234567_x86_64_unknown_linux_gnu"),
+        );
+        assert_eq!(
+            read_active_scheduler_from(sysfs_dir).as_deref(),
+            Some("scx_lavd")
+        );
+        write_sysfs(sysfs_dir, "enabled", Some("bpfland"));
+        assert_eq!(
+            read_active_scheduler_from(sysfs_dir).as_deref(),
+            Some("scx_bpfland")
+        );
+        // schedulers which aren't supported keep their ops name
+        write_sysfs(sysfs_dir, "enabled", Some("mysched"));
+        assert_eq!(
+            read_active_scheduler_from(sysfs_dir).as_deref(),
+            Some("mysched")
+        );
+    }
+
+    #[test]
+    fn test_read_active_scheduler_none_attached() {
+        let tmp = tempfile::tempdir().unwrap();
+        let sysfs_dir = tmp.path();
+
+        // sched_ext isn't available at all
+        assert_eq!(read_active_scheduler_from(sysfs_dir), None);
+        write_sysfs(sysfs_dir, "disabled", None);
+        assert_eq!(read_active_scheduler_from(sysfs_dir), None);
+        // a scheduler which is detaching may leave the ops behind
+        write_sysfs(sysfs_dir, "disabling", Some("lavd_1.0.6"));
+        assert_eq!(read_active_scheduler_from(sysfs_dir), None);
+    }
+
+    #[test]
+    fn test_is_sched_attached() {
+        let source = FakeSchedExtState::new("enabled", "bpfland_x");
+        assert!(is_sched_attached(&source, "scx_bpfland"));
+        assert!(is_sched_attached(&source, "/usr/bin/scx_bpfland"));
+        assert!(!is_sched_attached(&source, "scx_lavd"));
+        // custom schedulers only need sched_ext to be enabled
+        assert!(is_sched_attached(&source, "/opt/scx/bin/scx_mysched"));
+
+        source.set("disabled", "");
+        assert!(!is_sched_attached(&source, "scx_bpfland"));
+        assert!(!is_sched_attached(&source, "/opt/scx/bin/scx_mysched"));
+    }
+
+    /// Reports the scheduler as attached from the given poll on
+    struct AttachAfter {
+        polls: Cell<u32>,
+        attach_at: u32,
+    }
+
+    impl SchedExtState for AttachAfter {
+        fn state(&self) -> io::Result<String> {
+            self.polls.set(self.polls.get() + 1);
+            let state = match self.polls.get() >= self.attach_at {
+                true => "enabled",
+                false => "disabled",
+            };
+            Ok(state.to_owned())
+        }
+
+        fn ops(&self) -> io::Result<String> {
+            Ok("nest".to_owned())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_attach() {
+        let source = AttachAfter {
+            polls: Cell::new(0),
+            attach_at: 3,
+        };
+        wait_for_attach(&source, "scx_nest", Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(source.polls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_attach_timeout() {
+        let source = AttachAfter {
+            polls: Cell::new(0),
+            attach_at: u32::MAX,
+        };
+        let err = wait_for_attach(&source, "scx_nest", Duration::from_millis(250))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "scx_nest didn't attach to sched_ext within 250ms"
+        );
+        // polled right away and again until the deadline
+        assert!(source.polls.get() > 1);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_attach_exited() {
+        let source = AttachAfter {
+            polls: Cell::new(0),
+            attach_at: u32::MAX,
+        };
+        // the next scheduler of a handoff exits right away, the fallback doesn't wait for the
+        // timeout
+        let started = Instant::now();
+        let err = wait_for_attach_while(&source, "scx_nest", Duration::from_secs(5), || false)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "scx_nest exited before attaching to sched_ext"
+        );
+        assert_eq!(source.polls.get(), 1);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_health_check_transitions() {
+        let source = FakeSchedExtState::new("disabled", "");
+        let mut checker = HealthChecker::default();
+        let mut restart_tracker = RestartTracker::new(3, RESTART_WINDOW);
+
+        // still loading, not a failure
+        assert_eq!(checker.check(&source, "scx_lavd"), None);
+
+        source.set("enabled", "lavd");
+        assert_eq!(
+            checker.check(&source, "scx_lavd"),
+            Some(HealthEvent::Attached)
+        );
+        assert_eq!(checker.check(&source, "scx_lavd"), None);
+
+        source.set("disabled", "");
+        assert_eq!(
+            checker.check(&source, "scx_lavd"),
+            Some(HealthEvent::Detached)
+        );
+        assert!(matches!(
+            restart_tracker.on_exit(Instant::now()),
+            RestartDecision::Restart { attempt: 1, .. }
+        ));
+        assert_eq!(checker.check(&source, "scx_lavd"), None);
+
+        // replaced by another scheduler
+        checker.reset();
+        source.set("enabled", "lavd");
+        assert_eq!(
+            checker.check(&source, "scx_lavd"),
+            Some(HealthEvent::Attached)
+        );
+        source.set("enabled", "bpfland_x");
+        assert_eq!(
+            checker.check(&source, "scx_lavd"),
+            Some(HealthEvent::Detached)
+        );
+    }
+}
@@ -0,0 +1,145 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::VecDeque;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::state::SchedState;
+
+/// Switches kept in the history unless history_size is configured
+pub const DEFAULT_HISTORY_SIZE: usize = 32;
+
+/// Scheduler launched by the loader and why it was launched
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SwitchRecord {
+    #[serde(flatten)]
+    pub sched_state: SchedState,
+    /// What triggered the switch, e.g. "start", "switch" or "restart", or "stop" for the
+    /// scheduler stopped on request
+    pub reason: String,
+}
+
+/// Ring buffer of the last switches, the oldest ones are evicted once it's full
+#[derive(Debug)]
+pub struct SwitchHistory {
+    capacity: usize,
+    records: VecDeque<SwitchRecord>,
+    /// Mirror the recorded switches to the log
+    log_switches: bool,
+}
+
+impl SwitchHistory {
+    pub fn new(capacity: usize, log_switches: bool) -> Self {
+        Self {
+            capacity,
+            records: VecDeque::with_capacity(capacity),
+            log_switches,
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(
+            config.history_size.unwrap_or(DEFAULT_HISTORY_SIZE),
+            config.log_history.unwrap_or(false),
+        )
+    }
+
+    /// Record the launched scheduler, evicting the oldest switch if the history is full
+    pub fn record(&mut self, sched_state: SchedState, reason: &str) {
+        if self.log_switches {
+            log::info!(
+                "switched to {} with mode {:?} and flags {:?} ({reason})",
+                sched_state.sched,
+                sched_state.mode,
+                sched_state.flags
+            );
+        }
+        self.push(sched_state, reason);
+    }
+
+    /// Record that the scheduler was stopped on request
+    pub fn record_stop(&mut self, sched_state: SchedState) {
+        if self.log_switches {
+            log::info!("stopped {}", sched_state.sched);
+        }
+        self.push(sched_state, "stop");
+    }
+
+    fn push(&mut self, sched_state: SchedState, reason: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(SwitchRecord {
+            sched_state,
+            reason: reason.to_owned(),
+        });
+    }
+
+    /// Get the recorded switches, oldest first
+    pub fn records(&self) -> impl Iterator<Item = &SwitchRecord> {
+        self.records.iter()
+    }
+
+    /// Serialize the recorded switches into JSON, oldest first
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string(&self.records)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::history::*;
+    use crate::SchedMode;
+    use crate::SupportedSched;
+
+    fn sched_state(sched: SupportedSched, started_at: u64) -> SchedState {
+        SchedState {
+            started_at,
+            ..SchedState::new(sched, Some(SchedMode::Auto), vec![])
+        }
+    }
+
+    #[test]
+    fn test_history_eviction() {
+        let mut history = SwitchHistory::new(2, false);
+        history.record(sched_state(SupportedSched::Bpfland, 1), "start");
+        history.record(sched_state(SupportedSched::Lavd, 2), "switch");
+        history.record(sched_state(SupportedSched::Rusty, 3), "restart");
+
+        let records: Vec<_> = history
+            .records()
+            .map(|record| (record.sched_state.started_at, record.reason.as_str()))
+            .collect();
+        assert_eq!(records, vec![(2, "switch"), (3, "restart")]);
+
+        let mut disabled = SwitchHistory::new(0, false);
+        disabled.record(sched_state(SupportedSched::Bpfland, 1), "start");
+        assert_eq!(disabled.records().count(), 0);
+    }
+
+    #[test]
+    fn test_history_json() {
+        let config = Config {
+            history_size: Some(4),
+            ..Default::default()
+        };
+        let mut history = SwitchHistory::from_config(&config);
+        assert_eq!(history.to_json().unwrap(), "[]");
+
+        history.record(sched_state(SupportedSched::Flash, 17), "start");
+        assert_eq!(
+            history.to_json().unwrap(),
+            r#"[{"sched":"scx_flash","mode":"Auto","flags":[],"started_at":17,"reason":"start"}]"#
+        );
+    }
+}
@@ -0,0 +1,176 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::process::Command;
+
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::get_name_from_scx;
+use crate::SupportedSched;
+
+/// Shell commands run around starting and stopping a scheduler
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hooks {
+    /// Run before the scheduler is started, a failing command aborts the start
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub prestart: Vec<String>,
+    /// Run after the scheduler is stopped, failures are only logged
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub poststop: Vec<String>,
+}
+
+/// Runs a hook command line and fails if it doesn't succeed
+pub trait CommandRunner {
+    fn run(&self, command: &str) -> Result<()>;
+}
+
+/// Runs the hook commands with sh
+pub struct ShellCommandRunner;
+
+impl CommandRunner for ShellCommandRunner {
+    fn run(&self, command: &str) -> Result<()> {
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .status()
+            .context("Failed to spawn sh")?;
+        if !status.success() {
+            anyhow::bail!("exited with {status}");
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Get the hooks of the scheduler, the global prestart commands run before the ones of the
+    /// scheduler and the global poststop commands after them
+    pub fn get_hooks(&self, scx_sched: &SupportedSched) -> Hooks {
+        let global_hooks = self.hooks.clone().unwrap_or_default();
+        let Some(sched_hooks) = self
+            .scheds
+            .get(get_name_from_scx(scx_sched))
+            .and_then(|sched_config| sched_config.hooks.clone())
+        else {
+            return global_hooks;
+        };
+        Hooks {
+            prestart: [global_hooks.prestart, sched_hooks.prestart].concat(),
+            poststop: [sched_hooks.poststop, global_hooks.poststop].concat(),
+        }
+    }
+}
+
+/// Run the prestart commands in order, stopping at the first failing one
+pub fn run_prestart(runner: &impl CommandRunner, hooks: &Hooks) -> Result<()> {
+    for command in &hooks.prestart {
+        log::info!("running prestart hook {command:?}");
+        runner
+            .run(command)
+            .with_context(|| format!("prestart hook {command:?} failed"))?;
+    }
+    Ok(())
+}
+
+/// Run all poststop commands, failures are logged as the scheduler is already stopped
+pub fn run_poststop(runner: &impl CommandRunner, hooks: &Hooks) {
+    for command in &hooks.poststop {
+        log::info!("running poststop hook {command:?}");
+        if let Err(err) = runner.run(command) {
+            log::warn!("poststop hook {command:?} failed: {err:#}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use crate::hooks::*;
+
+    /// Records the commands, failing the ones containing "false"
+    #[derive(Default)]
+    struct FakeRunner {
+        commands: RefCell<Vec<String>>,
+    }
+
+    impl CommandRunner for FakeRunner {
+        fn run(&self, command: &str) -> Result<()> {
+            self.commands.borrow_mut().push(command.to_owned());
+            if command.contains("false") {
+                anyhow::bail!("exited with exit status: 1");
+            }
+            Ok(())
+        }
+    }
+
+    fn hooks(prestart: &[&str], poststop: &[&str]) -> Hooks {
+        Hooks {
+            prestart: prestart.iter().map(|command| command.to_string()).collect(),
+            poststop: poststop.iter().map(|command| command.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_prestart_failure_aborts() {
+        let runner = FakeRunner::default();
+        let err = run_prestart(&runner, &hooks(&["echo 1", "false", "echo 2"], &[])).unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "prestart hook \"false\" failed: exited with exit status: 1"
+        );
+        // the commands after the failing one aren't run
+        assert_eq!(*runner.commands.borrow(), vec!["echo 1", "false"]);
+
+        let runner = FakeRunner::default();
+        run_prestart(&runner, &hooks(&["echo 1", "echo 2"], &[])).unwrap();
+        assert_eq!(*runner.commands.borrow(), vec!["echo 1", "echo 2"]);
+    }
+
+    #[test]
+    fn test_poststop_failure_continues() {
+        let runner = FakeRunner::default();
+        run_poststop(&runner, &hooks(&[], &["false", "echo 1"]));
+        assert_eq!(*runner.commands.borrow(), vec!["false", "echo 1"]);
+    }
+
+    #[test]
+    fn test_get_hooks() {
+        let config: Config = toml::from_str(
+            r#"
+[hooks]
+prestart = ["global prestart"]
+poststop = ["global poststop"]
+
+[scheds.scx_lavd.hooks]
+prestart = ["lavd prestart"]
+poststop = ["lavd poststop"]
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.get_hooks(&SupportedSched::Lavd),
+            hooks(
+                &["global prestart", "lavd prestart"],
+                &["lavd poststop", "global poststop"]
+            )
+        );
+        assert_eq!(
+            config.get_hooks(&SupportedSched::Bpfland),
+            hooks(&["global prestart"], &["global poststop"])
+        );
+        assert_eq!(
+            Config::default().get_hooks(&SupportedSched::Bpfland),
+            Hooks::default()
+        );
+    }
+}
@@ -0,0 +1,220 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::get_name_from_scx;
+use crate::SupportedSched;
+
+/// Oldest kernel with sched_ext, available as a backport before it was merged in 6.12
+const SCHED_EXT_MIN_KERNEL: KernelVersion = KernelVersion::new(6, 6, 0);
+
+/// Kernel version, compared by major, minor and patch version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct KernelVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl KernelVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl FromStr for KernelVersion {
+    type Err = anyhow::Error;
+
+    /// Parse a version like "6.12" or a kernel release like "6.12.3-arch1-1", the suffix after
+    /// the version numbers is ignored
+    fn from_str(version: &str) -> Result<Self> {
+        let numbers_end = version
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(version.len());
+        let mut numbers = version[..numbers_end].split('.').map(|number| {
+            number
+                .parse::<u32>()
+                .with_context(|| format!("Invalid kernel version {version:?}"))
+        });
+        let major = numbers
+            .next()
+            .with_context(|| format!("Invalid kernel version {version:?}"))??;
+        let minor = numbers.next().transpose()?.unwrap_or(0);
+        let patch = numbers.next().transpose()?.unwrap_or(0);
+        Ok(Self::new(major, minor, patch))
+    }
+}
+
+impl fmt::Display for KernelVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.patch == 0 {
+            write!(f, "{}.{}", self.major, self.minor)
+        } else {
+            write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+        }
+    }
+}
+
+/// Source of the release of the running kernel
+pub trait KernelRelease {
+    /// Get the kernel release, e.g. "6.12.3-arch1-1"
+    fn release(&self) -> Result<String>;
+}
+
+/// Reads the kernel release with uname
+pub struct UnameKernelRelease;
+
+impl KernelRelease for UnameKernelRelease {
+    fn release(&self) -> Result<String> {
+        let uts_name = nix::sys::utsname::uname().context("Failed to get the kernel release")?;
+        Ok(uts_name.release().to_string_lossy().into_owned())
+    }
+}
+
+/// Get the oldest kernel the scheduler is known to run on, None if it isn't known
+pub fn builtin_min_kernel(scx_sched: &SupportedSched) -> Option<KernelVersion> {
+    match scx_sched {
+        SupportedSched::Custom(_) => None,
+        _ => Some(SCHED_EXT_MIN_KERNEL),
+    }
+}
+
+impl Config {
+    /// Get the oldest kernel the scheduler can be started on, the configured min_kernel
+    /// overrides the built-in requirement. An invalid min_kernel is reported by `validate`
+    pub fn get_min_kernel(&self, scx_sched: &SupportedSched) -> Option<KernelVersion> {
+        let configured = self
+            .scheds
+            .get(get_name_from_scx(scx_sched))
+            .and_then(|sched_config| sched_config.min_kernel.as_deref())
+            .and_then(|min_kernel| min_kernel.parse().ok());
+        configured.or_else(|| builtin_min_kernel(scx_sched))
+    }
+}
+
+/// Check that the running kernel is at least the given version before starting the scheduler
+pub fn check_min_kernel(
+    source: &impl KernelRelease,
+    sched_bin: &str,
+    min_kernel: KernelVersion,
+) -> Result<()> {
+    let release = source.release()?;
+    let running_kernel: KernelVersion = release.parse()?;
+    if running_kernel < min_kernel {
+        anyhow::bail!("{sched_bin} requires kernel >= {min_kernel}, running {release}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kernel::*;
+
+    struct FakeKernelRelease(&'static str);
+
+    impl KernelRelease for FakeKernelRelease {
+        fn release(&self) -> Result<String> {
+            Ok(self.0.to_owned())
+        }
+    }
+
+    #[test]
+    fn test_parse_kernel_version() {
+        assert_eq!(
+            "6.12.3-arch1-1".parse::<KernelVersion>().unwrap(),
+            KernelVersion::new(6, 12, 3)
+        );
+        assert_eq!(
+            "6.12".parse::<KernelVersion>().unwrap(),
+            KernelVersion::new(6, 12, 0)
+        );
+        assert_eq!(
+            "6.13-rc4".parse::<KernelVersion>().unwrap(),
+            KernelVersion::new(6, 13, 0)
+        );
+        assert_eq!(
+            "7".parse::<KernelVersion>().unwrap(),
+            KernelVersion::new(7, 0, 0)
+        );
+        assert!("".parse::<KernelVersion>().is_err());
+        assert!("linux".parse::<KernelVersion>().is_err());
+        assert!("6..1".parse::<KernelVersion>().is_err());
+        assert_eq!(KernelVersion::new(6, 12, 0).to_string(), "6.12");
+        assert_eq!(KernelVersion::new(6, 6, 58).to_string(), "6.6.58");
+    }
+
+    #[test]
+    fn test_compare_kernel_versions() {
+        let min_kernel: KernelVersion = "6.11".parse().unwrap();
+        assert!("6.12".parse::<KernelVersion>().unwrap() >= min_kernel);
+        assert!("6.11.0".parse::<KernelVersion>().unwrap() >= min_kernel);
+        assert!("6.9".parse::<KernelVersion>().unwrap() < min_kernel);
+        // the minor version is compared as a number
+        assert!("6.100".parse::<KernelVersion>().unwrap() > min_kernel);
+        assert!("7.0".parse::<KernelVersion>().unwrap() > min_kernel);
+    }
+
+    #[test]
+    fn test_check_min_kernel() {
+        let min_kernel = KernelVersion::new(6, 11, 0);
+        check_min_kernel(&FakeKernelRelease("6.12.1-zen1"), "scx_lavd", min_kernel).unwrap();
+        assert_eq!(
+            check_min_kernel(&FakeKernelRelease("6.9.12-arch1-1"), "scx_lavd", min_kernel)
+                .unwrap_err()
+                .to_string(),
+            "scx_lavd requires kernel >= 6.11, running 6.9.12-arch1-1"
+        );
+    }
+
+    #[test]
+    fn test_get_min_kernel() {
+        let config: Config = toml::from_str(
+            r#"
+[scheds.scx_lavd]
+min_kernel = "6.13"
+
+[scheds.scx_custom]
+path = "/opt/scx_custom"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.get_min_kernel(&SupportedSched::Lavd),
+            Some(KernelVersion::new(6, 13, 0))
+        );
+        assert_eq!(
+            config.get_min_kernel(&SupportedSched::Bpfland),
+            Some(SCHED_EXT_MIN_KERNEL)
+        );
+        assert_eq!(
+            config.get_min_kernel(&SupportedSched::Custom("scx_custom".to_owned())),
+            None
+        );
+        config.validate().unwrap();
+
+        // an invalid min_kernel falls back to the built-in requirement
+        let config: Config = toml::from_str("[scheds.scx_lavd]\nmin_kernel = \"six\"\n").unwrap();
+        assert_eq!(
+            config.get_min_kernel(&SupportedSched::Lavd),
+            Some(SCHED_EXT_MIN_KERNEL)
+        );
+        assert_eq!(
+            config.validation_report().errors,
+            vec!["scx_lavd min_kernel: Invalid kernel version \"six\""]
+        );
+    }
+}
@@ -0,0 +1,471 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+pub mod affinity;
+pub mod alias;
+pub mod cgroup;
+pub mod config;
+pub mod config_hash;
+pub mod debounce;
+pub mod diff;
+pub mod error;
+pub mod flags;
+pub mod focus;
+pub mod handoff;
+pub mod health;
+pub mod history;
+pub mod hooks;
+pub mod kernel;
+pub mod list;
+pub mod lock;
+pub mod log_fields;
+pub mod metrics;
+pub mod oom;
+pub mod output;
+pub mod pin;
+pub mod power;
+pub mod priority;
+pub mod reload;
+pub mod restart;
+pub mod rollback;
+pub mod sched_bin;
+pub mod sched_env;
+pub mod schedule;
+pub mod state;
+pub mod status;
+pub mod stop;
+pub mod topology;
+pub mod watch;
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de;
+use serde::ser;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+use zvariant::Signature;
+use zvariant::Type;
+use zvariant::Value;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum SupportedSched {
+    Bpfland,
+    Rusty,
+    Lavd,
+    Rustland,
+    Layered,
+    Flash,
+    P2dq,
+    Tickless,
+    Nest,
+    Central,
+    Cosmos,
+    Flatcg,
+    Simple,
+    Chaos,
+    /// Scheduler defined in the config with the path to its binary
+    Custom(String),
+}
+
+/// All schedulers supported by the loader
+pub const SUPPORTED_SCHEDS: &[SupportedSched] = &[
+    SupportedSched::Bpfland,
+    SupportedSched::Rusty,
+    SupportedSched::Lavd,
+    SupportedSched::Rustland,
+    SupportedSched::Layered,
+    SupportedSched::Flash,
+    SupportedSched::P2dq,
+    SupportedSched::Tickless,
+    SupportedSched::Nest,
+    SupportedSched::Central,
+    SupportedSched::Cosmos,
+    SupportedSched::Flatcg,
+    SupportedSched::Simple,
+    SupportedSched::Chaos,
+];
+
+/// Mode of the scheduler, sent over DBUS as its number. Config files use the variant names, any
+/// other name is a custom mode
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchedMode {
+    /// Default values for the scheduler
+    Auto,
+    /// Applies flags for better gaming experience
+    Gaming,
+    /// Applies flags for lower power usage
+    PowerSave,
+    /// Starts scheduler in low latency mode
+    LowLatency,
+    /// Applies flags for throughput on headless servers
+    Server,
+    /// Applies the flags of the named profile of the scheduler, falls back to the flags of Auto
+    /// if it has no such profile. Only available in the config, it has no number
+    Custom(String),
+}
+
+/// All scheduler modes
+pub const SCHED_MODES: &[SchedMode] = &[
+    SchedMode::Auto,
+    SchedMode::Gaming,
+    SchedMode::PowerSave,
+    SchedMode::LowLatency,
+    SchedMode::Server,
+];
+
+impl FromStr for SchedMode {
+    type Err = anyhow::Error;
+
+    /// Parse the mode name ignoring case, e.g. "Gaming", "low-latency" or "powersave"
+    fn from_str(mode_name: &str) -> Result<Self, Self::Err> {
+        match mode_name.to_ascii_lowercase().as_str() {
+            "auto" => Ok(SchedMode::Auto),
+            "gaming" => Ok(SchedMode::Gaming),
+            "lowlatency" | "low-latency" => Ok(SchedMode::LowLatency),
+            "powersave" | "power-save" => Ok(SchedMode::PowerSave),
+            "server" => Ok(SchedMode::Server),
+            _ => anyhow::bail!("Unknown scheduler mode {mode_name}"),
+        }
+    }
+}
+
+impl fmt::Display for SchedMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode_name = match self {
+            SchedMode::Auto => "auto",
+            SchedMode::Gaming => "gaming",
+            SchedMode::LowLatency => "lowlatency",
+            SchedMode::PowerSave => "powersave",
+            SchedMode::Server => "server",
+            SchedMode::Custom(profile_name) => profile_name,
+        };
+        f.write_str(mode_name)
+    }
+}
+
+impl SchedMode {
+    /// Get the number of the mode used over DBUS, None for custom modes. The numbers are part of
+    /// the DBUS interface and must not change: 0 Auto, 1 Gaming, 2 PowerSave, 3 LowLatency and
+    /// 4 Server
+    pub fn to_number(&self) -> Option<u32> {
+        match self {
+            SchedMode::Auto => Some(0),
+            SchedMode::Gaming => Some(1),
+            SchedMode::PowerSave => Some(2),
+            SchedMode::LowLatency => Some(3),
+            SchedMode::Server => Some(4),
+            SchedMode::Custom(_) => None,
+        }
+    }
+
+    /// Get the mode of the number used over DBUS
+    pub fn from_number(mode_number: u32) -> Option<Self> {
+        SCHED_MODES
+            .iter()
+            .find(|sched_mode| sched_mode.to_number() == Some(mode_number))
+            .cloned()
+    }
+
+    /// Parse either the mode name, see `from_str`, or the number of the mode, see `to_number`,
+    /// for clients which pass the mode by number, e.g. "1" for Gaming
+    pub fn parse_loose(value: &str) -> anyhow::Result<Self> {
+        let value = value.trim();
+        match value.parse::<u32>() {
+            Ok(mode_number) => SchedMode::from_number(mode_number)
+                .ok_or_else(|| anyhow::anyhow!("Unknown scheduler mode number {mode_number}")),
+            Err(_) => value.parse(),
+        }
+    }
+
+    /// Get the name of the mode used in the config, e.g. "PowerSave"
+    fn config_name(&self) -> &str {
+        match self {
+            SchedMode::Auto => "Auto",
+            SchedMode::Gaming => "Gaming",
+            SchedMode::PowerSave => "PowerSave",
+            SchedMode::LowLatency => "LowLatency",
+            SchedMode::Server => "Server",
+            SchedMode::Custom(profile_name) => profile_name,
+        }
+    }
+}
+
+impl Serialize for SchedMode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.serialize_str(self.config_name());
+        }
+        match self.to_number() {
+            Some(mode_number) => serializer.serialize_u32(mode_number),
+            None => Err(ser::Error::custom(format!(
+                "custom mode {self} has no number"
+            ))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SchedMode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if !deserializer.is_human_readable() {
+            let mode_number = u32::deserialize(deserializer)?;
+            return SchedMode::from_number(mode_number)
+                .ok_or_else(|| de::Error::custom(format!("Unknown scheduler mode {mode_number}")));
+        }
+        // the built-in names ignore case like on the command line, the rest are custom modes
+        let mode_name = String::deserialize(deserializer)?;
+        Ok(mode_name.parse().unwrap_or(SchedMode::Custom(mode_name)))
+    }
+}
+
+impl Type for SchedMode {
+    fn signature() -> Signature<'static> {
+        u32::signature()
+    }
+}
+
+impl From<SchedMode> for Value<'_> {
+    /// Custom modes have no number, they are reported as Auto
+    fn from(sched_mode: SchedMode) -> Self {
+        Value::U32(sched_mode.to_number().unwrap_or_default())
+    }
+}
+
+impl FromStr for SupportedSched {
+    type Err = anyhow::Error;
+
+    /// Parse the name of a supported scheduler, see `parse_scx_from_name`. Custom schedulers
+    /// are only known to the config, see `config::resolve_sched_name`
+    fn from_str(scx_name: &str) -> Result<Self, Self::Err> {
+        parse_scx_from_name(scx_name)
+            .ok_or_else(|| anyhow::anyhow!("{scx_name} is not a supported scheduler"))
+    }
+}
+
+impl fmt::Display for SupportedSched {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(get_name_from_scx(self))
+    }
+}
+
+impl From<String> for SupportedSched {
+    /// Names which aren't supported out of the box refer to a custom scheduler
+    fn from(scx_name: String) -> Self {
+        parse_scx_from_name(&scx_name).unwrap_or(SupportedSched::Custom(scx_name))
+    }
+}
+
+impl From<SupportedSched> for String {
+    fn from(scx_sched: SupportedSched) -> Self {
+        scx_sched.to_string()
+    }
+}
+
+/// Get the supported scheduler from its name, with or without the "scx_" prefix, e.g. "scx_lavd"
+/// or "lavd". Returns None for names which aren't supported out of the box
+pub fn parse_scx_from_name(scx_name: &str) -> Option<SupportedSched> {
+    let scx_name = scx_name.strip_prefix("scx_").unwrap_or(scx_name);
+    SUPPORTED_SCHEDS
+        .iter()
+        .find(|scx_sched| get_name_from_scx(scx_sched).strip_prefix("scx_") == Some(scx_name))
+        .cloned()
+}
+
+/// Get the scx name from the given scx trait
+pub fn get_name_from_scx(supported_sched: &SupportedSched) -> &str {
+    match supported_sched {
+        SupportedSched::Bpfland => "scx_bpfland",
+        SupportedSched::Rusty => "scx_rusty",
+        SupportedSched::Lavd => "scx_lavd",
+        SupportedSched::Rustland => "scx_rustland",
+        SupportedSched::Layered => "scx_layered",
+        SupportedSched::Flash => "scx_flash",
+        SupportedSched::P2dq => "scx_p2dq",
+        SupportedSched::Tickless => "scx_tickless",
+        SupportedSched::Nest => "scx_nest",
+        SupportedSched::Central => "scx_central",
+        SupportedSched::Cosmos => "scx_cosmos",
+        SupportedSched::Flatcg => "scx_flatcg",
+        SupportedSched::Simple => "scx_simple",
+        SupportedSched::Chaos => "scx_chaos",
+        SupportedSched::Custom(scx_name) => scx_name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_parse_scx_from_name() {
+        for scx_sched in SUPPORTED_SCHEDS {
+            let scx_name = get_name_from_scx(scx_sched);
+            assert_eq!(parse_scx_from_name(scx_name).as_ref(), Some(scx_sched));
+
+            let bare_name = scx_name.strip_prefix("scx_").unwrap();
+            assert_eq!(parse_scx_from_name(bare_name).as_ref(), Some(scx_sched));
+        }
+        assert_eq!(
+            parse_scx_from_name("bpfland"),
+            Some(SupportedSched::Bpfland)
+        );
+        assert_eq!(parse_scx_from_name("scx_unknown"), None);
+        assert_eq!(parse_scx_from_name("unknown"), None);
+        assert_eq!(parse_scx_from_name("scx_"), None);
+        assert_eq!(parse_scx_from_name(""), None);
+    }
+
+    #[test]
+    fn test_supported_sched_from_str() {
+        for scx_sched in SUPPORTED_SCHEDS {
+            assert_eq!(
+                &scx_sched.to_string().parse::<SupportedSched>().unwrap(),
+                scx_sched
+            );
+        }
+        assert_eq!(
+            "flash".parse::<SupportedSched>().unwrap(),
+            SupportedSched::Flash
+        );
+        assert_eq!(SupportedSched::Lavd.to_string(), "scx_lavd");
+        assert_eq!(
+            SupportedSched::Custom("scx_mysched".to_owned()).to_string(),
+            "scx_mysched"
+        );
+        assert!("scx_mysched".parse::<SupportedSched>().is_err());
+    }
+
+    #[test]
+    fn test_sched_mode_from_str() {
+        assert_eq!("auto".parse::<SchedMode>().unwrap(), SchedMode::Auto);
+        assert_eq!("Gaming".parse::<SchedMode>().unwrap(), SchedMode::Gaming);
+        assert_eq!(
+            "LowLatency".parse::<SchedMode>().unwrap(),
+            SchedMode::LowLatency
+        );
+        assert_eq!(
+            "low-latency".parse::<SchedMode>().unwrap(),
+            SchedMode::LowLatency
+        );
+        assert_eq!(
+            "POWER-SAVE".parse::<SchedMode>().unwrap(),
+            SchedMode::PowerSave
+        );
+        assert_eq!(
+            "powersave".parse::<SchedMode>().unwrap(),
+            SchedMode::PowerSave
+        );
+
+        assert_eq!("Server".parse::<SchedMode>().unwrap(), SchedMode::Server);
+
+        let err = "turbo".parse::<SchedMode>().unwrap_err();
+        assert_eq!(err.to_string(), "Unknown scheduler mode turbo");
+
+        for sched_mode in SCHED_MODES {
+            assert_eq!(
+                &sched_mode.to_string().parse::<SchedMode>().unwrap(),
+                sched_mode
+            );
+        }
+    }
+
+    #[test]
+    fn test_sched_mode_serde() {
+        #[derive(Debug, PartialEq, Deserialize, Serialize)]
+        struct ModeConfig {
+            mode: SchedMode,
+        }
+
+        for (mode_str, sched_mode) in [
+            ("\"PowerSave\"", SchedMode::PowerSave),
+            ("\"server\"", SchedMode::Server),
+            ("\"compile\"", SchedMode::Custom("compile".to_owned())),
+        ] {
+            let mode_config: ModeConfig = toml::from_str(&format!("mode = {mode_str}")).unwrap();
+            assert_eq!(mode_config.mode, sched_mode);
+        }
+        assert_eq!(
+            toml::to_string(&ModeConfig {
+                mode: SchedMode::LowLatency
+            })
+            .unwrap(),
+            "mode = \"LowLatency\"\n"
+        );
+        assert_eq!(
+            toml::to_string(&ModeConfig {
+                mode: SchedMode::Custom("compile".to_owned())
+            })
+            .unwrap(),
+            "mode = \"compile\"\n"
+        );
+        assert!(toml::from_str::<ModeConfig>("mode = 1").is_err());
+    }
+
+    #[test]
+    fn test_sched_mode_number() {
+        for sched_mode in SCHED_MODES {
+            let mode_number = sched_mode.to_number().unwrap();
+            assert_eq!(
+                SchedMode::from_number(mode_number).as_ref(),
+                Some(sched_mode)
+            );
+        }
+        assert_eq!(SchedMode::PowerSave.to_number(), Some(2));
+        assert_eq!(SchedMode::Custom("compile".to_owned()).to_number(), None);
+        assert_eq!(SchedMode::from_number(5), None);
+        assert_eq!(SchedMode::signature(), "u");
+    }
+
+    #[test]
+    fn test_sched_mode_parse_loose() {
+        // the numbers are stable, clients rely on them
+        let numbered_modes = [
+            ("0", SchedMode::Auto),
+            ("1", SchedMode::Gaming),
+            ("2", SchedMode::PowerSave),
+            ("3", SchedMode::LowLatency),
+            (" 4 ", SchedMode::Server),
+        ];
+        for (value, sched_mode) in numbered_modes {
+            assert_eq!(SchedMode::parse_loose(value).unwrap(), sched_mode);
+        }
+
+        assert_eq!(SchedMode::parse_loose("Gaming").unwrap(), SchedMode::Gaming);
+        assert_eq!(
+            SchedMode::parse_loose("low-latency").unwrap(),
+            SchedMode::LowLatency
+        );
+
+        assert_eq!(
+            SchedMode::parse_loose("5").unwrap_err().to_string(),
+            "Unknown scheduler mode number 5"
+        );
+        assert_eq!(
+            SchedMode::parse_loose("-1").unwrap_err().to_string(),
+            "Unknown scheduler mode -1"
+        );
+        assert!(SchedMode::parse_loose("turbo").is_err());
+    }
+
+    #[test]
+    fn test_sched_mode_dbus() {
+        let ctxt = zvariant::serialized::Context::new_dbus(zvariant::LE, 0);
+        let encoded = zvariant::to_bytes(ctxt, &SchedMode::PowerSave).unwrap();
+        assert_eq!(encoded.deserialize::<u32>().unwrap().0, 2);
+        assert_eq!(
+            encoded.deserialize::<SchedMode>().unwrap().0,
+            SchedMode::PowerSave
+        );
+
+        let encoded = zvariant::to_bytes(ctxt, &7u32).unwrap();
+        assert!(encoded.deserialize::<SchedMode>().is_err());
+        assert!(zvariant::to_bytes(ctxt, &SchedMode::Custom("compile".to_owned())).is_err());
+    }
+}
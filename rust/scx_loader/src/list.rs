@@ -0,0 +1,176 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::config;
+use crate::config::Config;
+use crate::config::SchedCapabilities;
+use crate::get_name_from_scx;
+use crate::sched_bin;
+use crate::SupportedSched;
+use crate::SUPPORTED_SCHEDS;
+
+/// Row of a supported scheduler as printed by --list
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SchedListing {
+    pub sched: String,
+    /// Whether the binary of the scheduler is installed
+    pub installed: bool,
+    /// Special modes the scheduler has built-in flags for
+    #[serde(flatten)]
+    pub capabilities: SchedCapabilities,
+}
+
+/// List every supported scheduler followed by the custom ones, installed as the loader would
+/// find them, i.e. in the bin_dirs, at their path or in the search directories
+pub fn list_scheds(config: &Config, search_dirs: &[PathBuf]) -> Vec<SchedListing> {
+    let custom_scheds = config
+        .scheduler_names()
+        .into_iter()
+        .filter(|sched_name| config::is_custom_sched(config, sched_name))
+        .map(SupportedSched::from);
+    SUPPORTED_SCHEDS
+        .iter()
+        .cloned()
+        .chain(custom_scheds)
+        .map(|scx_sched| SchedListing {
+            sched: get_name_from_scx(&scx_sched).to_owned(),
+            installed: sched_bin::is_sched_available(config, &scx_sched, search_dirs),
+            capabilities: config::describe(&scx_sched),
+        })
+        .collect()
+}
+
+pub fn listing_to_json(listings: &[SchedListing]) -> Result<String> {
+    serde_json::to_string(listings).context("Failed to serialize scheduler list")
+}
+
+/// Format the list as a table of one scheduler per line
+pub fn listing_to_table(listings: &[SchedListing]) -> String {
+    let yes_no = |value: bool| if value { "yes" } else { "no" };
+    let mut table = format!(
+        "{:<14} {:<10} {:<7} {:<11} powersave\n",
+        "scheduler", "installed", "gaming", "lowlatency"
+    );
+    for listing in listings {
+        let _ = writeln!(
+            table,
+            "{:<14} {:<10} {:<7} {:<11} {}",
+            listing.sched,
+            yes_no(listing.installed),
+            yes_no(listing.capabilities.gaming),
+            yes_no(listing.capabilities.lowlatency),
+            yes_no(listing.capabilities.powersave),
+        );
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use crate::list::*;
+
+    /// Get a directory with executables of the given names
+    fn bin_dir(bin_names: &[&str]) -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        for bin_name in bin_names {
+            let bin_path = tmp.path().join(bin_name);
+            fs::write(&bin_path, "#!/bin/sh\n").unwrap();
+            fs::set_permissions(&bin_path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        tmp
+    }
+
+    #[test]
+    fn test_list_scheds() {
+        let tmp = bin_dir(&["scx_lavd", "scx_rustland", "scx_mine"]);
+        let config: Config = toml::from_str(&format!(
+            r#"
+[scheds.scx_mine]
+path = "{}"
+"#,
+            tmp.path().join("scx_mine").display()
+        ))
+        .unwrap();
+        let listings = list_scheds(&config, &[tmp.path().to_path_buf()]);
+        assert_eq!(listings.len(), SUPPORTED_SCHEDS.len() + 1);
+
+        let listing = |sched_name: &str| {
+            listings
+                .iter()
+                .find(|listing| listing.sched == sched_name)
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(
+            listing("scx_lavd"),
+            SchedListing {
+                sched: "scx_lavd".to_owned(),
+                installed: true,
+                capabilities: SchedCapabilities {
+                    gaming: true,
+                    lowlatency: true,
+                    powersave: true,
+                },
+            }
+        );
+        // installed, but without built-in flags for the special modes
+        assert_eq!(
+            listing("scx_rustland"),
+            SchedListing {
+                sched: "scx_rustland".to_owned(),
+                installed: true,
+                capabilities: SchedCapabilities::default(),
+            }
+        );
+        assert!(!listing("scx_bpfland").installed);
+        assert!(listing("scx_bpfland").capabilities.gaming);
+        // custom schedulers are found at their path
+        assert_eq!(listings.last().unwrap().sched, "scx_mine");
+        assert!(listing("scx_mine").installed);
+    }
+
+    #[test]
+    fn test_list_scheds_bin_dirs() {
+        let tmp = bin_dir(&["scx_bpfland"]);
+        let config = Config {
+            bin_dirs: vec![tmp.path().display().to_string()],
+            ..Default::default()
+        };
+        let listings = list_scheds(&config, &[]);
+        assert!(listings[0].installed);
+        assert_eq!(listings[0].sched, "scx_bpfland");
+    }
+
+    #[test]
+    fn test_listing_formats() {
+        let tmp = bin_dir(&["scx_lavd"]);
+        let listings = list_scheds(&Config::default(), &[tmp.path().to_path_buf()]);
+        let listings = &listings[..3];
+
+        assert_eq!(
+            listing_to_table(listings),
+            "scheduler      installed  gaming  lowlatency  powersave\n\
+             scx_bpfland    no         yes     yes         yes\n\
+             scx_rusty      no         yes     yes         yes\n\
+             scx_lavd       yes        yes     yes         yes\n"
+        );
+        assert_eq!(
+            listing_to_json(&listings[2..]).unwrap(),
+            r#"[{"sched":"scx_lavd","installed":true,"gaming":true,"lowlatency":true,"powersave":true}]"#
+        );
+    }
+}
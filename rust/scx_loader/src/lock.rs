@@ -0,0 +1,177 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::Context;
+use anyhow::Result;
+use nix::errno::Errno;
+use nix::fcntl::Flock;
+use nix::fcntl::FlockArg;
+
+/// Time waited for another process to release the config lock before giving up
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Delay between the attempts to take the config lock
+const LOCK_RETRY_DELAY: Duration = Duration::from_millis(25);
+
+/// Kind of the advisory lock, any number of readers share it while a writer holds it alone
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockKind {
+    Shared,
+    Exclusive,
+}
+
+/// Advisory lock of a config file, released when dropped
+#[derive(Debug)]
+pub struct ConfigLock {
+    _lock: Flock<File>,
+}
+
+/// Lock the config file itself, retrying until the timeout while another process holds a
+/// conflicting lock.
+///
+/// The config is replaced by renaming a new file over it, so a lock taken on the replaced file
+/// is retried on the new one. Nothing is locked, i.e. None is returned, if the config doesn't
+/// exist yet, or for a shared lock if it can't be opened, reading it reports the error then.
+pub fn lock_config(
+    filepath: &str,
+    kind: LockKind,
+    timeout: Duration,
+) -> Result<Option<ConfigLock>> {
+    let flock_arg = match kind {
+        LockKind::Shared => FlockArg::LockSharedNonblock,
+        LockKind::Exclusive => FlockArg::LockExclusiveNonblock,
+    };
+    let started_at = Instant::now();
+    loop {
+        let mut config_file = match File::open(filepath) {
+            Ok(config_file) => config_file,
+            Err(err) if kind == LockKind::Shared || err.kind() == io::ErrorKind::NotFound => {
+                log::debug!("Not locking {filepath}: {err}");
+                return Ok(None);
+            }
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to open config file {filepath}"))
+            }
+        };
+        let lock = loop {
+            match Flock::lock(config_file, flock_arg) {
+                Ok(lock) => break lock,
+                Err((file, Errno::EWOULDBLOCK)) if started_at.elapsed() < timeout => {
+                    config_file = file;
+                    thread::sleep(LOCK_RETRY_DELAY);
+                }
+                Err((_, Errno::EWOULDBLOCK)) => anyhow::bail!(
+                    "Config file {filepath} is locked by another process, gave up after {timeout:?}"
+                ),
+                Err((_, errno)) => {
+                    return Err(errno).with_context(|| format!("Failed to lock {filepath}"))
+                }
+            }
+        };
+        if !is_replaced(&lock, filepath) {
+            return Ok(Some(ConfigLock { _lock: lock }));
+        }
+    }
+}
+
+/// Check whether the locked file was replaced by a write meanwhile
+fn is_replaced(locked_file: &File, filepath: &str) -> bool {
+    match (locked_file.metadata(), fs::metadata(filepath)) {
+        (Ok(locked), Ok(current)) => (locked.dev(), locked.ino()) != (current.dev(), current.ino()),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lock::*;
+
+    #[test]
+    fn test_lock_contention_times_out() {
+        let tmp = tempfile::tempdir().unwrap();
+        let filepath = tmp.path().join("scx_loader.toml");
+        fs::write(&filepath, "").unwrap();
+        let filepath = filepath.to_str().unwrap();
+
+        let held_lock = lock_config(filepath, LockKind::Exclusive, Duration::ZERO).unwrap();
+        assert!(held_lock.is_some());
+
+        let started_at = Instant::now();
+        let err = lock_config(filepath, LockKind::Shared, Duration::from_millis(100)).unwrap_err();
+        assert!(started_at.elapsed() >= Duration::from_millis(100));
+        assert_eq!(
+            err.to_string(),
+            format!("Config file {filepath} is locked by another process, gave up after 100ms")
+        );
+        assert!(lock_config(filepath, LockKind::Exclusive, Duration::ZERO).is_err());
+
+        // readers share the lock
+        drop(held_lock);
+        let shared_lock = lock_config(filepath, LockKind::Shared, Duration::ZERO).unwrap();
+        assert!(shared_lock.is_some());
+        assert!(lock_config(filepath, LockKind::Shared, Duration::ZERO)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_lock_retries_until_released() {
+        let tmp = tempfile::tempdir().unwrap();
+        let filepath = tmp.path().join("scx_loader.toml");
+        fs::write(&filepath, "").unwrap();
+        let filepath = filepath.to_str().unwrap().to_owned();
+
+        let held_lock = lock_config(&filepath, LockKind::Exclusive, Duration::ZERO).unwrap();
+        let releaser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            drop(held_lock);
+        });
+
+        let lock = lock_config(&filepath, LockKind::Exclusive, DEFAULT_LOCK_TIMEOUT).unwrap();
+        assert!(lock.is_some());
+        releaser.join().unwrap();
+    }
+
+    #[test]
+    fn test_missing_config_isnt_locked() {
+        let filepath = "/nonexistent/scx_loader.toml";
+        assert!(lock_config(filepath, LockKind::Shared, Duration::ZERO)
+            .unwrap()
+            .is_none());
+        assert!(lock_config(filepath, LockKind::Exclusive, Duration::ZERO)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_lock_follows_replaced_config() {
+        let tmp = tempfile::tempdir().unwrap();
+        let filepath = tmp.path().join("scx_loader.toml");
+        fs::write(&filepath, "").unwrap();
+        let filepath = filepath.to_str().unwrap().to_owned();
+
+        // a writer replaces the config while holding the lock of the old file
+        let held_lock = lock_config(&filepath, LockKind::Exclusive, Duration::ZERO).unwrap();
+        let tmp_filepath = format!("{filepath}.tmp");
+        fs::write(&tmp_filepath, "auto_restart = true\n").unwrap();
+        fs::rename(&tmp_filepath, &filepath).unwrap();
+        let lock = lock_config(&filepath, LockKind::Exclusive, Duration::ZERO).unwrap();
+        assert!(lock.is_some());
+        drop(held_lock);
+
+        // no lock file is left next to the config
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,142 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::fmt;
+
+use log::kv;
+use log::Level;
+use log::Record;
+
+use crate::get_name_from_scx;
+use crate::state::SchedState;
+
+/// Structured fields attached to a log record, e.g. the scheduler and mode of a switch
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LogFields(Vec<(&'static str, String)>);
+
+impl LogFields {
+    /// Fields of the given kind of event, e.g. "config_load"
+    pub fn event(event: &str) -> Self {
+        Self(vec![("event", event.to_owned())])
+    }
+
+    /// Fields of starting the scheduler with the given command line
+    pub fn for_start(event: &str, sched_state: &SchedState, argv: &[String]) -> Self {
+        let mut fields = Self::event(event).with("sched", get_name_from_scx(&sched_state.sched));
+        if let Some(sched_mode) = &sched_state.mode {
+            fields = fields.with("mode", sched_mode);
+        }
+        fields.with("argv", argv.join(" "))
+    }
+
+    /// Add the field, the value is formatted with Display
+    pub fn with(mut self, key: &'static str, value: impl fmt::Display) -> Self {
+        self.0.push((key, value.to_string()));
+        self
+    }
+}
+
+impl kv::Source for LogFields {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn kv::VisitSource<'kvs>) -> Result<(), kv::Error> {
+        for (key, value) in &self.0 {
+            visitor.visit_pair(kv::Key::from_str(key), kv::Value::from(value.as_str()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Build the record of the message with the fields attached
+pub fn build_record<'a>(
+    level: Level,
+    fields: &'a LogFields,
+    args: fmt::Arguments<'a>,
+) -> Record<'a> {
+    Record::builder()
+        .level(level)
+        .target("scx_loader")
+        .args(args)
+        .key_values(fields)
+        .build()
+}
+
+/// Log the message with the fields attached
+pub fn log_with_fields(level: Level, fields: &LogFields, args: fmt::Arguments<'_>) {
+    if level <= log::max_level() {
+        log::logger().log(&build_record(level, fields, args));
+    }
+}
+
+/// Format the fields of a record as " key=value" pairs, quoting values with spaces
+pub fn format_fields(source: &dyn kv::Source) -> String {
+    struct Formatter(String);
+
+    impl<'kvs> kv::VisitSource<'kvs> for Formatter {
+        fn visit_pair(
+            &mut self,
+            key: kv::Key<'kvs>,
+            value: kv::Value<'kvs>,
+        ) -> Result<(), kv::Error> {
+            let value = value.to_string();
+            if value.contains(char::is_whitespace) || value.is_empty() {
+                self.0.push_str(&format!(" {key}={value:?}"));
+            } else {
+                self.0.push_str(&format!(" {key}={value}"));
+            }
+            Ok(())
+        }
+    }
+
+    let mut formatter = Formatter(String::new());
+    let _ = source.visit(&mut formatter);
+    formatter.0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::log_fields::*;
+    use crate::SchedMode;
+    use crate::SupportedSched;
+
+    #[test]
+    fn test_for_start() {
+        let sched_state = SchedState::new(
+            SupportedSched::Lavd,
+            Some(SchedMode::Gaming),
+            vec!["--performance".to_owned()],
+        );
+        let argv = vec!["scx_lavd".to_owned(), "--performance".to_owned()];
+        let fields = LogFields::for_start("sched_start", &sched_state, &argv);
+
+        let formatted = format_fields(
+            build_record(Level::Info, &fields, format_args!("starting")).key_values(),
+        );
+        assert_eq!(
+            formatted,
+            " event=sched_start sched=scx_lavd mode=gaming argv=\"scx_lavd --performance\""
+        );
+
+        // started with explicit args, there is no mode
+        let sched_state = SchedState::new(SupportedSched::Bpfland, None, vec![]);
+        let fields = LogFields::for_start("sched_start", &sched_state, &["scx_bpfland".to_owned()]);
+        assert_eq!(
+            format_fields(&fields),
+            " event=sched_start sched=scx_bpfland argv=scx_bpfland"
+        );
+    }
+
+    #[test]
+    fn test_format_fields() {
+        let fields = LogFields::event("config_load")
+            .with("path", "/etc/scx_loader.toml")
+            .with("scheds", 3)
+            .with("empty", "");
+        assert_eq!(
+            format_fields(&fields),
+            " event=config_load path=/etc/scx_loader.toml scheds=3 empty=\"\""
+        );
+    }
+}
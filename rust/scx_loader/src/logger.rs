@@ -11,6 +11,7 @@ use colored::Colorize;
 use log::Level;
 use log::Metadata;
 use log::Record;
+use scx_loader::log_fields;
 
 struct SimpleLogger;
 
@@ -30,7 +31,8 @@ impl log::Log for SimpleLogger {
                 Level::Debug => "[DEBUG]".white(),
                 Level::Trace => "[TRACE]".black(),
             };
-            println!("{level_str}: {}", record.args());
+            let fields = log_fields::format_fields(record.key_values());
+            println!("{level_str}: {}{fields}", record.args());
         }
     }
 
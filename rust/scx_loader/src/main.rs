@@ -11,30 +11,87 @@ use std::process::Stdio;
 use std::sync::atomic::AtomicU32;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
 use anyhow::Context;
 use anyhow::Result;
 use clap::Parser;
-use serde::Deserialize;
-use serde::Serialize;
+use scx_loader::affinity;
+use scx_loader::cgroup::CgroupPlacement;
+use scx_loader::config;
+use scx_loader::config::Config;
+use scx_loader::debounce::Debouncer;
+use scx_loader::flags;
+use scx_loader::get_name_from_scx;
+use scx_loader::handoff;
+use scx_loader::handoff::SwitchPlan;
+use scx_loader::handoff::SwitchStep;
+use scx_loader::handoff::SwitchSteps;
+use scx_loader::health;
+use scx_loader::health::HealthChecker;
+use scx_loader::health::HealthEvent;
+use scx_loader::health::SysfsSchedExtState;
+use scx_loader::history::SwitchHistory;
+use scx_loader::hooks;
+use scx_loader::hooks::ShellCommandRunner;
+use scx_loader::kernel;
+use scx_loader::kernel::UnameKernelRelease;
+use scx_loader::list;
+use scx_loader::log_fields;
+use scx_loader::log_fields::LogFields;
+use scx_loader::metrics::Metrics;
+use scx_loader::oom::OomScoreAdj;
+use scx_loader::oom::SystemChildIo;
+use scx_loader::output;
+use scx_loader::output::FileSink;
+use scx_loader::output::RotatingWriter;
+use scx_loader::pin::Pin;
+use scx_loader::power::PowerMonitor;
+use scx_loader::power::SysfsPowerSupply;
+use scx_loader::priority;
+use scx_loader::priority::ProcessPriority;
+use scx_loader::priority::SystemPriorityControl;
+use scx_loader::reload;
+use scx_loader::reload::ReloadDecision;
+use scx_loader::restart::RestartDecision;
+use scx_loader::restart::RestartTracker;
+use scx_loader::rollback;
+use scx_loader::rollback::RollbackTracker;
+use scx_loader::sched_bin;
+use scx_loader::schedule::ScheduleFollower;
+use scx_loader::state;
+use scx_loader::state::SchedState;
+use scx_loader::status::Status;
+use scx_loader::stop;
+use scx_loader::topology;
+use scx_loader::topology::CpuTopology;
+use scx_loader::topology::SysfsCpuTopology;
+use scx_loader::topology::Topology;
+use scx_loader::watch::ConfigWatcher;
+use scx_loader::watch::InotifyWatcher;
+use scx_loader::SchedMode;
+use scx_loader::SupportedSched;
 use sysinfo::System;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
 use tokio::process::Child;
 use tokio::process::Command;
+use tokio::signal::unix::signal;
+use tokio::signal::unix::SignalKind;
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::time::Duration;
 use tokio::time::Instant;
 use zbus::interface;
 use zbus::Connection;
-use zvariant::Type;
-use zvariant::Value;
-
-#[derive(Debug, Clone, PartialEq)]
-enum SupportedSched {
-    Bpfland,
-    Rusty,
-    Lavd,
-}
+
+/// How often the schedule is checked for a change of the active window
+const SCHEDULE_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often the power supplies are checked for a change of the power source
+const POWER_POLL_INTERVAL: Duration = Duration::from_secs(10);
 
 #[derive(Debug, PartialEq)]
 enum ScxMessage {
@@ -44,38 +101,108 @@ enum ScxMessage {
     StartSchedArgs((SupportedSched, Vec<String>)),
     SwitchSched((SupportedSched, SchedMode)),
     SwitchSchedArgs((SupportedSched, Vec<String>)),
+    /// Use the reloaded config for the following messages
+    ReloadConfig(Box<Config>),
 }
 
+/// Messages for the scheduler runner, carrying the scheduler binary and the state to launch
 #[derive(Debug, PartialEq)]
 enum RunnerMessage {
-    Switch((SupportedSched, Vec<String>)),
-    Start((SupportedSched, Vec<String>)),
+    Switch((String, SchedState)),
+    Start((String, SchedState)),
+    /// Switch back to the last known good scheduler after a switch failed
+    RollBack((String, SchedState)),
     Stop,
+    /// Finish the runner, the scheduler is left as is
+    Quit,
+    /// Take the restart and health check settings from the reloaded config
+    Reconfigure(Box<Config>),
+    /// The scheduler process of the given run exited
+    Exited(u64),
+    /// Restart the scheduler of the given run after it exited unexpectedly
+    Restart(u64),
+    /// The scheduler of the given run attached to sched_ext
+    Attached(u64),
+    /// The scheduler of the given run didn't attach within the startup timeout
+    NotAttached(u64),
+}
+
+/// Settings of the scheduler runner, taken from the config
+struct RunnerOptions {
+    /// Interval of the checks that the scheduler is still attached, disabled if None
+    health_check_interval: Option<Duration>,
+    /// Time a started scheduler has to attach to sched_ext, not verified if None
+    startup_timeout: Option<Duration>,
+    /// Priority the scheduler process is run with
+    process_priority: Option<ProcessPriority>,
+    /// State of sched_ext in the configured sysfs
+    sched_ext_state: SysfsSchedExtState,
+    /// CPU topology in the configured sysfs
+    cpu_topology: SysfsCpuTopology,
+    /// Start the next scheduler before stopping the previous one on a switch
+    switch_handoff: bool,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, Type, Value, PartialEq)]
-enum SchedMode {
-    /// Default values for the scheduler
-    Auto = 0,
-    /// Applies flags for better gaming experience
-    Gaming = 1,
-    /// Applies flags for lower power usage
-    PowerSave = 2,
-    /// Starts scheduler in low latency mode
-    LowLatency = 3,
+impl RunnerOptions {
+    fn from_config(config: &Config) -> Self {
+        Self {
+            health_check_interval: config
+                .health_check_interval
+                .filter(|interval| *interval > 0)
+                .map(Duration::from_secs),
+            startup_timeout: config.startup_timeout.map(Duration::from_secs),
+            process_priority: config.process_priority.clone(),
+            sched_ext_state: SysfsSchedExtState::new(&config.get_sysfs_path()),
+            cpu_topology: SysfsCpuTopology::new(&config.get_sysfs_path()),
+            switch_handoff: config.switch_handoff.unwrap_or(false),
+        }
+    }
 }
 
 struct ScxLoader {
     current_scx: Option<SupportedSched>,
     current_mode: SchedMode,
     channel: UnboundedSender<ScxMessage>,
+    config: Config,
+    /// Scheduler actually launched by the runner
+    sched_state: Arc<Mutex<Option<SchedState>>>,
+    /// Last schedulers launched by the runner
+    history: Arc<Mutex<SwitchHistory>>,
+    /// Counters of the switches done by the runner
+    metrics: Arc<Metrics>,
+    /// Suspends the automatic switches, kept across config reloads
+    pin: Pin,
+    /// CPU topology read at startup, None if it couldn't be read
+    topology: Option<Topology>,
 }
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[clap(group(clap::ArgGroup::new("json_output").args(["status", "list"]).multiple(true)))]
 struct Args {
     #[clap(long, short, action)]
     auto: bool,
+    /// Print the effective config after merging the config files and drop-ins as TOML, then exit
+    #[clap(long, action)]
+    print_config: bool,
+    /// Validate the given config file without starting anything, exits with a nonzero status
+    /// if the config has errors
+    #[clap(long, value_name = "CONFIG")]
+    validate: Option<String>,
+    /// Also fail --validate on warnings
+    #[clap(long, action, requires = "validate")]
+    strict: bool,
+    /// Print the running scheduler, its mode, flags, uptime and restarts as reported by the
+    /// running scx_loader, then exit
+    #[clap(long, action)]
+    status: bool,
+    /// Print the supported schedulers, whether their binary is installed and which special
+    /// modes they have built-in flags for, then exit
+    #[clap(long, action)]
+    list: bool,
+    /// Print --status or --list as JSON
+    #[clap(long, action, requires = "json_output")]
+    json: bool,
 }
 
 #[interface(name = "org.scx.Loader")]
@@ -84,7 +211,7 @@ impl ScxLoader {
     #[zbus(property)]
     async fn current_scheduler(&self) -> String {
         if let Some(current_scx) = &self.current_scx {
-            let current_scx = get_name_from_scx(&current_scx).into();
+            let current_scx = current_scx.to_string();
             log::info!("called {current_scx:?}");
             return current_scx;
         }
@@ -97,10 +224,49 @@ impl ScxLoader {
         self.current_mode.clone()
     }
 
-    /// Get list of supported schedulers
+    /// Whether the automatic switches are suspended
     #[zbus(property)]
-    async fn supported_schedulers(&self) -> Vec<&str> {
-        vec!["scx_bpfland", "scx_rusty", "scx_lavd"]
+    async fn pinned(&self) -> bool {
+        self.pin.is_pinned()
+    }
+
+    /// Get list of supported schedulers, without the disabled ones and the ones the CPU
+    /// topology doesn't meet the requirements of
+    #[zbus(property)]
+    async fn supported_schedulers(&self) -> Vec<String> {
+        self.config.available_schedulers(self.topology.as_ref())
+    }
+
+    /// Get a hash of the loaded config which changes whenever the config does, so clients only
+    /// need to re-fetch details when it changed
+    #[zbus(property)]
+    async fn config_hash(&self) -> zbus::fdo::Result<String> {
+        self.config
+            .config_hash()
+            .map_err(|err| zbus::fdo::Error::Failed(err.to_string()))
+    }
+
+    /// Get the scheduler launched last as JSON, "null" if none is running
+    async fn current_state(&self) -> zbus::fdo::Result<String> {
+        let sched_state = self.sched_state.lock().unwrap();
+        state::state_to_json(sched_state.as_ref())
+            .map_err(|err| zbus::fdo::Error::Failed(err.to_string()))
+    }
+
+    /// Get the last scheduler switches as JSON, oldest first
+    async fn switch_history(&self) -> zbus::fdo::Result<String> {
+        let history = self.history.lock().unwrap();
+        history
+            .to_json()
+            .map_err(|err| zbus::fdo::Error::Failed(err.to_string()))
+    }
+
+    /// Get the switch counters as JSON, in total and per scheduler
+    async fn metrics(&self) -> zbus::fdo::Result<String> {
+        self.metrics
+            .snapshot()
+            .to_json()
+            .map_err(|err| zbus::fdo::Error::Failed(err.to_string()))
     }
 
     async fn start_scheduler(
@@ -108,7 +274,7 @@ impl ScxLoader {
         scx_name: &str,
         sched_mode: SchedMode,
     ) -> zbus::fdo::Result<()> {
-        let scx_name = get_scx_from_str(scx_name)?;
+        let scx_name = get_scx_from_str(&self.config, scx_name)?;
 
         log::info!("starting {scx_name:?} with mode {sched_mode:?}..");
 
@@ -127,7 +293,7 @@ impl ScxLoader {
         scx_name: &str,
         scx_args: Vec<String>,
     ) -> zbus::fdo::Result<()> {
-        let scx_name = get_scx_from_str(scx_name)?;
+        let scx_name = get_scx_from_str(&self.config, scx_name)?;
 
         log::info!("starting {scx_name:?} with args {scx_args:?}..");
 
@@ -146,7 +312,7 @@ impl ScxLoader {
         scx_name: &str,
         sched_mode: SchedMode,
     ) -> zbus::fdo::Result<()> {
-        let scx_name = get_scx_from_str(scx_name)?;
+        let scx_name = get_scx_from_str(&self.config, scx_name)?;
 
         log::info!("switching {scx_name:?} with mode {sched_mode:?}..");
 
@@ -160,12 +326,28 @@ impl ScxLoader {
         Ok(())
     }
 
+    async fn switch_scheduler_by_name(
+        &mut self,
+        scx_name: &str,
+        mode_name: &str,
+    ) -> zbus::fdo::Result<()> {
+        let scx_sched = config::resolve_sched_name(&self.config, scx_name)
+            .ok_or_else(|| zbus::fdo::Error::InvalidArgs(format!("{scx_name} is not supported")))?;
+        let sched_mode = self
+            .config
+            .resolve_mode(mode_name)
+            .map_err(|err: anyhow::Error| zbus::fdo::Error::InvalidArgs(err.to_string()))?;
+
+        self.switch_scheduler(&scx_sched.to_string(), sched_mode)
+            .await
+    }
+
     async fn switch_scheduler_with_args(
         &mut self,
         scx_name: &str,
         scx_args: Vec<String>,
     ) -> zbus::fdo::Result<()> {
-        let scx_name = get_scx_from_str(scx_name)?;
+        let scx_name = get_scx_from_str(&self.config, scx_name)?;
 
         log::info!("switching {scx_name:?} with args {scx_args:?}..");
 
@@ -179,14 +361,29 @@ impl ScxLoader {
         Ok(())
     }
 
+    /// Suspend the automatic switches by the schedule and the power source until unpinned
+    async fn pin_scheduler(&mut self) -> zbus::fdo::Result<()> {
+        log::info!("pinning the scheduler, automatic switches are suspended..");
+        self.pin.pin();
+        Ok(())
+    }
+
+    /// Resume the automatic switches
+    async fn unpin_scheduler(&mut self) -> zbus::fdo::Result<()> {
+        log::info!("unpinning the scheduler, automatic switches are resumed..");
+        self.pin.unpin();
+        Ok(())
+    }
+
+    /// Stop the scheduler and leave sched_ext to the kernel's default scheduler, nothing is
+    /// restarted until the next start or switch. A no-op if no scheduler is running
     async fn stop_scheduler(&mut self) -> zbus::fdo::Result<()> {
-        if let Some(current_scx) = &self.current_scx {
+        if let Some(current_scx) = self.current_scx.take() {
             let scx_name = get_name_from_scx(&current_scx);
-
             log::info!("stopping {scx_name:?}..");
-            let _ = self.channel.send(ScxMessage::StopSched);
-            self.current_scx = None;
         }
+        // also sent without a running scheduler, to cancel a restart which is still pending
+        let _ = self.channel.send(ScxMessage::StopSched);
 
         Ok(())
     }
@@ -257,6 +454,44 @@ async fn monitor_cpu_util() -> Result<()> {
     }
 }
 
+/// Query the running scx_loader over DBUS and print the status of its scheduler
+async fn print_status(json: bool) -> Result<()> {
+    let connection = Connection::system().await?;
+    let call_method = |method_name: &'static str| {
+        let connection = connection.clone();
+        async move {
+            let reply = connection
+                .call_method(
+                    Some("org.scx.Loader"),
+                    "/org/scx/Loader",
+                    Some("org.scx.Loader"),
+                    method_name,
+                    &(),
+                )
+                .await
+                .with_context(|| format!("Failed to call {method_name}, is scx_loader running?"))?;
+            reply
+                .body()
+                .deserialize::<String>()
+                .with_context(|| format!("Failed to read the reply of {method_name}"))
+        }
+    };
+    let state_json = call_method("CurrentState").await?;
+    let metrics_json = call_method("Metrics").await?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or_default();
+    let status = Status::from_json(&state_json, &metrics_json, now)?;
+    if json {
+        println!("{}", status.to_json()?);
+    } else {
+        print!("{}", status.to_table());
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // initialize the logger
@@ -264,6 +499,38 @@ async fn main() -> Result<()> {
 
     let args = Args::parse();
 
+    if let Some(config_path) = &args.validate {
+        let report = config::validate_config_file(config_path);
+        for error in &report.errors {
+            println!("error: {error}");
+        }
+        for warning in &report.warnings {
+            println!("warning: {warning}");
+        }
+        std::process::exit(report.exit_code(args.strict));
+    }
+
+    if args.status {
+        return print_status(args.json).await;
+    }
+
+    if args.list {
+        let config = config::init_config().context("Failed to initialize config")?;
+        let listings = list::list_scheds(&config, &sched_bin::get_path_dirs());
+        if args.json {
+            println!("{}", list::listing_to_json(&listings)?);
+        } else {
+            print!("{}", list::listing_to_table(&listings));
+        }
+        return Ok(());
+    }
+
+    if args.print_config {
+        let config = config::init_config().context("Failed to initialize config")?;
+        print!("{}", config::serialize_config(&config)?);
+        return Ok(());
+    }
+
     // If --auto is passed, start scx_loader as a standard background process
     // that swaps schedulers out automatically
     // based on CPU utilization without registering a dbus interface.
@@ -274,8 +541,17 @@ async fn main() -> Result<()> {
     }
 
     log::info!("Starting as dbus interface");
+    // initialize the config
+    let config = config::init_config().context("Failed to initialize config")?;
+    if let Err(validate_err) = config.validate() {
+        log::error!("{validate_err}");
+    }
+
     // setup channel
     let (channel, rx) = tokio::sync::mpsc::unbounded_channel::<ScxMessage>();
+    let sched_state = Arc::new(Mutex::new(None));
+    let history = Arc::new(Mutex::new(SwitchHistory::from_config(&config)));
+    let metrics = Arc::new(Metrics::default());
 
     let channel_clone = channel.clone();
     ctrlc::set_handler(move || {
@@ -284,6 +560,66 @@ async fn main() -> Result<()> {
     })
     .context("Error setting Ctrl-C handler")?;
 
+    // SIGTERM, e.g. sent by systemd, shuts down as well
+    let mut sigterm = signal(SignalKind::terminate()).context("Error setting SIGTERM handler")?;
+    let channel_clone = channel.clone();
+    tokio::spawn(async move {
+        sigterm.recv().await;
+        log::info!("shutting down..");
+        let _ = channel_clone.send(ScxMessage::Quit);
+    });
+
+    let topology = SysfsCpuTopology::new(&config.get_sysfs_path())
+        .read()
+        .inspect_err(|err| log::warn!("{err:#}, the schedulers aren't gated on the CPU topology"))
+        .ok();
+
+    // start the default scheduler, if one is configured, skipping the ones the topology gates
+    let (default_sched, default_mode) = match sched_bin::resolve_launch_sched(
+        &config,
+        &sched_bin::get_path_dirs(),
+        topology.as_ref(),
+    ) {
+        Ok((default_sched, default_mode)) => (Some(default_sched), default_mode),
+        Err(resolve_err) => {
+            log::error!("Failed to resolve default scheduler: {resolve_err}");
+            (None, SchedMode::Auto)
+        }
+    };
+    // a scheduler started before scx_loader, e.g. manually, is left attached. It isn't a child
+    // of the runner, so it stays unmanaged, i.e. isn't health checked, restarted or stopped,
+    // until a scheduler is started over dbus after it's gone
+    let active_sched = health::read_active_scheduler(&config.get_sysfs_path());
+    let (current_scx, current_mode) = match (default_sched, &active_sched) {
+        (Some(default_sched), Some(active_sched))
+            if get_name_from_scx(&default_sched) == active_sched =>
+        {
+            log::info!(
+                "default scheduler {default_sched} is already attached, not starting it again"
+            );
+            (Some(default_sched), default_mode)
+        }
+        (default_sched, Some(active_sched)) => {
+            if let Some(default_sched) = default_sched {
+                log::warn!("{active_sched} is already attached, not starting default scheduler {default_sched}");
+            }
+            // the flags it was started with are unknown
+            (
+                scx_loader::parse_scx_from_name(active_sched),
+                SchedMode::Auto,
+            )
+        }
+        (Some(default_sched), None) => {
+            log::info!("starting default scheduler {default_sched:?} with mode {default_mode:?}..");
+            let _ = channel.send(ScxMessage::StartSched((
+                default_sched.clone(),
+                default_mode.clone(),
+            )));
+            (Some(default_sched), default_mode)
+        }
+        (None, None) => (None, default_mode),
+    };
+
     // register dbus interface
     let connection = Connection::system().await?;
     connection
@@ -291,26 +627,273 @@ async fn main() -> Result<()> {
         .at(
             "/org/scx/Loader",
             ScxLoader {
-                current_scx: None,
-                current_mode: SchedMode::Auto,
+                current_scx,
+                current_mode,
                 channel: channel.clone(),
+                config: config.clone(),
+                sched_state: sched_state.clone(),
+                history: history.clone(),
+                metrics: metrics.clone(),
+                pin: Pin::default(),
+                topology,
             },
         )
         .await?;
 
     connection.request_name("org.scx.Loader").await?;
 
+    // reload the config on SIGHUP and, if enabled, when the config file changes
+    let (reload_tx, mut reload_rx) = tokio::sync::mpsc::unbounded_channel::<()>();
+    let mut sighup = signal(SignalKind::hangup()).context("Error setting SIGHUP handler")?;
+    let sighup_tx = reload_tx.clone();
+    tokio::spawn(async move {
+        while sighup.recv().await.is_some() {
+            let _ = sighup_tx.send(());
+        }
+    });
+    if config.watch_config.unwrap_or(false) {
+        match config::get_active_config_path() {
+            Some(config_path) => watch_config_file(config_path, reload_tx),
+            None => log::warn!("No config file is loaded, watch_config has no effect"),
+        }
+    }
+    {
+        let connection = connection.clone();
+        let channel = channel.clone();
+        tokio::spawn(async move {
+            while reload_rx.recv().await.is_some() {
+                if let Err(reload_err) = reload_config(&connection, &channel).await {
+                    log::error!("Failed to reload the config: {reload_err}");
+                }
+            }
+        });
+    }
+
+    // switch schedulers by the time of day and modes by the power source, they idle while
+    // the config has no schedule or [power] respectively, which a reload may add
+    {
+        let connection = connection.clone();
+        tokio::spawn(async move {
+            if let Err(schedule_err) = follow_schedule(connection).await {
+                log::error!("Failed to follow the schedule: {schedule_err}");
+            }
+        });
+    }
+    {
+        let connection = connection.clone();
+        tokio::spawn(async move {
+            if let Err(power_err) = follow_power_state(connection).await {
+                log::error!("Failed to follow the power state: {power_err}");
+            }
+        });
+    }
+
     // run worker/receiver loop
-    worker_loop(rx).await?;
+    worker_loop(config, sched_state, history, metrics, rx).await?;
+
+    Ok(())
+}
+
+/// Request a config reload whenever the config file changes, the watch blocks so it runs on its
+/// own thread
+fn watch_config_file(config_path: String, reload_tx: UnboundedSender<()>) {
+    std::thread::spawn(move || {
+        let watch_result = InotifyWatcher::new()
+            .context("Failed to initialize inotify")
+            .and_then(|watcher| ConfigWatcher::new(watcher, std::path::Path::new(&config_path)));
+        let mut watcher = match watch_result {
+            Ok(watcher) => watcher,
+            Err(watch_err) => {
+                log::error!("{watch_err:#}");
+                return;
+            }
+        };
+        log::info!("watching {config_path} for changes..");
+
+        loop {
+            if let Err(watch_err) = watcher.wait_for_change() {
+                log::error!("Stopped watching the config: {watch_err:#}");
+                return;
+            }
+            if reload_tx.send(()).is_err() {
+                return;
+            }
+        }
+    });
+}
 
+/// Reload the config files and apply them, restarting the running scheduler only if its
+/// settings changed. A config which fails to load is logged and the old one is kept
+async fn reload_config(
+    connection: &Connection,
+    channel: &UnboundedSender<ScxMessage>,
+) -> Result<()> {
+    log::info!("reloading config..");
+    // reading the config may wait for its lock, don't block the runtime nor hold the interface
+    let new_config = tokio::task::spawn_blocking(config::init_config)
+        .await
+        .context("Failed to load the config")?;
+    let iface_ref = connection
+        .object_server()
+        .interface::<_, ScxLoader>("/org/scx/Loader")
+        .await?;
+    let mut iface = iface_ref.get_mut().await;
+    let running_state = iface.sched_state.lock().unwrap().clone();
+    let running_sched = running_state.as_ref().map(|sched_state| &sched_state.sched);
+
+    match reload::decide_reload(&iface.config, new_config, running_sched) {
+        ReloadDecision::KeepOld(reload_err) => {
+            log::error!("Failed to reload the config, keeping the old one: {reload_err}");
+        }
+        ReloadDecision::Apply {
+            config,
+            restart,
+            deferred,
+        } => {
+            iface.config = (*config).clone();
+            iface
+                .config_hash_changed(iface_ref.signal_context())
+                .await?;
+            let _ = channel.send(ScxMessage::ReloadConfig(config));
+            match running_state {
+                Some(SchedState {
+                    sched,
+                    mode: Some(sched_mode),
+                    ..
+                }) if restart => {
+                    // the mode may select another scheduler with the new config
+                    let sched = config::get_mode_sched(&iface.config, &sched_mode)
+                        .cloned()
+                        .unwrap_or(sched);
+                    log::info!("restarting {sched} to apply the new config..");
+                    let _ = channel.send(ScxMessage::SwitchSched((sched, sched_mode)));
+                }
+                Some(sched_state) if restart => {
+                    log::warn!(
+                        "{} was started with explicit args, switch it again to apply the new config",
+                        sched_state.sched
+                    );
+                }
+                Some(sched_state) if deferred => {
+                    log::info!(
+                        "{} has restart_on_change disabled, the new config applies with the next switch",
+                        sched_state.sched
+                    );
+                }
+                _ => {}
+            }
+        }
+    }
     Ok(())
 }
 
-async fn worker_loop(mut receiver: UnboundedReceiver<ScxMessage>) -> Result<()> {
+/// Switch to the scheduler of the active schedule window whenever the window changes, unless
+/// the scheduler is pinned
+async fn follow_schedule(connection: Connection) -> Result<()> {
+    let iface_ref = connection
+        .object_server()
+        .interface::<_, ScxLoader>("/org/scx/Loader")
+        .await?;
+    let mut timer = tokio::time::interval(SCHEDULE_POLL_INTERVAL);
+    let mut schedule_follower = ScheduleFollower::default();
+
+    loop {
+        timer.tick().await;
+        let mut iface = iface_ref.get_mut().await;
+        // the config is read on every tick, so a reloaded schedule applies right away
+        let scx_loader = &mut *iface;
+        let Some((scx_sched, sched_mode)) = scx_loader
+            .pin
+            .resolve(|| schedule_follower.poll(&scx_loader.config, chrono::Local::now().time()))
+        else {
+            continue;
+        };
+        log::info!("schedule window changed, switching to {scx_sched} with mode {sched_mode}..");
+        // e.g. a reload removed the custom scheduler of the window, the next window may work
+        if let Err(switch_err) = scx_loader
+            .switch_scheduler(&scx_sched.to_string(), sched_mode)
+            .await
+        {
+            log::warn!("Failed to switch to the scheduler of the schedule window: {switch_err}");
+        }
+    }
+}
+
+/// Switch the mode of the running scheduler whenever the power source changes, unless the
+/// scheduler is pinned
+async fn follow_power_state(connection: Connection) -> Result<()> {
+    let iface_ref = connection
+        .object_server()
+        .interface::<_, ScxLoader>("/org/scx/Loader")
+        .await?;
+    let mut power_monitor = PowerMonitor::default();
+    let mut timer = tokio::time::interval(POWER_POLL_INTERVAL);
+
+    loop {
+        timer.tick().await;
+        let mut iface = iface_ref.get_mut().await;
+        // the config is read on every tick, so a reloaded [power] applies right away
+        let scx_loader = &mut *iface;
+        let Some(power_config) = &scx_loader.config.power else {
+            // once configured again, the power state at that time counts as a change
+            power_monitor = PowerMonitor::default();
+            continue;
+        };
+        let power_supply = SysfsPowerSupply::from_sysfs(&scx_loader.config.get_sysfs_path());
+        let Some(sched_mode) = scx_loader
+            .pin
+            .resolve(|| power_monitor.poll(power_config, &power_supply))
+        else {
+            continue;
+        };
+        let Some(current_scx) = scx_loader.current_scx.clone() else {
+            continue;
+        };
+        if scx_loader.current_mode == sched_mode {
+            continue;
+        }
+        // the mode may select another scheduler
+        let scx_sched = config::get_mode_sched(&scx_loader.config, &sched_mode)
+            .cloned()
+            .unwrap_or(current_scx);
+        log::info!("power source changed, switching {scx_sched} to mode {sched_mode}..");
+        if let Err(switch_err) = scx_loader
+            .switch_scheduler(&scx_sched.to_string(), sched_mode)
+            .await
+        {
+            log::warn!("Failed to switch to the mode of the power source: {switch_err}");
+        }
+    }
+}
+
+async fn worker_loop(
+    mut config: Config,
+    sched_state: Arc<Mutex<Option<SchedState>>>,
+    history: Arc<Mutex<SwitchHistory>>,
+    metrics: Arc<Metrics>,
+    mut receiver: UnboundedReceiver<ScxMessage>,
+) -> Result<()> {
     // setup channel for scheduler runner
     let (runner_tx, runner_rx) = tokio::sync::mpsc::channel::<RunnerMessage>(1);
 
-    let run_sched_future = tokio::spawn(async move { handle_child_process(runner_rx).await });
+    let restart_tracker = RestartTracker::from_config(&config);
+    let runner_options = RunnerOptions::from_config(&config);
+    let exit_tx = runner_tx.clone();
+    let mut switch_debouncer = Debouncer::new(config.get_switch_debounce());
+    let runner_metrics = metrics.clone();
+    let running_state = sched_state.clone();
+    let run_sched_future = tokio::spawn(async move {
+        handle_child_process(
+            restart_tracker,
+            runner_options,
+            sched_state,
+            history,
+            runner_metrics,
+            exit_tx,
+            runner_rx,
+        )
+        .await
+    });
 
     // prepare future for tokio
     tokio::pin!(run_sched_future);
@@ -319,9 +902,36 @@ async fn worker_loop(mut receiver: UnboundedReceiver<ScxMessage>) -> Result<()>
         // handle each future here
         let msg = tokio::select! {
             msg = receiver.recv() => {
-                match msg {
+                let msg = match msg {
                     None => return Ok(()),
                     Some(m) => m,
+                };
+                match msg {
+                    ScxMessage::SwitchSched(_) | ScxMessage::SwitchSchedArgs(_)
+                        if switch_debouncer.is_enabled() =>
+                    {
+                        // applied once the debounce window is over, unless replaced before
+                        if let Some(replaced) = switch_debouncer.push(msg, std::time::Instant::now()) {
+                            record_coalesced(&metrics, &replaced);
+                        }
+                        continue;
+                    }
+                    ScxMessage::StopSched
+                    | ScxMessage::StartSched(_)
+                    | ScxMessage::StartSchedArgs(_) => {
+                        // the later request supersedes the pending switch
+                        if let Some(cancelled) = switch_debouncer.cancel() {
+                            record_coalesced(&metrics, &cancelled);
+                        }
+                        msg
+                    }
+                    msg => msg,
+                }
+            }
+            _ = sleep_until_deadline(switch_debouncer.deadline()) => {
+                match switch_debouncer.poll(std::time::Instant::now()) {
+                    Some(msg) => msg,
+                    None => continue,
                 }
             }
             res = &mut run_sched_future => {
@@ -333,7 +943,26 @@ async fn worker_loop(mut receiver: UnboundedReceiver<ScxMessage>) -> Result<()>
         log::debug!("Got msg : {msg:?}");
 
         match msg {
-            ScxMessage::Quit => return Ok(()),
+            ScxMessage::Quit => {
+                let sched_running = running_state.lock().unwrap().is_some();
+                if !stop::stops_on_shutdown(config.get_stop_behavior(), sched_running) {
+                    log::info!("leaving the scheduler running");
+                    return Ok(());
+                }
+                // stop the scheduler and wait for the runner to finish it
+                runner_tx.send(RunnerMessage::Stop).await?;
+                runner_tx.send(RunnerMessage::Quit).await?;
+                return (&mut run_sched_future).await?;
+            }
+            ScxMessage::ReloadConfig(new_config) => {
+                log::info!("Got event to reload config!");
+
+                config = *new_config;
+                switch_debouncer.set_window(config.get_switch_debounce());
+                runner_tx
+                    .send(RunnerMessage::Reconfigure(Box::new(config.clone())))
+                    .await?;
+            }
             ScxMessage::StopSched => {
                 log::info!("Got event to stop scheduler!");
 
@@ -344,89 +973,422 @@ async fn worker_loop(mut receiver: UnboundedReceiver<ScxMessage>) -> Result<()>
                 log::info!("Got event to start scheduler!");
 
                 // get scheduler args for the mode
-                let args: Vec<_> = get_scx_flags_for_mode(&scx_sched, sched_mode)
-                    .into_iter()
-                    .map(String::from)
-                    .collect();
+                let args = config::get_scx_flags_for_mode(&config, &scx_sched, sched_mode.clone());
+                let sched = build_sched_state(&config, scx_sched, Some(sched_mode), args);
 
                 // send message with scheduler and asociated args to the runner
-                runner_tx
-                    .send(RunnerMessage::Start((scx_sched, args)))
-                    .await?;
+                runner_tx.send(RunnerMessage::Start(sched)).await?;
             }
             ScxMessage::StartSchedArgs((scx_sched, sched_args)) => {
                 log::info!("Got event to start scheduler with args!");
 
+                let sched = build_sched_state(&config, scx_sched, None, sched_args);
+
                 // send message with scheduler and asociated args to the runner
-                runner_tx
-                    .send(RunnerMessage::Start((scx_sched, sched_args)))
-                    .await?;
+                runner_tx.send(RunnerMessage::Start(sched)).await?;
             }
             ScxMessage::SwitchSched((scx_sched, sched_mode)) => {
                 log::info!("Got event to switch scheduler!");
 
                 // get scheduler args for the mode
-                let args: Vec<_> = get_scx_flags_for_mode(&scx_sched, sched_mode)
-                    .into_iter()
-                    .map(String::from)
-                    .collect();
+                let args = config::get_scx_flags_for_mode(&config, &scx_sched, sched_mode.clone());
+                let sched = build_sched_state(&config, scx_sched, Some(sched_mode), args);
 
                 // send message with scheduler and asociated args to the runner
-                runner_tx
-                    .send(RunnerMessage::Switch((scx_sched, args)))
-                    .await?;
+                runner_tx.send(RunnerMessage::Switch(sched)).await?;
             }
             ScxMessage::SwitchSchedArgs((scx_sched, sched_args)) => {
                 log::info!("Got event to switch scheduler with args!");
 
+                let sched = build_sched_state(&config, scx_sched, None, sched_args);
+
                 // send message with scheduler and asociated args to the runner
-                runner_tx
-                    .send(RunnerMessage::Switch((scx_sched, sched_args)))
-                    .await?;
+                runner_tx.send(RunnerMessage::Switch(sched)).await?;
             }
         }
     }
 }
 
-async fn handle_child_process(mut rx: tokio::sync::mpsc::Receiver<RunnerMessage>) -> Result<()> {
-    let child_id = Arc::new(AtomicU32::new(0));
+async fn handle_child_process(
+    mut restart_tracker: Option<RestartTracker>,
+    runner_options: RunnerOptions,
+    sched_state: Arc<Mutex<Option<SchedState>>>,
+    history: Arc<Mutex<SwitchHistory>>,
+    metrics: Arc<Metrics>,
+    runner_tx: tokio::sync::mpsc::Sender<RunnerMessage>,
+    mut rx: tokio::sync::mpsc::Receiver<RunnerMessage>,
+) -> Result<()> {
+    let RunnerOptions {
+        health_check_interval,
+        mut startup_timeout,
+        mut process_priority,
+        mut sched_ext_state,
+        mut cpu_topology,
+        mut switch_handoff,
+    } = runner_options;
+    let mut child_id = Arc::new(AtomicU32::new(0));
+    let mut health_checker = HealthChecker::default();
+    let mut health_check_timer = health_check_interval.map(tokio::time::interval);
+    // scheduler which is supposed to be running, restarted if it exits unexpectedly
+    let mut current_sched: Option<(String, SchedState)> = None;
+    // bumped by every requested start and stop, which tells their exits apart from crashes
+    let mut run_id: u64 = 0;
+    let mut rollback = RollbackTracker::default();
 
-    while let Some(message) = rx.recv().await {
-        match message {
-            RunnerMessage::Switch((scx_sched, sched_args)) => {
-                // stop the sched if its running
+    loop {
+        let message = tokio::select! {
+            message = rx.recv() => match message {
+                Some(message) => message,
+                None => break,
+            },
+            _ = tick_health_check(&mut health_check_timer) => {
+                let Some((sched_bin, current_state)) = &current_sched else {
+                    continue;
+                };
+                if child_id.load(Ordering::Relaxed) == 0 {
+                    continue;
+                }
+                let health_event = health_checker.check(&sched_ext_state, sched_bin);
+                if health_event == Some(HealthEvent::Attached) {
+                    rollback.attached(run_id, sched_bin, current_state);
+                }
+                if health_event != Some(HealthEvent::Detached) {
+                    continue;
+                }
+                log::warn!("{sched_bin} is no longer attached to sched_ext");
+                if restart_tracker.is_none() {
+                    continue;
+                }
+
+                // the exit caused by stopping it is handled here, not as a crash
+                run_id += 1;
                 if let Err(stop_err) = stop_scheduler(child_id.clone()).await {
-                    log::error!("Failed to stop previous scheduler: {stop_err}");
+                    log::error!("Failed to stop detached scheduler: {stop_err}");
                 }
+                *sched_state.lock().unwrap() = None;
+                schedule_restart(&mut restart_tracker, &mut current_sched, run_id, &runner_tx);
+                continue;
+            }
+        };
 
-                // overwise start scheduler
-                if let Err(sched_err) =
-                    start_scheduler(scx_sched, sched_args, child_id.clone()).await
-                {
+        let is_rollback = matches!(message, RunnerMessage::RollBack(_));
+        match message {
+            RunnerMessage::Switch((sched_bin, mut new_state))
+            | RunnerMessage::RollBack((sched_bin, mut new_state)) => {
+                let running = current_sched
+                    .as_ref()
+                    .filter(|_| child_id.load(Ordering::Relaxed) != 0)
+                    .map(|(running_bin, running_state)| (running_bin.as_str(), running_state));
+                if handoff::is_noop_switch(running, &sched_bin, &new_state) {
+                    log::info!(
+                        "{sched_bin} already runs with flags {:?} and the same settings, not restarting it",
+                        new_state.flags
+                    );
+                    // only the mode changed, report the requested one
+                    if let Some((_, running_state)) = &mut current_sched {
+                        running_state.mode = new_state.mode;
+                        *sched_state.lock().unwrap() = Some(running_state.clone());
+                    }
+                    continue;
+                }
+
+                run_id += 1;
+                let previous_sched = current_sched.take();
+                let running_bin = previous_sched
+                    .as_ref()
+                    .filter(|_| child_id.load(Ordering::Relaxed) != 0)
+                    .map(|(previous_bin, _)| previous_bin.clone());
+                let plan = handoff::plan_switch(running_bin.as_deref(), &sched_bin, switch_handoff);
+                // the previous scheduler keeps running during a handoff, track it separately
+                let previous_child = match plan {
+                    SwitchPlan::Handoff => {
+                        std::mem::replace(&mut child_id, Arc::new(AtomicU32::new(0)))
+                    }
+                    SwitchPlan::StopThenStart => child_id.clone(),
+                };
+                let mut previous_hooks =
+                    previous_sched.map(|(_, previous_state)| previous_state.hooks);
+
+                *sched_state.lock().unwrap() = None;
+                if let Some(restart_tracker) = &mut restart_tracker {
+                    restart_tracker.reset();
+                }
+                health_checker.reset();
+
+                let mut switch_steps = SwitchSteps::new(plan);
+                let mut prestart_err = None;
+                let mut start_result = Ok(());
+                while let Some(step) = switch_steps.next_step() {
+                    let succeeded = match step {
+                        SwitchStep::StopPrevious => {
+                            if let Err(stop_err) = stop_scheduler(previous_child.clone()).await {
+                                log::error!("Failed to stop previous scheduler: {stop_err}");
+                            }
+                            if let Some(previous_hooks) = previous_hooks.take() {
+                                hooks::run_poststop(&ShellCommandRunner, &previous_hooks);
+                            }
+                            true
+                        }
+                        SwitchStep::Prestart => {
+                            match hooks::run_prestart(&ShellCommandRunner, &new_state.hooks) {
+                                Ok(()) => true,
+                                Err(hook_err) => {
+                                    prestart_err = Some(hook_err);
+                                    false
+                                }
+                            }
+                        }
+                        SwitchStep::StartNext => {
+                            new_state.mark_started();
+                            start_result = start_scheduler(
+                                sched_bin.clone(),
+                                &new_state,
+                                child_id.clone(),
+                                run_id,
+                                process_priority.as_ref(),
+                                &cpu_topology,
+                                runner_tx.clone(),
+                            )
+                            .await;
+                            start_result.is_ok()
+                        }
+                        SwitchStep::WaitForAttach => {
+                            let handoff_timeout =
+                                startup_timeout.unwrap_or(health::DEFAULT_ATTACH_TIMEOUT);
+                            // a next scheduler refused by sched_ext exits, don't wait for it
+                            let attach_result = health::wait_for_attach_while(
+                                &sched_ext_state,
+                                &sched_bin,
+                                handoff_timeout,
+                                || child_id.load(Ordering::Relaxed) != 0,
+                            )
+                            .await;
+                            if let Err(attach_err) = &attach_result {
+                                log::warn!("{attach_err}, stopping the previous scheduler first");
+                            }
+                            attach_result.is_ok()
+                        }
+                        SwitchStep::StopNext => {
+                            // its exit is part of the fallback, not a crash
+                            run_id += 1;
+                            if let Err(stop_err) = stop_scheduler(child_id.clone()).await {
+                                log::error!("Failed to stop scheduler: {stop_err}");
+                            }
+                            true
+                        }
+                    };
+                    if !succeeded {
+                        switch_steps.failed(step);
+                    }
+                }
+
+                if is_rollback {
+                    rollback.rollback_started(run_id);
+                } else {
+                    rollback.switch_started(run_id, &sched_bin, &new_state);
+                }
+                if let Some(hook_err) = prestart_err {
+                    log::error!("Not switching to {sched_bin}: {hook_err:#}");
+                    roll_back(
+                        &mut rollback,
+                        run_id,
+                        &new_state,
+                        &format!("{hook_err:#}"),
+                        &runner_tx,
+                    );
+                    continue;
+                }
+                current_sched = Some((sched_bin.clone(), new_state.clone()));
+                if let Err(sched_err) = start_result {
                     log::error!("Scheduler exited with err: {sched_err}");
+                    metrics.record_failed_start(&new_state.sched);
+                    if roll_back(
+                        &mut rollback,
+                        run_id,
+                        &new_state,
+                        &format!("{sched_err:#}"),
+                        &runner_tx,
+                    ) {
+                        current_sched = None;
+                    }
                 } else {
                     log::debug!("Scheduler exited");
+                    metrics.record_switch(&new_state.sched);
+                    let reason = if is_rollback { "rollback" } else { "switch" };
+                    history.lock().unwrap().record(new_state.clone(), reason);
+                    verify_attach(
+                        &sched_ext_state,
+                        startup_timeout,
+                        sched_bin,
+                        run_id,
+                        &runner_tx,
+                    );
+                    *sched_state.lock().unwrap() = Some(new_state);
                 }
             }
-            RunnerMessage::Start((scx_sched, sched_args)) => {
+            RunnerMessage::Start((sched_bin, mut new_state)) => {
                 // check if sched is running or not
                 if child_id.load(Ordering::Relaxed) != 0 {
                     log::error!("Scheduler wasn't finished yet. Stop already running scheduler!");
                     continue;
                 }
+
+                run_id += 1;
+                *sched_state.lock().unwrap() = None;
+                if let Err(hook_err) = hooks::run_prestart(&ShellCommandRunner, &new_state.hooks) {
+                    log::error!("Not starting {sched_bin}: {hook_err:#}");
+                    continue;
+                }
+                new_state.mark_started();
+                current_sched = Some((sched_bin.clone(), new_state.clone()));
+                if let Some(restart_tracker) = &mut restart_tracker {
+                    restart_tracker.reset();
+                }
+                health_checker.reset();
+
                 // overwise start scheduler
-                if let Err(sched_err) =
-                    start_scheduler(scx_sched, sched_args, child_id.clone()).await
+                if let Err(sched_err) = start_scheduler(
+                    sched_bin.clone(),
+                    &new_state,
+                    child_id.clone(),
+                    run_id,
+                    process_priority.as_ref(),
+                    &cpu_topology,
+                    runner_tx.clone(),
+                )
+                .await
                 {
                     log::error!("Scheduler exited with err: {sched_err}");
+                    metrics.record_failed_start(&new_state.sched);
                 } else {
                     log::debug!("Scheduler exited");
+                    metrics.record_switch(&new_state.sched);
+                    history.lock().unwrap().record(new_state.clone(), "start");
+                    verify_attach(
+                        &sched_ext_state,
+                        startup_timeout,
+                        sched_bin,
+                        run_id,
+                        &runner_tx,
+                    );
+                    *sched_state.lock().unwrap() = Some(new_state);
                 }
             }
             RunnerMessage::Stop => {
+                // a pending restart or rollback belongs to the previous run and is dropped
+                run_id += 1;
+                *sched_state.lock().unwrap() = None;
                 if let Err(stop_err) = stop_scheduler(child_id.clone()).await {
                     log::error!("Failed to stop scheduler: {stop_err}");
                 }
+                let stopped = stop::stop_managed(
+                    &mut current_sched,
+                    &mut rollback,
+                    &mut history.lock().unwrap(),
+                );
+                if let Some((_, stopped_state)) = stopped {
+                    hooks::run_poststop(&ShellCommandRunner, &stopped_state.hooks);
+                }
+            }
+            RunnerMessage::Quit => break,
+            RunnerMessage::Reconfigure(config) => {
+                // the health checks apply right away, the other options from the next start on
+                let runner_options = RunnerOptions::from_config(&config);
+                startup_timeout = runner_options.startup_timeout;
+                process_priority = runner_options.process_priority;
+                sched_ext_state = runner_options.sched_ext_state;
+                cpu_topology = runner_options.cpu_topology;
+                switch_handoff = runner_options.switch_handoff;
+                health_check_timer = runner_options
+                    .health_check_interval
+                    .map(tokio::time::interval);
+                restart_tracker = RestartTracker::from_config(&config);
+            }
+            RunnerMessage::Exited(exited_run_id) => {
+                // requested stops and switches already moved on to the next run
+                if exited_run_id != run_id {
+                    continue;
+                }
+                let Some((sched_bin, exited_state)) = &current_sched else {
+                    continue;
+                };
+                log::warn!("{sched_bin} exited unexpectedly");
+                *sched_state.lock().unwrap() = None;
+                if roll_back(
+                    &mut rollback,
+                    run_id,
+                    exited_state,
+                    "it exited before attaching to sched_ext",
+                    &runner_tx,
+                ) {
+                    current_sched = None;
+                    continue;
+                }
+                schedule_restart(&mut restart_tracker, &mut current_sched, run_id, &runner_tx);
+            }
+            RunnerMessage::NotAttached(failed_run_id) => {
+                if failed_run_id != run_id {
+                    continue;
+                }
+                if let (Some((sched_bin, failed_state)), Some(startup_timeout)) =
+                    (&current_sched, startup_timeout)
+                {
+                    metrics.record_failed_start(&failed_state.sched);
+                    log::error!("{sched_bin} failed to start, it didn't attach to sched_ext within {startup_timeout:?}");
+                    roll_back(
+                        &mut rollback,
+                        run_id,
+                        failed_state,
+                        &format!("it didn't attach to sched_ext within {startup_timeout:?}"),
+                        &runner_tx,
+                    );
+                }
+            }
+            RunnerMessage::Attached(attached_run_id) => {
+                if attached_run_id != run_id {
+                    continue;
+                }
+                if let Some((sched_bin, attached_state)) = &current_sched {
+                    rollback.attached(run_id, sched_bin, attached_state);
+                }
+            }
+            RunnerMessage::Restart(restart_run_id) => {
+                if restart_run_id != run_id || child_id.load(Ordering::Relaxed) != 0 {
+                    continue;
+                }
+                let Some((sched_bin, restarted_state)) = &mut current_sched else {
+                    continue;
+                };
+                health_checker.reset();
+                restarted_state.mark_started();
+                if let Err(sched_err) = start_scheduler(
+                    sched_bin.clone(),
+                    restarted_state,
+                    child_id.clone(),
+                    run_id,
+                    process_priority.as_ref(),
+                    &cpu_topology,
+                    runner_tx.clone(),
+                )
+                .await
+                {
+                    log::error!("Failed to restart scheduler: {sched_err}");
+                    metrics.record_failed_start(&restarted_state.sched);
+                } else {
+                    metrics.record_restart(&restarted_state.sched);
+                    history
+                        .lock()
+                        .unwrap()
+                        .record(restarted_state.clone(), "restart");
+                    verify_attach(
+                        &sched_ext_state,
+                        startup_timeout,
+                        sched_bin.clone(),
+                        run_id,
+                        &runner_tx,
+                    );
+                    *sched_state.lock().unwrap() = Some(restarted_state.clone());
+                }
             }
         }
     }
@@ -434,35 +1396,275 @@ async fn handle_child_process(mut rx: tokio::sync::mpsc::Receiver<RunnerMessage>
     Ok(())
 }
 
+/// Get the CPU affinity of the scheduler in the mode, an invalid CPU list is logged and ignored
+fn get_cpu_affinity(
+    config: &Config,
+    scx_sched: &SupportedSched,
+    sched_mode: &SchedMode,
+) -> Option<Vec<usize>> {
+    config::get_cpu_affinity(config, scx_sched, sched_mode).unwrap_or_else(|affinity_err| {
+        log::error!(
+            "Ignoring the cpu_affinity of {}: {affinity_err:#}",
+            get_name_from_scx(scx_sched)
+        );
+        None
+    })
+}
+
+/// Get the binary and the state to launch the scheduler with the given flags. The
+/// cpu_affinity of the mode only applies if it's started in a mode, not with explicit args
+fn build_sched_state(
+    config: &Config,
+    scx_sched: SupportedSched,
+    sched_mode: Option<SchedMode>,
+    flags: Vec<String>,
+) -> (String, SchedState) {
+    let sched_bin = config::get_scx_bin_path(config, &scx_sched);
+    let cpu_affinity = sched_mode
+        .as_ref()
+        .and_then(|sched_mode| get_cpu_affinity(config, &scx_sched, sched_mode));
+    let mut sched_state = SchedState::new(scx_sched, sched_mode, flags);
+    sched_state.cpu_affinity = cpu_affinity;
+    sched_state.hooks = config.get_hooks(&sched_state.sched);
+    sched_state.min_kernel = config.get_min_kernel(&sched_state.sched);
+    sched_state.topology = config.get_topology_requirement(&sched_state.sched);
+    sched_state.env = config.get_sched_env(&sched_state.sched);
+    sched_state.oom_score_adj = config.get_oom_score_adj(&sched_state.sched);
+    sched_state.log_output = config.get_log_output(&sched_state.sched);
+    sched_state.cgroup = config.get_cgroup(&sched_state.sched);
+    sched_state.strict_flags = config.strict_flags.unwrap_or(false);
+    (sched_bin, sched_state)
+}
+
+/// Count the switch request dropped by the debouncer
+fn record_coalesced(metrics: &Metrics, msg: &ScxMessage) {
+    if let ScxMessage::SwitchSched((scx_sched, _)) | ScxMessage::SwitchSchedArgs((scx_sched, _)) =
+        msg
+    {
+        log::info!("coalesced the switch to {scx_sched} with a later request");
+        metrics.record_coalesced(scx_sched);
+    }
+}
+
+/// Wait until the debounce window of the pending switch is over, never completes if no switch
+/// is pending
+async fn sleep_until_deadline(deadline: Option<std::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(Instant::from_std(deadline)).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Wait for the next health check, never completes if health checks are disabled
+async fn tick_health_check(timer: &mut Option<tokio::time::Interval>) {
+    match timer {
+        Some(timer) => {
+            timer.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Check in the background that the scheduler of the given run attaches within the startup
+/// timeout, or the default one to confirm the attach if none is configured
+fn verify_attach(
+    sched_ext_state: &SysfsSchedExtState,
+    startup_timeout: Option<Duration>,
+    sched_bin: String,
+    run_id: u64,
+    runner_tx: &tokio::sync::mpsc::Sender<RunnerMessage>,
+) {
+    let attach_timeout = startup_timeout.unwrap_or(health::DEFAULT_ATTACH_TIMEOUT);
+    let runner_tx = runner_tx.clone();
+    let sched_ext_state = sched_ext_state.clone();
+    tokio::spawn(async move {
+        let message =
+            match health::wait_for_attach(&sched_ext_state, &sched_bin, attach_timeout).await {
+                Ok(()) => RunnerMessage::Attached(run_id),
+                Err(_) => RunnerMessage::NotAttached(run_id),
+            };
+        let _ = runner_tx.send(message).await;
+    });
+}
+
+/// Switch back to the last known good scheduler if the failed run is a switch which wasn't
+/// confirmed attached yet, returns whether it's rolled back
+fn roll_back(
+    rollback: &mut RollbackTracker,
+    failed_run_id: u64,
+    failed_state: &SchedState,
+    failure: &str,
+    runner_tx: &tokio::sync::mpsc::Sender<RunnerMessage>,
+) -> bool {
+    let Some((good_bin, good_state)) = rollback.failed(failed_run_id) else {
+        return false;
+    };
+    log::error!(
+        "{}",
+        rollback::rollback_error(failed_state, failure, &good_state)
+    );
+    // the runner is busy handling the current message, send it in the background
+    let rollback_tx = runner_tx.clone();
+    tokio::spawn(async move {
+        let _ = rollback_tx
+            .send(RunnerMessage::RollBack((good_bin, good_state)))
+            .await;
+    });
+    true
+}
+
+/// Restart the current scheduler of the given run after it exited or detached unexpectedly,
+/// unless auto_restart is disabled or the restart limit is reached
+fn schedule_restart(
+    restart_tracker: &mut Option<RestartTracker>,
+    current_sched: &mut Option<(String, SchedState)>,
+    run_id: u64,
+    runner_tx: &tokio::sync::mpsc::Sender<RunnerMessage>,
+) {
+    let Some((sched_bin, _)) = current_sched.as_ref() else {
+        return;
+    };
+    let Some(restart_tracker) = restart_tracker else {
+        *current_sched = None;
+        return;
+    };
+
+    match restart_tracker.on_exit(std::time::Instant::now()) {
+        RestartDecision::Restart { attempt, delay } => {
+            log::info!("restarting {sched_bin} in {delay:?} (attempt {attempt})..");
+            let restart_tx = runner_tx.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(delay).await;
+                let _ = restart_tx.send(RunnerMessage::Restart(run_id)).await;
+            });
+        }
+        RestartDecision::GiveUp => {
+            log::error!("{sched_bin} keeps exiting, giving up restarting it");
+            *current_sched = None;
+        }
+    }
+}
+
 /// Start the scheduler with the given arguments
 async fn start_scheduler(
-    scx_crate: SupportedSched,
-    args: Vec<String>,
+    sched_bin: String,
+    sched_state: &SchedState,
     child_id: Arc<AtomicU32>,
+    run_id: u64,
+    process_priority: Option<&ProcessPriority>,
+    cpu_topology: &SysfsCpuTopology,
+    exit_tx: tokio::sync::mpsc::Sender<RunnerMessage>,
 ) -> Result<()> {
-    let sched_bin_name = get_name_from_scx(&scx_crate);
-    log::info!("starting {sched_bin_name} command");
+    if let Some(min_kernel) = sched_state.min_kernel {
+        kernel::check_min_kernel(&UnameKernelRelease, &sched_bin, min_kernel)?;
+    }
+    topology::check_topology(cpu_topology, &sched_bin, &sched_state.topology)?;
+    config::check_required_flags(&sched_state.sched, &sched_state.flags)?;
+    flags::enforce_strict_flags(sched_state.strict_flags, &sched_bin, &sched_state.flags)?;
+
+    let mut argv = vec![sched_bin.clone()];
+    argv.extend(sched_state.flags.iter().cloned());
+    log_fields::log_with_fields(
+        log::Level::Info,
+        &LogFields::for_start("sched_start", sched_state, &argv),
+        format_args!("starting {}", argv.join(" ")),
+    );
 
-    let mut cmd = Command::new(sched_bin_name);
+    let mut cmd = Command::new(&sched_bin);
     // set arguments
-    cmd.args(args);
+    cmd.args(&sched_state.flags);
+    // set the environment of the scheduler only, on top of the inherited one
+    cmd.envs(sched_state.env.iter().cloned());
 
     // by default child IO handles are inherited from parent process
 
     // pipe stdin of child proc to /dev/null
     cmd.stdin(Stdio::null());
 
+    if let Some(oom_score_adj) = sched_state.oom_score_adj {
+        match OomScoreAdj::new(oom_score_adj) {
+            // SAFETY: applying the adjustment only does raw syscalls on preformatted buffers
+            Ok(oom_score_adj) => unsafe {
+                cmd.pre_exec(move || {
+                    oom_score_adj.apply_in_child(&SystemChildIo);
+                    Ok(())
+                });
+            },
+            Err(err) => log::error!("Not setting oom_score_adj of {sched_bin}: {err}"),
+        }
+    }
+    if let Some(cgroup) = &sched_state.cgroup {
+        match CgroupPlacement::new(cgroup) {
+            // SAFETY: applying the placement only does raw syscalls on preformatted buffers
+            Ok(placement) => unsafe {
+                cmd.pre_exec(move || placement.apply_in_child(&SystemChildIo));
+            },
+            Err(err) if cgroup.required => {
+                return Err(err.context(format!("{sched_bin} can't be placed in its cgroup")));
+            }
+            Err(err) => log::error!("Not placing {sched_bin} in its cgroup: {err:#}"),
+        }
+    }
+
+    // write the output to the log file if configured, otherwise it's inherited. A scheduler
+    // which outlives the loader writes to the file itself
+    if let Some(log_output) = sched_state
+        .log_output
+        .as_ref()
+        .filter(|log_output| log_output.direct)
+    {
+        match output::open_log_file(&log_output.path).and_then(|log_file| {
+            let stderr_file = log_file
+                .try_clone()
+                .context("Failed to duplicate the log file")?;
+            Ok((log_file, stderr_file))
+        }) {
+            Ok((stdout_file, stderr_file)) => {
+                cmd.stdout(stdout_file);
+                cmd.stderr(stderr_file);
+            }
+            Err(err) => log::error!("{err:#}, not capturing the output of {sched_bin}"),
+        }
+    }
+    let log_writer = sched_state
+        .log_output
+        .as_ref()
+        .filter(|log_output| !log_output.direct)
+        .and_then(|log_output| match FileSink::open(&log_output.path) {
+            Ok(sink) => Some(RotatingWriter::new(sink, log_output.max_size)),
+            Err(err) => {
+                log::error!("{err:#}, not capturing the output of {sched_bin}");
+                None
+            }
+        });
+    if log_writer.is_some() {
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+    }
+
     // spawn process
-    let mut child = cmd.spawn().expect("failed to spawn command");
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("Failed to spawn {sched_bin}"))?;
+    if let Some(log_writer) = log_writer {
+        forward_output(&mut child, log_writer);
+    }
 
     // NOTE: unsafe because the child might not exist, when we will try to stop it
     // set child id
-    child_id.store(
-        child
-            .id()
-            .ok_or(anyhow::anyhow!("Failed to get child id"))?,
-        Ordering::Relaxed,
-    );
+    let child_pid = child
+        .id()
+        .ok_or(anyhow::anyhow!("Failed to get child id"))?;
+    child_id.store(child_pid, Ordering::Relaxed);
+
+    if let Some(process_priority) = process_priority {
+        priority::apply_priority(&SystemPriorityControl, child_pid, process_priority);
+    }
+    if let Some(cpu_affinity) = &sched_state.cpu_affinity {
+        if let Err(affinity_err) = affinity::set_cpu_affinity(child_pid, cpu_affinity) {
+            log::error!("{affinity_err:#}");
+        }
+    }
 
     // Ensure the child process is exit is handled correctly in the runtime
     tokio::spawn(async move {
@@ -473,11 +1675,48 @@ async fn start_scheduler(
 
         log::debug!("Child process exited with status: {status:?}");
         child_id.store(0, Ordering::Relaxed);
+        let _ = exit_tx.send(RunnerMessage::Exited(run_id)).await;
     });
 
     Ok(())
 }
 
+/// Copy the stdout and stderr of the scheduler to its log file until the process exits
+fn forward_output(child: &mut Child, log_writer: RotatingWriter<FileSink>) {
+    let log_writer = Arc::new(Mutex::new(log_writer));
+    if let Some(stdout) = child.stdout.take() {
+        tokio::spawn(copy_output(stdout, log_writer.clone()));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        tokio::spawn(copy_output(stderr, log_writer));
+    }
+}
+
+async fn copy_output(
+    mut stream: impl AsyncRead + Unpin,
+    log_writer: Arc<Mutex<RotatingWriter<FileSink>>>,
+) {
+    let mut buf = [0; 4096];
+    let mut write_failed = false;
+    loop {
+        let len = match stream.read(&mut buf).await {
+            Ok(0) => return,
+            Ok(len) => len,
+            Err(err) => {
+                log::error!("Failed to read the scheduler output: {err}");
+                return;
+            }
+        };
+        // keep draining the stream after a failed write, so the scheduler doesn't block on it
+        if let Err(err) = log_writer.lock().unwrap().write(&buf[..len]) {
+            if !write_failed {
+                log::error!("Failed to write the scheduler output: {err}");
+            }
+            write_failed = true;
+        }
+    }
+}
+
 async fn stop_scheduler(child_id: Arc<AtomicU32>) -> Result<()> {
     // if child_proc is 0, then we assume the child process is terminated
     let child_proc = child_id.load(Ordering::Relaxed);
@@ -503,44 +1742,7 @@ async fn stop_scheduler(child_id: Arc<AtomicU32>) -> Result<()> {
 }
 
 /// Get the scx trait from the given scx name or return error if the given scx name is not supported
-fn get_scx_from_str(scx_name: &str) -> zbus::fdo::Result<SupportedSched> {
-    match scx_name {
-        "scx_bpfland" => Ok(SupportedSched::Bpfland),
-        "scx_rusty" => Ok(SupportedSched::Rusty),
-        "scx_lavd" => Ok(SupportedSched::Lavd),
-        _ => Err(zbus::fdo::Error::Failed(format!(
-            "{scx_name} is not supported"
-        ))),
-    }
-}
-
-/// Get the scx name from the given scx trait
-fn get_name_from_scx(supported_sched: &SupportedSched) -> &'static str {
-    match supported_sched {
-        SupportedSched::Bpfland => "scx_bpfland",
-        SupportedSched::Rusty => "scx_rusty",
-        SupportedSched::Lavd => "scx_lavd",
-    }
-}
-
-/// Get the scx flags for the given sched mode
-fn get_scx_flags_for_mode(scx_sched: &SupportedSched, sched_mode: SchedMode) -> Vec<&str> {
-    match scx_sched {
-        SupportedSched::Bpfland => match sched_mode {
-            SchedMode::Gaming => vec!["-c", "0", "-k", "-m", "performance"],
-            SchedMode::LowLatency => vec!["--lowlatency"],
-            SchedMode::PowerSave => vec!["-m", "powersave"],
-            SchedMode::Auto => vec![],
-        },
-        SupportedSched::Lavd => match sched_mode {
-            SchedMode::Gaming | SchedMode::LowLatency => vec!["--performance"],
-            SchedMode::PowerSave => vec!["--powersave"],
-            // NOTE: potentially adding --auto in future
-            SchedMode::Auto => vec![],
-        },
-        // scx_rusty doesn't support any of these modes
-        SupportedSched::Rusty => match sched_mode {
-            _ => vec![],
-        },
-    }
+fn get_scx_from_str(config: &Config, scx_name: &str) -> zbus::fdo::Result<SupportedSched> {
+    config::resolve_sched_name(config, scx_name)
+        .ok_or_else(|| zbus::fdo::Error::Failed(format!("{scx_name} is not supported")))
 }
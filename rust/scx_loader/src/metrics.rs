@@ -0,0 +1,235 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::get_name_from_scx;
+use crate::SupportedSched;
+use crate::SUPPORTED_SCHEDS;
+
+/// Name the custom schedulers are counted under
+const CUSTOM_SCHEDS_NAME: &str = "custom";
+
+#[derive(Debug, Default)]
+struct SchedCounters {
+    switches: AtomicU64,
+    failed_starts: AtomicU64,
+    restarts: AtomicU64,
+    coalesced: AtomicU64,
+}
+
+/// Counters of the scheduler switches, updated without locking or allocating
+#[derive(Debug)]
+pub struct Metrics {
+    /// Counters of each supported scheduler, followed by the ones of all custom schedulers
+    scheds: Vec<SchedCounters>,
+}
+
+/// Counters of a scheduler at the time of the snapshot
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct SchedMetrics {
+    /// Successful starts, including switching to the scheduler
+    pub switches: u64,
+    /// Starts which failed to spawn the scheduler or didn't attach to sched_ext in time
+    pub failed_starts: u64,
+    /// Restarts after the scheduler exited or detached unexpectedly
+    pub restarts: u64,
+    /// Switch requests dropped because a later request within the debounce window replaced them
+    #[serde(default)]
+    pub coalesced: u64,
+}
+
+/// Counters at the time of the snapshot, the totals are summed over all schedulers
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    #[serde(flatten)]
+    pub total: SchedMetrics,
+    /// Counters of the schedulers which were started at least once
+    pub scheds: BTreeMap<String, SchedMetrics>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            scheds: (0..=SUPPORTED_SCHEDS.len())
+                .map(|_| SchedCounters::default())
+                .collect(),
+        }
+    }
+}
+
+impl Metrics {
+    fn counters(&self, scx_sched: &SupportedSched) -> &SchedCounters {
+        let index = SUPPORTED_SCHEDS
+            .iter()
+            .position(|supported_sched| supported_sched == scx_sched)
+            .unwrap_or(SUPPORTED_SCHEDS.len());
+        &self.scheds[index]
+    }
+
+    pub fn record_switch(&self, scx_sched: &SupportedSched) {
+        self.counters(scx_sched)
+            .switches
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_failed_start(&self, scx_sched: &SupportedSched) {
+        self.counters(scx_sched)
+            .failed_starts
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_restart(&self, scx_sched: &SupportedSched) {
+        self.counters(scx_sched)
+            .restarts
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_coalesced(&self, scx_sched: &SupportedSched) {
+        self.counters(scx_sched)
+            .coalesced
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the current value of the counters
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let sched_names = SUPPORTED_SCHEDS
+            .iter()
+            .map(get_name_from_scx)
+            .chain([CUSTOM_SCHEDS_NAME]);
+
+        let mut snapshot = MetricsSnapshot::default();
+        for (sched_name, counters) in sched_names.zip(&self.scheds) {
+            let sched_metrics = SchedMetrics {
+                switches: counters.switches.load(Ordering::Relaxed),
+                failed_starts: counters.failed_starts.load(Ordering::Relaxed),
+                restarts: counters.restarts.load(Ordering::Relaxed),
+                coalesced: counters.coalesced.load(Ordering::Relaxed),
+            };
+            if sched_metrics == SchedMetrics::default() {
+                continue;
+            }
+            snapshot.total.switches += sched_metrics.switches;
+            snapshot.total.failed_starts += sched_metrics.failed_starts;
+            snapshot.total.restarts += sched_metrics.restarts;
+            snapshot.total.coalesced += sched_metrics.coalesced;
+            snapshot.scheds.insert(sched_name.to_owned(), sched_metrics);
+        }
+        snapshot
+    }
+}
+
+impl MetricsSnapshot {
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string(self).context("Failed to serialize metrics")
+    }
+
+    /// Format the counters in the Prometheus text format
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let counters = |sched_metrics: &SchedMetrics| {
+            [
+                ("switches", sched_metrics.switches),
+                ("failed_starts", sched_metrics.failed_starts),
+                ("restarts", sched_metrics.restarts),
+                ("coalesced", sched_metrics.coalesced),
+            ]
+        };
+        for (counter_name, value) in counters(&self.total) {
+            let _ = writeln!(text, "scx_loader_{counter_name}_total {value}");
+        }
+        for (sched_name, sched_metrics) in &self.scheds {
+            for (counter_name, value) in counters(sched_metrics) {
+                let _ = writeln!(
+                    text,
+                    "scx_loader_{counter_name}_total{{sched=\"{sched_name}\"}} {value}"
+                );
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::*;
+
+    #[test]
+    fn test_metrics_counters() {
+        let metrics = Metrics::default();
+        assert_eq!(metrics.snapshot(), MetricsSnapshot::default());
+
+        metrics.record_switch(&SupportedSched::Bpfland);
+        metrics.record_switch(&SupportedSched::Lavd);
+        metrics.record_failed_start(&SupportedSched::Lavd);
+        metrics.record_restart(&SupportedSched::Lavd);
+        metrics.record_switch(&SupportedSched::Bpfland);
+        metrics.record_failed_start(&SupportedSched::Custom("scx_custom".to_owned()));
+        metrics.record_coalesced(&SupportedSched::Bpfland);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(
+            snapshot.total,
+            SchedMetrics {
+                switches: 3,
+                failed_starts: 2,
+                restarts: 1,
+                coalesced: 1,
+            }
+        );
+        assert_eq!(
+            snapshot.scheds["scx_bpfland"],
+            SchedMetrics {
+                switches: 2,
+                failed_starts: 0,
+                restarts: 0,
+                coalesced: 1,
+            }
+        );
+        assert_eq!(
+            snapshot.scheds["scx_lavd"],
+            SchedMetrics {
+                switches: 1,
+                failed_starts: 1,
+                restarts: 1,
+                coalesced: 0,
+            }
+        );
+        assert_eq!(snapshot.scheds["custom"].failed_starts, 1);
+        assert_eq!(snapshot.scheds.len(), 3);
+    }
+
+    #[test]
+    fn test_metrics_formats() {
+        let metrics = Metrics::default();
+        metrics.record_switch(&SupportedSched::Flash);
+
+        assert_eq!(
+            metrics.snapshot().to_json().unwrap(),
+            r#"{"switches":1,"failed_starts":0,"restarts":0,"coalesced":0,"scheds":{"scx_flash":{"switches":1,"failed_starts":0,"restarts":0,"coalesced":0}}}"#
+        );
+        assert_eq!(
+            metrics.snapshot().to_text(),
+            "scx_loader_switches_total 1\n\
+             scx_loader_failed_starts_total 0\n\
+             scx_loader_restarts_total 0\n\
+             scx_loader_coalesced_total 0\n\
+             scx_loader_switches_total{sched=\"scx_flash\"} 1\n\
+             scx_loader_failed_starts_total{sched=\"scx_flash\"} 0\n\
+             scx_loader_restarts_total{sched=\"scx_flash\"} 0\n\
+             scx_loader_coalesced_total{sched=\"scx_flash\"} 0\n"
+        );
+    }
+}
@@ -0,0 +1,231 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::ffi::CStr;
+use std::io;
+use std::ops::RangeInclusive;
+
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::get_name_from_scx;
+use crate::SupportedSched;
+
+/// Range of the values accepted by /proc/<pid>/oom_score_adj
+pub const OOM_SCORE_ADJ_RANGE: RangeInclusive<i32> = -1000..=1000;
+
+/// Check that the oom_score_adj is accepted by the kernel
+pub fn validate_oom_score_adj(oom_score_adj: i32) -> Result<()> {
+    if !OOM_SCORE_ADJ_RANGE.contains(&oom_score_adj) {
+        anyhow::bail!(
+            "{oom_score_adj} is out of range {}..={}",
+            OOM_SCORE_ADJ_RANGE.start(),
+            OOM_SCORE_ADJ_RANGE.end()
+        );
+    }
+    Ok(())
+}
+
+impl Config {
+    /// Get the oom_score_adj of the scheduler process, the one of the scheduler overrides the
+    /// global one. An out of range value is reported by `validate`
+    pub fn get_oom_score_adj(&self, scx_sched: &SupportedSched) -> Option<i32> {
+        self.scheds
+            .get(get_name_from_scx(scx_sched))
+            .and_then(|sched_config| sched_config.oom_score_adj)
+            .or(self.oom_score_adj)
+    }
+}
+
+/// Writes done in the forked scheduler process before exec. Implementations must be
+/// async-signal-safe, i.e. not allocate or take locks
+pub trait ChildIo {
+    fn write_oom_score_adj(&self, content: &[u8]) -> io::Result<()>;
+    /// Move the process into the cgroup of the given cgroup.procs file
+    fn write_cgroup_procs(&self, procs_path: &CStr) -> io::Result<()>;
+    fn write_stderr(&self, message: &[u8]);
+}
+
+/// Writes to /proc/self/oom_score_adj, cgroup.procs and stderr with the raw syscalls
+pub struct SystemChildIo;
+
+impl SystemChildIo {
+    const OOM_SCORE_ADJ_PATH: &'static CStr = c"/proc/self/oom_score_adj";
+
+    fn write_file(path: &CStr, content: &[u8]) -> io::Result<()> {
+        // SAFETY: the path is NUL terminated and the content outlives the calls
+        unsafe {
+            let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let written = libc::write(fd, content.as_ptr().cast(), content.len());
+            let write_err = io::Error::last_os_error();
+            libc::close(fd);
+            if written < 0 {
+                return Err(write_err);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ChildIo for SystemChildIo {
+    fn write_oom_score_adj(&self, content: &[u8]) -> io::Result<()> {
+        Self::write_file(Self::OOM_SCORE_ADJ_PATH, content)
+    }
+
+    fn write_cgroup_procs(&self, procs_path: &CStr) -> io::Result<()> {
+        // the kernel reads 0 as the writing process
+        Self::write_file(procs_path, b"0\n")
+    }
+
+    fn write_stderr(&self, message: &[u8]) {
+        // SAFETY: the message outlives the call, which only reads it
+        unsafe {
+            libc::write(libc::STDERR_FILENO, message.as_ptr().cast(), message.len());
+        }
+    }
+}
+
+/// Records the writes done in the child instead of doing them, failing the writes of the files
+/// it's told to
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct RecordingChildIo {
+    pub writes: std::cell::RefCell<Vec<(&'static str, String)>>,
+    pub fail_oom_score_adj: bool,
+    pub fail_cgroup_procs: bool,
+}
+
+#[cfg(test)]
+impl RecordingChildIo {
+    fn record(&self, target: &'static str, content: String, fail: bool) -> io::Result<()> {
+        self.writes.borrow_mut().push((target, content));
+        match fail {
+            true => Err(io::Error::from(io::ErrorKind::PermissionDenied)),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+impl ChildIo for RecordingChildIo {
+    fn write_oom_score_adj(&self, content: &[u8]) -> io::Result<()> {
+        let content = String::from_utf8_lossy(content).into_owned();
+        self.record("oom_score_adj", content, self.fail_oom_score_adj)
+    }
+
+    fn write_cgroup_procs(&self, procs_path: &CStr) -> io::Result<()> {
+        let procs_path = procs_path.to_string_lossy().into_owned();
+        self.record("cgroup.procs", procs_path, self.fail_cgroup_procs)
+    }
+
+    fn write_stderr(&self, message: &[u8]) {
+        let _ = self.record(
+            "stderr",
+            String::from_utf8_lossy(message).into_owned(),
+            false,
+        );
+    }
+}
+
+/// OOM score adjustment of the scheduler process, formatted before forking so applying it in
+/// the child doesn't allocate
+#[derive(Debug, Clone, PartialEq)]
+pub struct OomScoreAdj {
+    content: Vec<u8>,
+    failure_message: Vec<u8>,
+}
+
+impl OomScoreAdj {
+    pub fn new(oom_score_adj: i32) -> Result<Self> {
+        validate_oom_score_adj(oom_score_adj)?;
+        Ok(Self {
+            content: format!("{oom_score_adj}\n").into_bytes(),
+            failure_message: format!("scx_loader: Failed to set oom_score_adj {oom_score_adj}\n")
+                .into_bytes(),
+        })
+    }
+
+    /// Apply the adjustment in the child before exec. A failure is reported on stderr, which
+    /// ends up in the log of the loader, and doesn't prevent starting the scheduler
+    pub fn apply_in_child(&self, child_io: &impl ChildIo) {
+        if child_io.write_oom_score_adj(&self.content).is_err() {
+            child_io.write_stderr(&self.failure_message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::oom::*;
+
+    #[test]
+    fn test_validate_oom_score_adj() {
+        validate_oom_score_adj(-1000).unwrap();
+        validate_oom_score_adj(0).unwrap();
+        validate_oom_score_adj(1000).unwrap();
+        assert_eq!(
+            validate_oom_score_adj(-1001).unwrap_err().to_string(),
+            "-1001 is out of range -1000..=1000"
+        );
+        assert!(OomScoreAdj::new(1001).is_err());
+
+        let config: Config = toml::from_str(
+            r#"
+oom_score_adj = -500
+
+[scheds.scx_lavd]
+oom_score_adj = -900
+
+[scheds.scx_bpfland]
+oom_score_adj = 2000
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.get_oom_score_adj(&SupportedSched::Lavd), Some(-900));
+        assert_eq!(config.get_oom_score_adj(&SupportedSched::Rusty), Some(-500));
+        let report = config.validation_report();
+        assert_eq!(
+            report.errors,
+            vec!["scx_bpfland oom_score_adj: 2000 is out of range -1000..=1000"]
+        );
+        assert_eq!(
+            Config::default().get_oom_score_adj(&SupportedSched::Lavd),
+            None
+        );
+    }
+
+    #[test]
+    fn test_apply_in_child() {
+        let oom_score_adj = OomScoreAdj::new(-900).unwrap();
+        let child_io = RecordingChildIo::default();
+        oom_score_adj.apply_in_child(&child_io);
+        assert_eq!(
+            *child_io.writes.borrow(),
+            vec![("oom_score_adj", "-900\n".to_owned())]
+        );
+
+        // the failure is reported after the failed write, the start goes on
+        let child_io = RecordingChildIo {
+            fail_oom_score_adj: true,
+            ..Default::default()
+        };
+        oom_score_adj.apply_in_child(&child_io);
+        assert_eq!(
+            *child_io.writes.borrow(),
+            vec![
+                ("oom_score_adj", "-900\n".to_owned()),
+                (
+                    "stderr",
+                    "scx_loader: Failed to set oom_score_adj -900\n".to_owned()
+                ),
+            ]
+        );
+    }
+}
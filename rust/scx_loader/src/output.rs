@@ -0,0 +1,290 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::get_name_from_scx;
+use crate::stop::StopBehavior;
+use crate::SupportedSched;
+
+/// Size of the scheduler log file before it's rotated, unless log_output_max_size is configured
+pub const DEFAULT_LOG_OUTPUT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Rotated log files kept next to the current one, e.g. "scx_lavd.log.1" to "scx_lavd.log.3"
+const ROTATED_FILES: u32 = 3;
+
+/// File the output of the scheduler process is written to
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogOutput {
+    pub path: PathBuf,
+    /// Size in bytes at which the file is rotated
+    pub max_size: u64,
+    /// Hand the file itself to the scheduler instead of piping its output through the loader,
+    /// so the scheduler can keep writing after the loader exited. The file isn't rotated then
+    pub direct: bool,
+}
+
+impl Config {
+    /// Get the file the stdout and stderr of the scheduler are written to, the log_output file
+    /// of the scheduler overrides the global log_output directory. None if the scheduler
+    /// inherits the streams of the loader. A scheduler left running when the loader exits
+    /// writes to the file directly, as nothing would read a pipe anymore
+    pub fn get_log_output(&self, scx_sched: &SupportedSched) -> Option<LogOutput> {
+        let sched_file = self
+            .scheds
+            .get(get_name_from_scx(scx_sched))
+            .and_then(|sched_config| sched_config.log_output.as_ref())
+            .map(PathBuf::from);
+        let path = sched_file.or_else(|| {
+            let log_dir = self.log_output.as_ref()?;
+            Some(log_output_path(Path::new(log_dir), scx_sched))
+        })?;
+        Some(LogOutput {
+            path,
+            max_size: self
+                .log_output_max_size
+                .unwrap_or(DEFAULT_LOG_OUTPUT_MAX_SIZE),
+            direct: self.get_stop_behavior() == StopBehavior::LeaveRunning,
+        })
+    }
+}
+
+/// Get the log file of the scheduler in the log directory, e.g. "<log_dir>/scx_lavd.log"
+pub fn log_output_path(log_dir: &Path, scx_sched: &SupportedSched) -> PathBuf {
+    log_dir.join(format!("{}.log", get_name_from_scx(scx_sched)))
+}
+
+/// Get the path of the log file rotated the given number of times, e.g. "scx_lavd.log.2"
+pub fn rotated_path(path: &Path, index: u32) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{index}"));
+    PathBuf::from(rotated)
+}
+
+/// Check whether the file has to be rotated before writing the given number of bytes. A
+/// single write larger than the maximum size still goes to a fresh file
+pub fn needs_rotation(current_size: u64, write_len: u64, max_size: u64) -> bool {
+    current_size > 0 && current_size + write_len > max_size
+}
+
+/// File the scheduler output is appended to, the RotatingWriter decides when to rotate it
+pub trait OutputSink {
+    /// Bytes written to the current file
+    fn size(&self) -> u64;
+    fn write(&mut self, data: &[u8]) -> io::Result<()>;
+    /// Move the current file aside and start a new one
+    fn rotate(&mut self) -> io::Result<()>;
+}
+
+/// Writes the output to the sink, rotating it whenever it would grow beyond the maximum size
+pub struct RotatingWriter<S: OutputSink> {
+    sink: S,
+    max_size: u64,
+}
+
+impl<S: OutputSink> RotatingWriter<S> {
+    pub fn new(sink: S, max_size: u64) -> Self {
+        Self { sink, max_size }
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        if needs_rotation(self.sink.size(), data.len() as u64, self.max_size) {
+            self.sink.rotate()?;
+        }
+        self.sink.write(data)
+    }
+}
+
+/// Open the log file for appending, creating it and its directory if missing
+pub fn open_log_file(path: &Path) -> Result<File> {
+    if let Some(log_dir) = path.parent() {
+        fs::create_dir_all(log_dir)
+            .with_context(|| format!("Failed to create log directory {}", log_dir.display()))?;
+    }
+    FileSink::open_file(path).with_context(|| format!("Failed to open log file {}", path.display()))
+}
+
+/// Appends the output to a log file, rotated files are renamed with an increasing suffix
+pub struct FileSink {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl FileSink {
+    /// Open the log file for appending, creating it and its directory if missing
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = open_log_file(path)?;
+        let size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        Ok(Self {
+            path: path.to_owned(),
+            file,
+            size,
+        })
+    }
+
+    fn open_file(path: &Path) -> io::Result<File> {
+        fs::OpenOptions::new().create(true).append(true).open(path)
+    }
+}
+
+impl OutputSink for FileSink {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.file.write_all(data)?;
+        self.size += data.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        // the oldest file is replaced by the one before it
+        for index in (1..ROTATED_FILES).rev() {
+            let rotated = rotated_path(&self.path, index);
+            if rotated.exists() {
+                fs::rename(&rotated, rotated_path(&self.path, index + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        self.file = Self::open_file(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output::*;
+
+    /// Records the writes, each rotation starts a new file
+    #[derive(Default)]
+    struct FakeSink {
+        files: Vec<Vec<u8>>,
+    }
+
+    impl OutputSink for FakeSink {
+        fn size(&self) -> u64 {
+            self.files.last().map_or(0, |file| file.len() as u64)
+        }
+
+        fn write(&mut self, data: &[u8]) -> io::Result<()> {
+            if self.files.is_empty() {
+                self.files.push(vec![]);
+            }
+            self.files.last_mut().unwrap().extend_from_slice(data);
+            Ok(())
+        }
+
+        fn rotate(&mut self) -> io::Result<()> {
+            self.files.push(vec![]);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_needs_rotation() {
+        assert!(!needs_rotation(0, 5, 10));
+        assert!(!needs_rotation(5, 5, 10));
+        assert!(needs_rotation(6, 5, 10));
+        // an oversized write to an empty file isn't rotated endlessly
+        assert!(!needs_rotation(0, 50, 10));
+
+        let mut writer = RotatingWriter::new(FakeSink::default(), 10);
+        for data in [&b"12345"[..], b"6789", b"abc", b"defghijklmn"] {
+            writer.write(data).unwrap();
+        }
+        assert_eq!(
+            writer.sink.files,
+            vec![
+                b"123456789".to_vec(),
+                b"abc".to_vec(),
+                b"defghijklmn".to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_log_output_paths() {
+        let config: Config = toml::from_str(
+            r#"
+log_output = "/var/log/scx_loader"
+log_output_max_size = 4096
+
+[scheds.scx_lavd]
+log_output = "/tmp/lavd.log"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.get_log_output(&SupportedSched::Bpfland),
+            Some(LogOutput {
+                path: PathBuf::from("/var/log/scx_loader/scx_bpfland.log"),
+                max_size: 4096,
+                direct: false,
+            })
+        );
+        assert_eq!(
+            config.get_log_output(&SupportedSched::Lavd).unwrap().path,
+            PathBuf::from("/tmp/lavd.log")
+        );
+        assert_eq!(
+            config.get_log_output(&SupportedSched::Custom("scx_custom".to_owned())),
+            Some(LogOutput {
+                path: PathBuf::from("/var/log/scx_loader/scx_custom.log"),
+                max_size: 4096,
+                direct: false,
+            })
+        );
+        assert_eq!(
+            Config::default().get_log_output(&SupportedSched::Lavd),
+            None
+        );
+        // the scheduler outlives the loader, so it gets the file instead of a pipe
+        let config = Config {
+            stop_behavior: Some(StopBehavior::LeaveRunning),
+            ..config
+        };
+        assert!(
+            config
+                .get_log_output(&SupportedSched::Bpfland)
+                .unwrap()
+                .direct
+        );
+
+        assert_eq!(
+            rotated_path(Path::new("/var/log/scx_loader/scx_lavd.log"), 2),
+            PathBuf::from("/var/log/scx_loader/scx_lavd.log.2")
+        );
+    }
+
+    #[test]
+    fn test_file_sink_rotation() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("logs").join("scx_lavd.log");
+        let mut writer = RotatingWriter::new(FileSink::open(&path).unwrap(), 4);
+        for data in ["aaa", "bbb", "ccc", "ddd", "eee"] {
+            writer.write(data.as_bytes()).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "eee");
+        assert_eq!(fs::read_to_string(rotated_path(&path, 1)).unwrap(), "ddd");
+        assert_eq!(fs::read_to_string(rotated_path(&path, 3)).unwrap(), "bbb");
+        // only ROTATED_FILES files are kept
+        assert!(!rotated_path(&path, 4).exists());
+    }
+}
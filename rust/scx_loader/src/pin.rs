@@ -0,0 +1,124 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+/// Pin of the running scheduler, which suspends the automatic switches by the schedule and the
+/// power source. Switches requested over DBUS are still done.
+///
+/// The pin is only kept in memory, it survives config reloads but not a restart of scx_loader.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Pin {
+    pinned: bool,
+}
+
+impl Pin {
+    pub fn pin(&mut self) {
+        self.pinned = true;
+    }
+
+    pub fn unpin(&mut self) {
+        self.pinned = false;
+    }
+
+    pub fn is_pinned(&self) -> bool {
+        self.pinned
+    }
+
+    /// Resolve the automatic switch unless pinned. The resolver isn't run while pinned, so a
+    /// change it would have reported is picked up once unpinned
+    pub fn resolve<T>(&self, resolver: impl FnOnce() -> Option<T>) -> Option<T> {
+        if self.pinned {
+            return None;
+        }
+        resolver()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use chrono::NaiveTime;
+
+    use crate::config::Config;
+    use crate::pin::*;
+    use crate::power::PowerConfig;
+    use crate::power::PowerMonitor;
+    use crate::power::PowerState;
+    use crate::power::PowerSupply;
+    use crate::schedule::ScheduleFollower;
+    use crate::SchedMode;
+    use crate::SupportedSched;
+
+    struct FakePowerSupply(Cell<PowerState>);
+
+    impl PowerSupply for FakePowerSupply {
+        fn power_state(&self) -> Option<PowerState> {
+            Some(self.0.get())
+        }
+    }
+
+    #[test]
+    fn test_pinned_power_switch() {
+        let power_config = PowerConfig {
+            on_battery_mode: Some(SchedMode::PowerSave),
+            on_ac_mode: Some(SchedMode::Gaming),
+        };
+        let power_supply = FakePowerSupply(Cell::new(PowerState::OnAc));
+        let mut power_monitor = PowerMonitor::default();
+        let mut pin = Pin::default();
+
+        assert_eq!(
+            pin.resolve(|| power_monitor.poll(&power_config, &power_supply)),
+            Some(SchedMode::Gaming)
+        );
+
+        pin.pin();
+        power_supply.0.set(PowerState::OnBattery);
+        assert_eq!(
+            pin.resolve(|| power_monitor.poll(&power_config, &power_supply)),
+            None
+        );
+
+        // the change while pinned is applied once unpinned
+        pin.unpin();
+        assert_eq!(
+            pin.resolve(|| power_monitor.poll(&power_config, &power_supply)),
+            Some(SchedMode::PowerSave)
+        );
+        assert_eq!(
+            pin.resolve(|| power_monitor.poll(&power_config, &power_supply)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_pinned_schedule_switch() {
+        let config: Config = toml::from_str(
+            r#"
+[[schedule]]
+start = "22:00"
+end = "06:00"
+sched = "scx_lavd"
+mode = "PowerSave"
+"#,
+        )
+        .unwrap();
+        let night = NaiveTime::from_hms_opt(23, 0, 0).unwrap();
+        let mut schedule_follower = ScheduleFollower::default();
+        let mut pin = Pin::default();
+
+        pin.pin();
+        assert_eq!(pin.resolve(|| schedule_follower.poll(&config, night)), None);
+        pin.unpin();
+        assert_eq!(
+            pin.resolve(|| schedule_follower.poll(&config, night)),
+            Some((SupportedSched::Lavd, SchedMode::PowerSave))
+        );
+        // the window is only switched to once
+        assert_eq!(pin.resolve(|| schedule_follower.poll(&config, night)), None);
+    }
+}
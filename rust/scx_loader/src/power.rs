@@ -0,0 +1,201 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::config;
+use crate::SchedMode;
+
+/// Directory with one subdirectory per power supply, relative to the sysfs mount
+const POWER_SUPPLY_SYSFS_DIR: &str = "class/power_supply";
+
+/// Modes applied when the power source changes
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct PowerConfig {
+    pub on_battery_mode: Option<SchedMode>,
+    pub on_ac_mode: Option<SchedMode>,
+}
+
+impl PowerConfig {
+    /// Get the configured mode for the given power state
+    pub fn mode_for(&self, power_state: PowerState) -> Option<SchedMode> {
+        match power_state {
+            PowerState::OnBattery => self.on_battery_mode.clone(),
+            PowerState::OnAc => self.on_ac_mode.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PowerState {
+    OnBattery,
+    OnAc,
+}
+
+/// Source of whether the machine runs on battery or on AC
+pub trait PowerSupply {
+    /// Get the current power state, or None if it's unknown, e.g. on desktops without battery
+    fn power_state(&self) -> Option<PowerState>;
+}
+
+/// Reads the power state from the power supplies in sysfs
+pub struct SysfsPowerSupply {
+    power_supply_dir: PathBuf,
+}
+
+impl Default for SysfsPowerSupply {
+    fn default() -> Self {
+        Self::from_sysfs(Path::new(config::DEFAULT_SYSFS_PATH))
+    }
+}
+
+impl SysfsPowerSupply {
+    pub fn new(power_supply_dir: &Path) -> Self {
+        Self {
+            power_supply_dir: power_supply_dir.to_path_buf(),
+        }
+    }
+
+    /// Read the power supplies in the sysfs mounted at the given path
+    pub fn from_sysfs(sysfs_path: &Path) -> Self {
+        Self::new(&sysfs_path.join(POWER_SUPPLY_SYSFS_DIR))
+    }
+}
+
+impl PowerSupply for SysfsPowerSupply {
+    /// Any online mains or USB supply means AC, otherwise a battery means we're running on it
+    fn power_state(&self) -> Option<PowerState> {
+        let read_value = |supply_path: &Path, name: &str| {
+            fs::read_to_string(supply_path.join(name))
+                .map(|value| value.trim().to_owned())
+                .unwrap_or_default()
+        };
+
+        let mut has_battery = false;
+        for entry in fs::read_dir(&self.power_supply_dir).ok()?.flatten() {
+            let supply_path = entry.path();
+            match read_value(&supply_path, "type").as_str() {
+                "Mains" | "USB" if read_value(&supply_path, "online") == "1" => {
+                    return Some(PowerState::OnAc);
+                }
+                "Battery" => has_battery = true,
+                _ => {}
+            }
+        }
+        has_battery.then_some(PowerState::OnBattery)
+    }
+}
+
+/// Tracks the power state and reports the mode to switch to when it changes
+#[derive(Debug, Default)]
+pub struct PowerMonitor {
+    power_state: Option<PowerState>,
+}
+
+impl PowerMonitor {
+    /// Check the power supply and get the configured mode for the new power state if it
+    /// changed since the last check. The first known state counts as a change as well
+    pub fn poll(&mut self, config: &PowerConfig, source: &impl PowerSupply) -> Option<SchedMode> {
+        let power_state = source.power_state()?;
+        if self.power_state == Some(power_state) {
+            return None;
+        }
+        self.power_state = Some(power_state);
+        config.mode_for(power_state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use crate::power::*;
+
+    struct FakePowerSupply(Cell<Option<PowerState>>);
+
+    impl PowerSupply for FakePowerSupply {
+        fn power_state(&self) -> Option<PowerState> {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn test_power_monitor_transitions() {
+        let config = PowerConfig {
+            on_battery_mode: Some(SchedMode::PowerSave),
+            on_ac_mode: Some(SchedMode::Auto),
+        };
+        let source = FakePowerSupply(Cell::new(Some(PowerState::OnAc)));
+        let mut monitor = PowerMonitor::default();
+
+        assert_eq!(monitor.poll(&config, &source), Some(SchedMode::Auto));
+        assert_eq!(monitor.poll(&config, &source), None);
+
+        source.0.set(Some(PowerState::OnBattery));
+        assert_eq!(monitor.poll(&config, &source), Some(SchedMode::PowerSave));
+        assert_eq!(monitor.poll(&config, &source), None);
+
+        // unknown state keeps the current mode
+        source.0.set(None);
+        assert_eq!(monitor.poll(&config, &source), None);
+
+        source.0.set(Some(PowerState::OnAc));
+        assert_eq!(monitor.poll(&config, &source), Some(SchedMode::Auto));
+
+        // no mode configured for battery
+        let ac_only = PowerConfig {
+            on_ac_mode: Some(SchedMode::Gaming),
+            ..Default::default()
+        };
+        source.0.set(Some(PowerState::OnBattery));
+        assert_eq!(monitor.poll(&ac_only, &source), None);
+    }
+
+    #[test]
+    fn test_sysfs_power_supply() {
+        let tmp = tempfile::tempdir().unwrap();
+        let write_supply = |name: &str, supply_type: &str, online: &str| {
+            let supply_path = tmp.path().join(name);
+            fs::create_dir_all(&supply_path).unwrap();
+            fs::write(supply_path.join("type"), format!("{supply_type}\n")).unwrap();
+            fs::write(supply_path.join("online"), format!("{online}\n")).unwrap();
+        };
+        let source = SysfsPowerSupply::new(tmp.path());
+        assert_eq!(source.power_state(), None);
+
+        write_supply("BAT0", "Battery", "1");
+        write_supply("AC", "Mains", "0");
+        assert_eq!(source.power_state(), Some(PowerState::OnBattery));
+
+        write_supply("AC", "Mains", "1");
+        assert_eq!(source.power_state(), Some(PowerState::OnAc));
+
+        assert_eq!(
+            SysfsPowerSupply::new(&tmp.path().join("missing")).power_state(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_power_supply_sysfs_path() {
+        let tmp = tempfile::tempdir().unwrap();
+        let supply_path = tmp.path().join("class/power_supply/BAT0");
+        fs::create_dir_all(&supply_path).unwrap();
+        fs::write(supply_path.join("type"), "Battery\n").unwrap();
+        fs::write(supply_path.join("online"), "1\n").unwrap();
+
+        assert_eq!(
+            SysfsPowerSupply::from_sysfs(tmp.path()).power_state(),
+            Some(PowerState::OnBattery)
+        );
+    }
+}
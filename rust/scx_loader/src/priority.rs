@@ -0,0 +1,192 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::io;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Range of nice values accepted by setpriority
+const NICE_RANGE: (i32, i32) = (-20, 19);
+
+/// Range of SCHED_FIFO priorities accepted by sched_setscheduler
+const REALTIME_PRIORITY_RANGE: (i32, i32) = (1, 99);
+
+/// Priority the scheduler process is run with
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ProcessPriority {
+    /// Nice value of the scheduler process, clamped to -20..=19
+    pub nice: Option<i32>,
+    /// Run the scheduler process with SCHED_FIFO at this priority, clamped to 1..=99
+    pub realtime_priority: Option<i32>,
+}
+
+impl ProcessPriority {
+    /// Get the configured nice value within the range accepted by the kernel
+    pub fn clamped_nice(&self) -> Option<i32> {
+        self.nice.map(|nice| nice.clamp(NICE_RANGE.0, NICE_RANGE.1))
+    }
+
+    /// Get the configured realtime priority within the range accepted by the kernel
+    pub fn clamped_realtime_priority(&self) -> Option<i32> {
+        self.realtime_priority
+            .map(|priority| priority.clamp(REALTIME_PRIORITY_RANGE.0, REALTIME_PRIORITY_RANGE.1))
+    }
+}
+
+/// Sets the nice value and the realtime priority of a process
+pub trait PriorityControl {
+    fn set_nice(&self, pid: u32, nice: i32) -> io::Result<()>;
+    fn set_realtime_priority(&self, pid: u32, priority: i32) -> io::Result<()>;
+}
+
+/// Sets the priority with setpriority and sched_setscheduler
+pub struct SystemPriorityControl;
+
+impl PriorityControl for SystemPriorityControl {
+    fn set_nice(&self, pid: u32, nice: i32) -> io::Result<()> {
+        // SAFETY: setpriority only reads its arguments
+        let ret = unsafe { libc::setpriority(libc::PRIO_PROCESS, pid, nice) };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn set_realtime_priority(&self, pid: u32, priority: i32) -> io::Result<()> {
+        let param = libc::sched_param {
+            sched_priority: priority,
+        };
+        // SAFETY: the param outlives the call, which only reads it
+        let ret = unsafe { libc::sched_setscheduler(pid as libc::pid_t, libc::SCHED_FIFO, &param) };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// Apply the configured priority to the process, failures are logged as the scheduler works
+/// without it as well
+pub fn apply_priority(control: &impl PriorityControl, pid: u32, priority: &ProcessPriority) {
+    if let Some(nice) = priority.clamped_nice() {
+        if let Err(err) = control.set_nice(pid, nice) {
+            log::warn!("Failed to set nice value {nice} of process {pid}: {err}");
+        }
+    }
+    if let Some(realtime_priority) = priority.clamped_realtime_priority() {
+        if let Err(err) = control.set_realtime_priority(pid, realtime_priority) {
+            log::warn!(
+                "Failed to set realtime priority {realtime_priority} of process {pid}: {err}"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use crate::config::parse_config;
+    use crate::priority::*;
+
+    #[derive(Default)]
+    struct FakePriorityControl {
+        calls: RefCell<Vec<(&'static str, u32, i32)>>,
+        fail: bool,
+    }
+
+    impl FakePriorityControl {
+        fn record(&self, call: &'static str, pid: u32, value: i32) -> io::Result<()> {
+            self.calls.borrow_mut().push((call, pid, value));
+            match self.fail {
+                true => Err(io::Error::from(io::ErrorKind::PermissionDenied)),
+                false => Ok(()),
+            }
+        }
+    }
+
+    impl PriorityControl for FakePriorityControl {
+        fn set_nice(&self, pid: u32, nice: i32) -> io::Result<()> {
+            self.record("nice", pid, nice)
+        }
+
+        fn set_realtime_priority(&self, pid: u32, priority: i32) -> io::Result<()> {
+            self.record("realtime", pid, priority)
+        }
+    }
+
+    #[test]
+    fn test_parse_process_priority() {
+        let config_str = r#"
+[process_priority]
+nice = -5
+realtime_priority = 10
+"#;
+        let config = parse_config(config_str);
+        assert_eq!(
+            config.process_priority,
+            Some(ProcessPriority {
+                nice: Some(-5),
+                realtime_priority: Some(10),
+            })
+        );
+
+        let config = parse_config("[process_priority]\nnice = 3\n");
+        assert_eq!(config.process_priority.unwrap().realtime_priority, None);
+    }
+
+    #[test]
+    fn test_clamp_priority() {
+        let priority = ProcessPriority {
+            nice: Some(-40),
+            realtime_priority: Some(150),
+        };
+        assert_eq!(priority.clamped_nice(), Some(-20));
+        assert_eq!(priority.clamped_realtime_priority(), Some(99));
+
+        let priority = ProcessPriority {
+            nice: Some(25),
+            realtime_priority: Some(0),
+        };
+        assert_eq!(priority.clamped_nice(), Some(19));
+        assert_eq!(priority.clamped_realtime_priority(), Some(1));
+
+        let priority = ProcessPriority {
+            nice: Some(-7),
+            realtime_priority: None,
+        };
+        assert_eq!(priority.clamped_nice(), Some(-7));
+        assert_eq!(priority.clamped_realtime_priority(), None);
+    }
+
+    #[test]
+    fn test_apply_priority() {
+        let priority = ProcessPriority {
+            nice: Some(-30),
+            realtime_priority: Some(5),
+        };
+        let control = FakePriorityControl::default();
+        apply_priority(&control, 42, &priority);
+        assert_eq!(
+            *control.calls.borrow(),
+            vec![("nice", 42, -20), ("realtime", 42, 5)]
+        );
+
+        // failures don't stop applying the rest
+        let control = FakePriorityControl {
+            fail: true,
+            ..Default::default()
+        };
+        apply_priority(&control, 42, &priority);
+        assert_eq!(control.calls.borrow().len(), 2);
+
+        let control = FakePriorityControl::default();
+        apply_priority(&control, 42, &ProcessPriority::default());
+        assert!(control.calls.borrow().is_empty());
+    }
+}
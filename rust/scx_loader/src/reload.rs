@@ -0,0 +1,250 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::get_name_from_scx;
+use crate::SupportedSched;
+
+/// What to do with a config loaded on reload
+#[derive(Debug, PartialEq)]
+pub enum ReloadDecision {
+    /// The new config failed to load or validate, keep running with the old one
+    KeepOld(String),
+    /// Use the new config, restarting the running scheduler if its settings changed. The
+    /// changes are deferred to the next switch instead if the scheduler has restart_on_change
+    /// disabled
+    Apply {
+        config: Box<Config>,
+        restart: bool,
+        deferred: bool,
+    },
+}
+
+/// Decide whether to switch to the reloaded config and whether the running scheduler, if any,
+/// has to be restarted to pick up the changes
+pub fn decide_reload(
+    old_config: &Config,
+    new_config: Result<Config>,
+    running_sched: Option<&SupportedSched>,
+) -> ReloadDecision {
+    let new_config = match new_config.and_then(|new_config| {
+        new_config.validate()?;
+        Ok(new_config)
+    }) {
+        Ok(new_config) => new_config,
+        Err(reload_err) => return ReloadDecision::KeepOld(format!("{reload_err:#}")),
+    };
+
+    let diff = old_config.diff(&new_config);
+    let changed = running_sched.is_some_and(|scx_sched| {
+        diff.affects_sched(get_name_from_scx(scx_sched)) || diff.launch_changed
+    });
+    let restart_on_change =
+        running_sched.is_none_or(|scx_sched| new_config.restarts_on_change(scx_sched));
+    ReloadDecision::Apply {
+        config: Box::new(new_config),
+        restart: changed && restart_on_change,
+        deferred: changed && !restart_on_change,
+    }
+}
+
+impl Config {
+    /// Whether the running scheduler is restarted when a reload changes its settings, defaults
+    /// to true
+    pub fn restarts_on_change(&self, scx_sched: &SupportedSched) -> bool {
+        self.scheds
+            .get(get_name_from_scx(scx_sched))
+            .and_then(|sched_config| sched_config.restart_on_change)
+            .unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::parse_config;
+    use crate::reload::*;
+
+    #[test]
+    fn test_reload_invalid_config_keeps_old() {
+        let old_config = parse_config("[scheds.scx_bpfland]\ngaming_mode = [\"-k\"]\n");
+
+        let decision = decide_reload(
+            &old_config,
+            Err(anyhow::anyhow!("Failed to parse config")),
+            Some(&SupportedSched::Bpfland),
+        );
+        assert_eq!(
+            decision,
+            ReloadDecision::KeepOld("Failed to parse config".to_owned())
+        );
+
+        // parses, but fails validation
+        let invalid_config =
+            parse_config("[scheds.scx_lavd]\ngaming_mode = [\"--performance\", \"--powersave\"]\n");
+        assert!(matches!(
+            decide_reload(&old_config, Ok(invalid_config), None),
+            ReloadDecision::KeepOld(_)
+        ));
+    }
+
+    #[test]
+    fn test_reload_restarts_changed_sched() {
+        let old_config = parse_config(
+            r#"
+[scheds.scx_bpfland]
+gaming_mode = ["-k"]
+
+[scheds.scx_lavd]
+gaming_mode = ["--performance"]
+"#,
+        );
+        let new_config = parse_config(
+            r#"
+[scheds.scx_bpfland]
+gaming_mode = ["-k", "-m", "performance"]
+
+[scheds.scx_lavd]
+gaming_mode = ["--performance"]
+"#,
+        );
+
+        let decision = decide_reload(
+            &old_config,
+            Ok(new_config.clone()),
+            Some(&SupportedSched::Bpfland),
+        );
+        assert_eq!(
+            decision,
+            ReloadDecision::Apply {
+                config: Box::new(new_config.clone()),
+                restart: true,
+                deferred: false,
+            }
+        );
+
+        // the running scheduler didn't change
+        assert!(matches!(
+            decide_reload(
+                &old_config,
+                Ok(new_config.clone()),
+                Some(&SupportedSched::Lavd)
+            ),
+            ReloadDecision::Apply { restart: false, .. }
+        ));
+        assert!(matches!(
+            decide_reload(&old_config, Ok(new_config.clone()), None),
+            ReloadDecision::Apply { restart: false, .. }
+        ));
+
+        // global flags apply to every scheduler
+        let global_flags_config = Config {
+            global_flags: vec!["-v".to_owned()],
+            ..old_config.clone()
+        };
+        assert!(matches!(
+            decide_reload(
+                &old_config,
+                Ok(global_flags_config),
+                Some(&SupportedSched::Lavd)
+            ),
+            ReloadDecision::Apply { restart: true, .. }
+        ));
+        // as do the other global launch settings
+        for launch_config in [
+            Config {
+                oom_score_adj: Some(-1000),
+                ..old_config.clone()
+            },
+            Config {
+                cgroup: Some("scx.slice".to_owned()),
+                ..old_config.clone()
+            },
+            Config {
+                bin_dirs: vec!["/opt/scx/bin".to_owned()],
+                ..old_config.clone()
+            },
+        ] {
+            assert!(matches!(
+                decide_reload(&old_config, Ok(launch_config), Some(&SupportedSched::Lavd)),
+                ReloadDecision::Apply { restart: true, .. }
+            ));
+        }
+        // the runner picks up the restart settings without restarting the scheduler
+        let auto_restart_config = Config {
+            auto_restart: Some(true),
+            ..old_config.clone()
+        };
+        assert!(matches!(
+            decide_reload(
+                &old_config,
+                Ok(auto_restart_config),
+                Some(&SupportedSched::Lavd)
+            ),
+            ReloadDecision::Apply { restart: false, .. }
+        ));
+    }
+
+    #[test]
+    fn test_reload_defers_without_restart_on_change() {
+        let old_config = parse_config(
+            r#"
+[scheds.scx_bpfland]
+gaming_mode = ["-k"]
+restart_on_change = false
+
+[scheds.scx_lavd]
+gaming_mode = ["--performance"]
+"#,
+        );
+        let mut new_config = old_config.clone();
+        for sched_config in new_config.scheds.values_mut() {
+            sched_config.gaming_mode = Some(vec!["-v".to_owned()]);
+        }
+
+        // the changed scheduler is left running, the new config is still applied
+        assert_eq!(
+            decide_reload(
+                &old_config,
+                Ok(new_config.clone()),
+                Some(&SupportedSched::Bpfland)
+            ),
+            ReloadDecision::Apply {
+                config: Box::new(new_config.clone()),
+                restart: false,
+                deferred: true,
+            }
+        );
+        assert!(matches!(
+            decide_reload(
+                &old_config,
+                Ok(new_config.clone()),
+                Some(&SupportedSched::Lavd)
+            ),
+            ReloadDecision::Apply {
+                restart: true,
+                deferred: false,
+                ..
+            }
+        ));
+
+        // unchanged settings aren't deferred
+        assert!(matches!(
+            decide_reload(
+                &old_config,
+                Ok(old_config.clone()),
+                Some(&SupportedSched::Bpfland)
+            ),
+            ReloadDecision::Apply {
+                restart: false,
+                deferred: false,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,158 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::config::Config;
+
+/// Restarts allowed within the restart window when restart_limit isn't set
+pub const DEFAULT_RESTART_LIMIT: u32 = 3;
+
+/// Restarts older than this don't count towards the restart limit anymore
+pub const RESTART_WINDOW: Duration = Duration::from_secs(60);
+
+/// Delay before the first restart, doubled for every further restart within the window
+const RESTART_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Upper bound of the delay between restarts
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(30);
+
+/// What to do after the scheduler exited unexpectedly
+#[derive(Debug, PartialEq)]
+pub enum RestartDecision {
+    /// Restart the scheduler after the delay, attempt counts the restarts within the window
+    Restart { attempt: u32, delay: Duration },
+    /// The restart limit was reached, leave the scheduler stopped
+    GiveUp,
+}
+
+/// Bookkeeping of the restarts of a single scheduler run, to back off and stop crash loops
+#[derive(Debug)]
+pub struct RestartTracker {
+    limit: u32,
+    window: Duration,
+    restarts: Vec<Instant>,
+}
+
+impl RestartTracker {
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            restarts: vec![],
+        }
+    }
+
+    /// Get the tracker for the configured restart policy, or None if auto_restart is disabled
+    pub fn from_config(config: &Config) -> Option<Self> {
+        if !config.auto_restart.unwrap_or(false) {
+            return None;
+        }
+        let limit = config.restart_limit.unwrap_or(DEFAULT_RESTART_LIMIT);
+        Some(Self::new(limit, RESTART_WINDOW))
+    }
+
+    /// Record that the scheduler exited unexpectedly at the given time and decide whether to
+    /// restart it
+    pub fn on_exit(&mut self, now: Instant) -> RestartDecision {
+        let window = self.window;
+        self.restarts
+            .retain(|restart| now.saturating_duration_since(*restart) < window);
+        if self.restarts.len() >= self.limit as usize {
+            return RestartDecision::GiveUp;
+        }
+
+        self.restarts.push(now);
+        let attempt = self.restarts.len() as u32;
+        let delay = RESTART_BACKOFF
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(MAX_RESTART_BACKOFF);
+        RestartDecision::Restart { attempt, delay }
+    }
+
+    /// Forget the previous restarts, e.g. when a different scheduler is started on request
+    pub fn reset(&mut self) {
+        self.restarts.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::restart::*;
+
+    #[test]
+    fn test_restart_backoff_and_limit() {
+        let start = Instant::now();
+        let mut tracker = RestartTracker::new(3, RESTART_WINDOW);
+
+        assert_eq!(
+            tracker.on_exit(start),
+            RestartDecision::Restart {
+                attempt: 1,
+                delay: Duration::from_secs(1)
+            }
+        );
+        assert_eq!(
+            tracker.on_exit(start + Duration::from_secs(2)),
+            RestartDecision::Restart {
+                attempt: 2,
+                delay: Duration::from_secs(2)
+            }
+        );
+        assert_eq!(
+            tracker.on_exit(start + Duration::from_secs(5)),
+            RestartDecision::Restart {
+                attempt: 3,
+                delay: Duration::from_secs(4)
+            }
+        );
+        assert_eq!(
+            tracker.on_exit(start + Duration::from_secs(10)),
+            RestartDecision::GiveUp
+        );
+
+        // restarts outside the window are forgotten
+        assert_eq!(
+            tracker.on_exit(start + Duration::from_secs(61)),
+            RestartDecision::Restart {
+                attempt: 3,
+                delay: Duration::from_secs(4)
+            }
+        );
+
+        tracker.reset();
+        assert_eq!(
+            tracker.on_exit(start + Duration::from_secs(62)),
+            RestartDecision::Restart {
+                attempt: 1,
+                delay: Duration::from_secs(1)
+            }
+        );
+    }
+
+    #[test]
+    fn test_restart_from_config() {
+        let mut config = Config::default();
+        assert!(RestartTracker::from_config(&config).is_none());
+
+        config.auto_restart = Some(true);
+        let mut tracker = RestartTracker::from_config(&config).unwrap();
+        assert_eq!(tracker.limit, DEFAULT_RESTART_LIMIT);
+
+        config.restart_limit = Some(0);
+        let mut tracker_without_restarts = RestartTracker::from_config(&config).unwrap();
+        assert_eq!(
+            tracker_without_restarts.on_exit(Instant::now()),
+            RestartDecision::GiveUp
+        );
+        assert!(matches!(
+            tracker.on_exit(Instant::now()),
+            RestartDecision::Restart { attempt: 1, .. }
+        ));
+    }
+}
@@ -0,0 +1,193 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use crate::state::SchedState;
+
+/// Run of a switch which isn't confirmed attached yet
+#[derive(Debug)]
+struct PendingSwitch {
+    run_id: u64,
+    /// Binary and state to roll back to if the switch fails, None for rollbacks themselves
+    rollback_to: Option<(String, SchedState)>,
+}
+
+/// Tracks the last scheduler confirmed attached, to roll back to it when a switch fails
+#[derive(Debug, Default)]
+pub struct RollbackTracker {
+    last_good: Option<(String, SchedState)>,
+    pending: Option<PendingSwitch>,
+}
+
+impl RollbackTracker {
+    /// Get the binary and state of the last scheduler confirmed attached
+    pub fn last_good(&self) -> Option<&(String, SchedState)> {
+        self.last_good.as_ref()
+    }
+
+    /// Note the switch to the scheduler in the given run, it rolls back to the last known good
+    /// scheduler if it fails. Switching to the last known good scheduler again never rolls back
+    pub fn switch_started(&mut self, run_id: u64, sched_bin: &str, sched_state: &SchedState) {
+        let rollback_to = self.last_good.clone().filter(|(good_bin, good_state)| {
+            !is_same_launch(good_bin, good_state, sched_bin, sched_state)
+        });
+        self.pending = Some(PendingSwitch {
+            run_id,
+            rollback_to,
+        });
+    }
+
+    /// Note the rollback in the given run, a failing rollback doesn't roll back again
+    pub fn rollback_started(&mut self, run_id: u64) {
+        self.pending = Some(PendingSwitch {
+            run_id,
+            rollback_to: None,
+        });
+    }
+
+    /// The scheduler of the given run attached, it becomes the last known good one
+    pub fn attached(&mut self, run_id: u64, sched_bin: &str, sched_state: &SchedState) {
+        if self
+            .pending
+            .as_ref()
+            .is_some_and(|pending| pending.run_id == run_id)
+        {
+            self.pending = None;
+        }
+        self.last_good = Some((sched_bin.to_owned(), sched_state.clone()));
+    }
+
+    /// The scheduler of the given run failed to start, get the binary and state to roll back to
+    /// if the run is an unconfirmed switch
+    pub fn failed(&mut self, run_id: u64) -> Option<(String, SchedState)> {
+        let pending = self.pending.take_if(|pending| pending.run_id == run_id)?;
+        pending.rollback_to
+    }
+
+    /// Forget the last known good scheduler, e.g. after it was stopped on request
+    pub fn clear(&mut self) {
+        self.last_good = None;
+        self.pending = None;
+    }
+}
+
+/// Check whether both launches run the same scheduler the same way, ignoring the start time
+fn is_same_launch(
+    bin: &str,
+    state: &SchedState,
+    other_bin: &str,
+    other_state: &SchedState,
+) -> bool {
+    bin == other_bin
+        && state.sched == other_state.sched
+        && state.mode == other_state.mode
+        && state.flags == other_state.flags
+}
+
+/// Describe the launched scheduler, e.g. "scx_lavd with mode gaming"
+pub fn describe_launch(sched_state: &SchedState) -> String {
+    match &sched_state.mode {
+        Some(sched_mode) => format!("{} with mode {sched_mode}", sched_state.sched),
+        None => format!("{} with flags {:?}", sched_state.sched, sched_state.flags),
+    }
+}
+
+/// Error reported when the switch failed and the last known good scheduler is launched again
+pub fn rollback_error(failed_state: &SchedState, failure: &str, good_state: &SchedState) -> String {
+    format!(
+        "Switching to {} failed: {failure}, rolling back to {}",
+        describe_launch(failed_state),
+        describe_launch(good_state)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rollback::*;
+    use crate::SchedMode;
+    use crate::SupportedSched;
+
+    fn sched_state(sched: SupportedSched, mode: SchedMode) -> SchedState {
+        SchedState::new(sched, Some(mode), vec![])
+    }
+
+    #[test]
+    fn test_rollback_to_last_good() {
+        let lavd = sched_state(SupportedSched::Lavd, SchedMode::Gaming);
+        let flash = sched_state(SupportedSched::Flash, SchedMode::Gaming);
+        let mut rollback = RollbackTracker::default();
+
+        // nothing attached yet, there is nothing to roll back to
+        rollback.switch_started(1, "scx_lavd", &lavd);
+        assert_eq!(rollback.failed(1), None);
+
+        rollback.switch_started(2, "scx_lavd", &lavd);
+        rollback.attached(2, "scx_lavd", &lavd);
+        rollback.switch_started(3, "scx_flash", &flash);
+        // failures of other runs don't count
+        assert_eq!(rollback.failed(2), None);
+        assert_eq!(
+            rollback.failed(3),
+            Some(("scx_lavd".to_owned(), lavd.clone()))
+        );
+        // a run only rolls back once
+        assert_eq!(rollback.failed(3), None);
+
+        // a failing rollback doesn't roll back again
+        rollback.rollback_started(4);
+        assert_eq!(rollback.failed(4), None);
+        assert_eq!(rollback.last_good(), Some(&("scx_lavd".to_owned(), lavd)));
+    }
+
+    #[test]
+    fn test_last_good_after_attach() {
+        let lavd = sched_state(SupportedSched::Lavd, SchedMode::Gaming);
+        let flash = sched_state(SupportedSched::Flash, SchedMode::Gaming);
+        let mut rollback = RollbackTracker::default();
+        rollback.attached(1, "scx_lavd", &lavd);
+
+        // not confirmed yet, the previous scheduler is still the last known good one
+        rollback.switch_started(2, "scx_flash", &flash);
+        assert_eq!(rollback.last_good().unwrap().1, lavd);
+
+        rollback.attached(2, "scx_flash", &flash);
+        assert_eq!(rollback.last_good().unwrap().1, flash);
+        // a confirmed switch doesn't roll back if it exits later
+        assert_eq!(rollback.failed(2), None);
+
+        rollback.clear();
+        rollback.switch_started(3, "scx_lavd", &lavd);
+        assert_eq!(rollback.failed(3), None);
+    }
+
+    #[test]
+    fn test_no_rollback_to_same_launch() {
+        let lavd = sched_state(SupportedSched::Lavd, SchedMode::Gaming);
+        let mut rollback = RollbackTracker::default();
+        rollback.attached(1, "scx_lavd", &lavd);
+
+        // switching to the last known good scheduler again would fail the same way
+        let mut restarted_lavd = lavd.clone();
+        restarted_lavd.started_at = 1700000000;
+        rollback.switch_started(2, "scx_lavd", &restarted_lavd);
+        assert_eq!(rollback.failed(2), None);
+
+        // another mode of the same scheduler is a different launch
+        let powersave_lavd = sched_state(SupportedSched::Lavd, SchedMode::PowerSave);
+        rollback.switch_started(3, "scx_lavd", &powersave_lavd);
+        assert_eq!(rollback.failed(3), Some(("scx_lavd".to_owned(), lavd)));
+    }
+
+    #[test]
+    fn test_rollback_error() {
+        let lavd = sched_state(SupportedSched::Lavd, SchedMode::Gaming);
+        let flash = SchedState::new(SupportedSched::Flash, None, vec!["-k".to_owned()]);
+        assert_eq!(
+            rollback_error(&flash, "Failed to spawn scx_flash", &lavd),
+            "Switching to scx_flash with flags [\"-k\"] failed: Failed to spawn scx_flash, rolling back to scx_lavd with mode gaming"
+        );
+    }
+}
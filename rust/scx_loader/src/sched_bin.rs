@@ -0,0 +1,502 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::config;
+use crate::config::Config;
+use crate::get_name_from_scx;
+use crate::topology;
+use crate::topology::Topology;
+use crate::SchedMode;
+use crate::SupportedSched;
+use crate::SUPPORTED_SCHEDS;
+
+/// Schedulers started when no default_sched is set, the first installed and enabled one wins
+pub const AUTO_SELECT_SCHEDS: &[SupportedSched] = &[
+    SupportedSched::Lavd,
+    SupportedSched::Bpfland,
+    SupportedSched::Rusty,
+];
+
+/// Get the directories listed in PATH, in lookup order
+pub fn get_path_dirs() -> Vec<PathBuf> {
+    env::var_os("PATH")
+        .map(|path| env::split_paths(&path).collect())
+        .unwrap_or_default()
+}
+
+/// Check whether the path points to an executable file
+pub fn is_executable(path: &Path) -> bool {
+    fs::metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// Find the executable with the given name in the search directories, first match wins
+pub fn find_executable(bin_name: &str, search_dirs: &[PathBuf]) -> Option<PathBuf> {
+    search_dirs
+        .iter()
+        .map(|search_dir| search_dir.join(bin_name))
+        .find(|bin_path| is_executable(bin_path))
+}
+
+/// Get the supported schedulers whose binary is installed in PATH
+pub fn list_available_scheds() -> Vec<SupportedSched> {
+    list_available_scheds_in(&get_path_dirs())
+}
+
+/// Get the supported schedulers whose binary is installed in one of the search directories
+pub fn list_available_scheds_in(search_dirs: &[PathBuf]) -> Vec<SupportedSched> {
+    SUPPORTED_SCHEDS
+        .iter()
+        .filter(|scx_sched| find_executable(get_name_from_scx(scx_sched), search_dirs).is_some())
+        .cloned()
+        .collect()
+}
+
+/// Check whether the binary of the given scheduler is installed, custom schedulers configured with
+/// a path and schedulers found in the bin_dirs are checked directly, all others are looked up in
+/// the search directories
+pub fn is_sched_available(
+    config: &Config,
+    scx_sched: &SupportedSched,
+    search_dirs: &[PathBuf],
+) -> bool {
+    let sched_bin = config::get_scx_bin_path(config, scx_sched);
+    if sched_bin.contains('/') {
+        is_executable(Path::new(&sched_bin))
+    } else {
+        find_executable(&sched_bin, search_dirs).is_some()
+    }
+}
+
+/// Check whether the scheduler is enabled and meets its topology requirement, so it may be
+/// started. An unknown topology doesn't gate anything
+fn is_sched_startable(
+    config: &Config,
+    scx_sched: &SupportedSched,
+    topology: Option<&Topology>,
+) -> bool {
+    config.is_sched_enabled(scx_sched)
+        && topology.is_none_or(|topology| {
+            topology::check_requirement(&config.get_topology_requirement(scx_sched), topology)
+                .is_ok()
+        })
+}
+
+/// Get the scheduler to start on launch: the default_sched if it's installed, enabled and meets
+/// its topology requirement, otherwise the first such scheduler from fallback_scheds. Without
+/// default_sched one is selected from AUTO_SELECT_SCHEDS, see `auto_select_sched`
+pub fn resolve_default_sched(
+    config: &Config,
+    search_dirs: &[PathBuf],
+    topology: Option<&Topology>,
+) -> Result<Option<SupportedSched>> {
+    let Some(default_sched) = &config.default_sched else {
+        return auto_select_sched(config, search_dirs, topology).map(Some);
+    };
+
+    let default_reason = if !config.is_sched_enabled(default_sched) {
+        "disabled"
+    } else if !is_sched_startable(config, default_sched, topology) {
+        "not supported by the CPU topology"
+    } else {
+        "not installed"
+    };
+    let candidates: Vec<&SupportedSched> = std::iter::once(default_sched)
+        .chain(&config.fallback_scheds)
+        .filter(|scx_sched| is_sched_startable(config, scx_sched, topology))
+        .collect();
+    if let Some(scx_sched) = candidates
+        .iter()
+        .find(|scx_sched| is_sched_available(config, scx_sched, search_dirs))
+    {
+        if *scx_sched != default_sched {
+            log::warn!("{default_sched} is {default_reason}, falling back to {scx_sched}");
+        }
+        return Ok(Some((*scx_sched).clone()));
+    }
+
+    if candidates.is_empty() {
+        anyhow::bail!(
+            "{default_sched} is {default_reason} and none of fallback_scheds is enabled and \
+             supported by the CPU topology"
+        );
+    }
+    let tried: Vec<&str> = candidates.into_iter().map(get_name_from_scx).collect();
+    anyhow::bail!("None of the schedulers is installed: {}", tried.join(", "));
+}
+
+/// Get the scheduler and its flags for the given sched mode: the scheduler the mode selects in
+/// [mode_scheds], otherwise the one `resolve_default_sched` resolves
+pub fn resolve_mode_sched(
+    config: &Config,
+    sched_mode: SchedMode,
+    search_dirs: &[PathBuf],
+    topology: Option<&Topology>,
+) -> Result<(SupportedSched, Vec<String>)> {
+    let scx_sched = match config::get_mode_sched(config, &sched_mode) {
+        Some(scx_sched) => scx_sched.clone(),
+        None => resolve_default_sched(config, search_dirs, topology)?
+            .context("No scheduler to start")?,
+    };
+    let scx_flags = config::get_scx_flags_for_mode(config, &scx_sched, sched_mode);
+    Ok((scx_sched, scx_flags))
+}
+
+/// Get the scheduler and mode to start on launch. A global default_mode which selects its own
+/// scheduler starts that one, otherwise the default scheduler starts with its default_mode
+pub fn resolve_launch_sched(
+    config: &Config,
+    search_dirs: &[PathBuf],
+    topology: Option<&Topology>,
+) -> Result<(SupportedSched, SchedMode)> {
+    let global_mode = config.default_mode.clone().unwrap_or(SchedMode::Auto);
+    let mode_selects_sched = config::get_mode_sched(config, &global_mode).is_some();
+    let (scx_sched, _) = resolve_mode_sched(config, global_mode.clone(), search_dirs, topology)?;
+    let sched_mode = match mode_selects_sched {
+        true => global_mode,
+        false => config::resolve_default_mode(config, &scx_sched),
+    };
+    Ok((scx_sched, sched_mode))
+}
+
+/// Select the first installed and enabled scheduler of AUTO_SELECT_SCHEDS which meets its
+/// topology requirement
+pub fn auto_select_sched(
+    config: &Config,
+    search_dirs: &[PathBuf],
+    topology: Option<&Topology>,
+) -> Result<SupportedSched> {
+    let candidates: Vec<&SupportedSched> = AUTO_SELECT_SCHEDS
+        .iter()
+        .filter(|scx_sched| is_sched_startable(config, scx_sched, topology))
+        .collect();
+    if let Some(scx_sched) = candidates
+        .iter()
+        .find(|scx_sched| is_sched_available(config, scx_sched, search_dirs))
+    {
+        log::info!("no default_sched is set, selected {scx_sched}");
+        return Ok((*scx_sched).clone());
+    }
+
+    let tried: Vec<&str> = candidates.into_iter().map(get_name_from_scx).collect();
+    anyhow::bail!(
+        "No default_sched is set and none of the schedulers is installed: {}",
+        tried.join(", ")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    use crate::sched_bin::*;
+
+    fn create_executable(path: &Path) {
+        fs::write(path, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn test_list_available_scheds() {
+        let tmp = tempfile::tempdir().unwrap();
+        let first_dir = tmp.path().join("bin");
+        let second_dir = tmp.path().join("opt/bin");
+        fs::create_dir_all(&first_dir).unwrap();
+        fs::create_dir_all(&second_dir).unwrap();
+
+        create_executable(&first_dir.join("scx_lavd"));
+        create_executable(&second_dir.join("scx_bpfland"));
+        // not executable, so it's not available
+        fs::write(first_dir.join("scx_rusty"), "").unwrap();
+
+        let search_dirs = env::split_paths(&env::join_paths([&first_dir, &second_dir]).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            list_available_scheds_in(&search_dirs),
+            vec![SupportedSched::Bpfland, SupportedSched::Lavd]
+        );
+        assert_eq!(
+            find_executable("scx_bpfland", &search_dirs),
+            Some(second_dir.join("scx_bpfland"))
+        );
+        assert!(list_available_scheds_in(&[tmp.path().join("missing")]).is_empty());
+    }
+
+    #[test]
+    fn test_bin_dirs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let bin_dir = tmp.path().join("opt/scx/bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        create_executable(&bin_dir.join("scx_lavd"));
+
+        let mut config = Config {
+            bin_dirs: vec![
+                tmp.path().join("missing").to_str().unwrap().to_owned(),
+                bin_dir.to_str().unwrap().to_owned(),
+            ],
+            ..Default::default()
+        };
+        assert!(is_sched_available(&config, &SupportedSched::Lavd, &[]));
+        assert_eq!(
+            config::get_scx_bin_path(&config, &SupportedSched::Lavd),
+            bin_dir.join("scx_lavd").to_str().unwrap()
+        );
+        assert!(!is_sched_available(&config, &SupportedSched::Bpfland, &[]));
+        assert_eq!(
+            config::get_scx_bin_path(&config, &SupportedSched::Bpfland),
+            "scx_bpfland"
+        );
+
+        config.bin_dirs.clear();
+        assert!(!is_sched_available(&config, &SupportedSched::Lavd, &[]));
+    }
+
+    #[test]
+    fn test_resolve_default_sched() {
+        let tmp = tempfile::tempdir().unwrap();
+        create_executable(&tmp.path().join("scx_bpfland"));
+        create_executable(&tmp.path().join("scx_rusty"));
+        let search_dirs = vec![tmp.path().to_path_buf()];
+
+        let mut config = Config {
+            default_sched: Some(SupportedSched::Lavd),
+            fallback_scheds: vec![SupportedSched::Flash, SupportedSched::Rusty],
+            ..Default::default()
+        };
+        assert_eq!(
+            resolve_default_sched(&config, &search_dirs, None).unwrap(),
+            Some(SupportedSched::Rusty)
+        );
+
+        config.default_sched = Some(SupportedSched::Bpfland);
+        assert_eq!(
+            resolve_default_sched(&config, &search_dirs, None).unwrap(),
+            Some(SupportedSched::Bpfland)
+        );
+
+        // disabled schedulers are skipped as fallback
+        config.default_sched = Some(SupportedSched::Lavd);
+        config.scheds.insert(
+            "scx_rusty".to_owned(),
+            config::Sched {
+                enabled: Some(false),
+                ..Default::default()
+            },
+        );
+        let err = resolve_default_sched(&config, &search_dirs, None).unwrap_err();
+        assert!(err.to_string().contains("scx_lavd, scx_flash"));
+
+        config.fallback_scheds = vec![SupportedSched::Flash];
+        let err = resolve_default_sched(&config, &search_dirs, None).unwrap_err();
+        assert!(err.to_string().contains("scx_lavd, scx_flash"));
+
+        // a disabled default_sched isn't started even though it's installed
+        config.default_sched = Some(SupportedSched::Bpfland);
+        config.fallback_scheds = vec![SupportedSched::Flash, SupportedSched::Rusty];
+        config.scheds.clear();
+        config.scheds.insert(
+            "scx_bpfland".to_owned(),
+            config::Sched {
+                enabled: Some(false),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            resolve_default_sched(&config, &search_dirs, None).unwrap(),
+            Some(SupportedSched::Rusty)
+        );
+        config.fallback_scheds.clear();
+        assert_eq!(
+            resolve_default_sched(&config, &search_dirs, None)
+                .unwrap_err()
+                .to_string(),
+            "scx_bpfland is disabled and none of fallback_scheds is enabled and supported by the CPU \
+             topology"
+        );
+
+        // without default_sched one is selected
+        config.scheds.clear();
+        config.default_sched = None;
+        assert_eq!(
+            resolve_default_sched(&config, &search_dirs, None).unwrap(),
+            Some(SupportedSched::Bpfland)
+        );
+    }
+
+    #[test]
+    fn test_auto_select_sched() {
+        let tmp = tempfile::tempdir().unwrap();
+        let search_dirs = vec![tmp.path().to_path_buf()];
+        let mut config = Config::default();
+
+        let err = auto_select_sched(&config, &search_dirs, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "No default_sched is set and none of the schedulers is installed: \
+             scx_lavd, scx_bpfland, scx_rusty"
+        );
+
+        create_executable(&tmp.path().join("scx_rusty"));
+        assert_eq!(
+            auto_select_sched(&config, &search_dirs, None).unwrap(),
+            SupportedSched::Rusty
+        );
+
+        // the preference order wins over the order of installing
+        create_executable(&tmp.path().join("scx_bpfland"));
+        create_executable(&tmp.path().join("scx_lavd"));
+        assert_eq!(
+            auto_select_sched(&config, &search_dirs, None).unwrap(),
+            SupportedSched::Lavd
+        );
+
+        // disabled schedulers aren't selected
+        config.scheds.insert(
+            "scx_lavd".to_owned(),
+            config::Sched {
+                enabled: Some(false),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            auto_select_sched(&config, &search_dirs, None).unwrap(),
+            SupportedSched::Bpfland
+        );
+    }
+
+    #[test]
+    fn test_resolve_mode_sched() {
+        let tmp = tempfile::tempdir().unwrap();
+        create_executable(&tmp.path().join("scx_rusty"));
+        let search_dirs = vec![tmp.path().to_path_buf()];
+        let mut config: Config = toml::from_str(
+            r#"
+default_sched = "scx_bpfland"
+fallback_scheds = ["scx_rusty"]
+
+[mode_scheds]
+gaming_mode = "scx_lavd"
+powersave_mode = "scx_tickless"
+
+[scheds.scx_tickless]
+powersave_mode = "--frequency 100"
+"#,
+        )
+        .unwrap();
+
+        let (gaming_sched, gaming_flags) =
+            resolve_mode_sched(&config, SchedMode::Gaming, &search_dirs, None).unwrap();
+        assert_eq!(gaming_sched, SupportedSched::Lavd);
+        assert_eq!(
+            gaming_flags,
+            config::get_scx_flags_for_mode(&config, &SupportedSched::Lavd, SchedMode::Gaming)
+        );
+        assert_eq!(
+            resolve_mode_sched(&config, SchedMode::PowerSave, &search_dirs, None).unwrap(),
+            (
+                SupportedSched::Tickless,
+                vec!["--frequency".to_owned(), "100".to_owned()]
+            )
+        );
+        // the other modes resolve the default_sched, which isn't installed
+        assert_eq!(
+            resolve_mode_sched(&config, SchedMode::LowLatency, &search_dirs, None)
+                .unwrap()
+                .0,
+            SupportedSched::Rusty
+        );
+
+        // the default mode selects the scheduler started on launch
+        assert_eq!(
+            resolve_launch_sched(&config, &search_dirs, None).unwrap(),
+            (SupportedSched::Rusty, SchedMode::Auto)
+        );
+        config.default_mode = Some(SchedMode::PowerSave);
+        assert_eq!(
+            resolve_launch_sched(&config, &search_dirs, None).unwrap(),
+            (SupportedSched::Tickless, SchedMode::PowerSave)
+        );
+
+        // nothing installed to fall back to
+        config.fallback_scheds.clear();
+        assert!(resolve_mode_sched(&config, SchedMode::Auto, &search_dirs, None).is_err());
+    }
+
+    #[test]
+    fn test_resolve_gated_default_sched() {
+        let tmp = tempfile::tempdir().unwrap();
+        create_executable(&tmp.path().join("scx_layered"));
+        create_executable(&tmp.path().join("scx_bpfland"));
+        let search_dirs = vec![tmp.path().to_path_buf()];
+        let mut config: Config = toml::from_str(
+            r#"
+default_sched = "scx_layered"
+fallback_scheds = ["scx_bpfland"]
+
+[scheds.scx_layered]
+min_cpus = 16
+"#,
+        )
+        .unwrap();
+        let eight_cores = Topology {
+            cpus: 8,
+            hybrid: false,
+        };
+
+        // the default_sched can't run on this machine, the ungated fallback is started instead
+        assert_eq!(
+            resolve_default_sched(&config, &search_dirs, Some(&eight_cores)).unwrap(),
+            Some(SupportedSched::Bpfland)
+        );
+        assert_eq!(
+            resolve_launch_sched(&config, &search_dirs, Some(&eight_cores))
+                .unwrap()
+                .0,
+            SupportedSched::Bpfland
+        );
+        // an unknown topology doesn't gate it
+        assert_eq!(
+            resolve_default_sched(&config, &search_dirs, None).unwrap(),
+            Some(SupportedSched::Layered)
+        );
+
+        config.fallback_scheds.clear();
+        assert_eq!(
+            resolve_default_sched(&config, &search_dirs, Some(&eight_cores))
+                .unwrap_err()
+                .to_string(),
+            "scx_layered is not supported by the CPU topology and none of fallback_scheds is \
+             enabled and supported by the CPU topology"
+        );
+
+        // without default_sched the gated schedulers are skipped too
+        config.default_sched = None;
+        config.scheds.insert(
+            "scx_lavd".to_owned(),
+            config::Sched {
+                require_hybrid: Some(true),
+                ..Default::default()
+            },
+        );
+        create_executable(&tmp.path().join("scx_lavd"));
+        assert_eq!(
+            auto_select_sched(&config, &search_dirs, Some(&eight_cores)).unwrap(),
+            SupportedSched::Bpfland
+        );
+    }
+}
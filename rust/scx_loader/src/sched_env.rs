@@ -0,0 +1,116 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::flags;
+use crate::get_name_from_scx;
+use crate::SupportedSched;
+
+impl Config {
+    /// Get the environment variables set for the scheduler process, sorted by name and with
+    /// the `${VAR}` references in the values expanded like in the flags
+    pub fn get_sched_env(&self, scx_sched: &SupportedSched) -> Vec<(String, String)> {
+        let Some(env) = self
+            .scheds
+            .get(get_name_from_scx(scx_sched))
+            .and_then(|sched_config| sched_config.env.as_ref())
+        else {
+            return vec![];
+        };
+        expand_env_with(env, |var_name| std::env::var(var_name).ok())
+    }
+}
+
+fn expand_env_with(
+    env: &HashMap<String, String>,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Vec<(String, String)> {
+    let mut expanded: Vec<(String, String)> = env
+        .iter()
+        .map(|(var_name, value)| (var_name.clone(), flags::expand_vars_with(value, &lookup)))
+        .collect();
+    expanded.sort_unstable();
+    expanded
+}
+
+/// Check that the name can be set as environment variable
+pub fn validate_env_var_name(var_name: &str) -> Result<()> {
+    if var_name.is_empty() || var_name.contains(['=', '\0']) {
+        anyhow::bail!("invalid variable name {var_name:?}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sched_env::*;
+
+    fn lookup(var_name: &str) -> Option<String> {
+        match var_name {
+            "HOME" => Some("/home/user".to_owned()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_sched_env() {
+        let config: Config = toml::from_str(
+            r#"
+[scheds.scx_layered.env]
+RUST_LOG = "info"
+LAYERED_CONFIG = "${HOME}/.config/layered.json"
+STATE_DIR = "${SCX_LOADER_TEST_UNSET_VAR}/state"
+"#,
+        )
+        .unwrap();
+        let env = config.scheds["scx_layered"].env.as_ref().unwrap();
+
+        assert_eq!(
+            expand_env_with(env, lookup),
+            vec![
+                (
+                    "LAYERED_CONFIG".to_owned(),
+                    "/home/user/.config/layered.json".to_owned()
+                ),
+                ("RUST_LOG".to_owned(), "info".to_owned()),
+                // unset variables are kept as is
+                (
+                    "STATE_DIR".to_owned(),
+                    "${SCX_LOADER_TEST_UNSET_VAR}/state".to_owned()
+                ),
+            ]
+        );
+        assert_eq!(
+            config.get_sched_env(&SupportedSched::Layered)[2],
+            (
+                "STATE_DIR".to_owned(),
+                "${SCX_LOADER_TEST_UNSET_VAR}/state".to_owned()
+            )
+        );
+        assert!(config.get_sched_env(&SupportedSched::Lavd).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_env_var_name() {
+        let config: Config = toml::from_str(
+            r#"
+[scheds.scx_lavd.env]
+"RUST=LOG" = "info"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.validation_report().errors,
+            vec!["scx_lavd env: invalid variable name \"RUST=LOG\""]
+        );
+        assert!(validate_env_var_name("RUST_LOG").is_ok());
+    }
+}
@@ -0,0 +1,210 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use chrono::NaiveTime;
+use chrono::Timelike;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::config;
+use crate::config::Config;
+use crate::SchedMode;
+use crate::SupportedSched;
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// Time of day in the "HH:MM" format
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ClockTime(NaiveTime);
+
+impl ClockTime {
+    /// Get the minutes since midnight
+    fn minutes(&self) -> u32 {
+        self.0.hour() * 60 + self.0.minute()
+    }
+}
+
+impl TryFrom<String> for ClockTime {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        NaiveTime::parse_from_str(&value, "%H:%M")
+            .map(ClockTime)
+            .map_err(|_| format!("invalid time {value}, expected HH:MM"))
+    }
+}
+
+impl From<ClockTime> for String {
+    fn from(clock_time: ClockTime) -> Self {
+        clock_time.0.format("%H:%M").to_string()
+    }
+}
+
+/// Time window in which the given scheduler should run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduleEntry {
+    /// Start of the window, inclusive
+    pub start: ClockTime,
+    /// End of the window, exclusive. Windows ending before they start span midnight, windows
+    /// ending when they start cover the whole day
+    pub end: ClockTime,
+    pub sched: SupportedSched,
+    /// Mode of the scheduler, defaults to the default mode of the scheduler
+    pub mode: Option<SchedMode>,
+}
+
+impl ScheduleEntry {
+    /// Get the length of the window in minutes
+    fn len_minutes(&self) -> u32 {
+        match (self.end.minutes() + MINUTES_PER_DAY - self.start.minutes()) % MINUTES_PER_DAY {
+            0 => MINUTES_PER_DAY,
+            len => len,
+        }
+    }
+
+    /// Check whether the time of day falls within the window
+    fn contains(&self, now: NaiveTime) -> bool {
+        let now = now.hour() * 60 + now.minute();
+        let since_start = (now + MINUTES_PER_DAY - self.start.minutes()) % MINUTES_PER_DAY;
+        since_start < self.len_minutes()
+    }
+}
+
+impl Config {
+    /// Get the scheduler and mode scheduled for the given time of day, if any.
+    ///
+    /// Of overlapping windows the shortest one wins, as it's the most specific. Windows of the
+    /// same length are picked in config order.
+    pub fn current_scheduled_choice(&self, now: NaiveTime) -> Option<(SupportedSched, SchedMode)> {
+        let mut choice: Option<&ScheduleEntry> = None;
+        for entry in self.schedule.iter().filter(|entry| entry.contains(now)) {
+            if choice.is_none_or(|choice| entry.len_minutes() < choice.len_minutes()) {
+                choice = Some(entry);
+            }
+        }
+
+        choice.map(|entry| {
+            let sched_mode = entry
+                .mode
+                .clone()
+                .unwrap_or_else(|| config::resolve_default_mode(self, &entry.sched));
+            (entry.sched.clone(), sched_mode)
+        })
+    }
+}
+
+/// Follows the schedule, reporting each change of the active window once
+#[derive(Debug, Default)]
+pub struct ScheduleFollower {
+    last_choice: Option<(SupportedSched, SchedMode)>,
+}
+
+impl ScheduleFollower {
+    /// Get the scheduler and mode to switch to if the active window changed since the last
+    /// poll. Leaving a window keeps the current scheduler running, so it reports no switch
+    pub fn poll(&mut self, config: &Config, now: NaiveTime) -> Option<(SupportedSched, SchedMode)> {
+        let choice = config.current_scheduled_choice(now);
+        if choice == self.last_choice {
+            return None;
+        }
+        self.last_choice = choice.clone();
+        choice
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::parse_config;
+    use crate::schedule::*;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_schedule_across_midnight() {
+        let config = parse_config(
+            r#"
+[[schedule]]
+start = "22:00"
+end = "07:00"
+sched = "scx_bpfland"
+mode = "PowerSave"
+"#,
+        );
+
+        let powersave = Some((SupportedSched::Bpfland, SchedMode::PowerSave));
+        assert_eq!(config.current_scheduled_choice(time(22, 0)), powersave);
+        assert_eq!(config.current_scheduled_choice(time(23, 59)), powersave);
+        assert_eq!(config.current_scheduled_choice(time(0, 0)), powersave);
+        assert_eq!(config.current_scheduled_choice(time(6, 59)), powersave);
+        assert_eq!(config.current_scheduled_choice(time(7, 0)), None);
+        assert_eq!(config.current_scheduled_choice(time(12, 0)), None);
+    }
+
+    #[test]
+    fn test_schedule_overlap() {
+        let config = parse_config(
+            r#"
+default_mode = "Gaming"
+
+[[schedule]]
+start = "18:00"
+end = "23:00"
+sched = "scx_lavd"
+
+[[schedule]]
+start = "20:00"
+end = "21:00"
+sched = "scx_bpfland"
+mode = "LowLatency"
+
+[[schedule]]
+start = "20:00"
+end = "21:00"
+sched = "scx_flash"
+"#,
+        );
+
+        assert_eq!(
+            config.current_scheduled_choice(time(19, 0)),
+            Some((SupportedSched::Lavd, SchedMode::Gaming))
+        );
+        assert_eq!(
+            config.current_scheduled_choice(time(20, 30)),
+            Some((SupportedSched::Bpfland, SchedMode::LowLatency))
+        );
+        assert_eq!(
+            config.current_scheduled_choice(time(22, 0)),
+            Some((SupportedSched::Lavd, SchedMode::Gaming))
+        );
+    }
+
+    #[test]
+    fn test_schedule_invalid_time() {
+        let config_str = r#"
+[[schedule]]
+start = "25:00"
+end = "07:00"
+sched = "scx_bpfland"
+"#;
+        let err = toml::from_str::<Config>(config_str).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("invalid time 25:00, expected HH:MM"));
+
+        let entry = ScheduleEntry {
+            start: ClockTime::try_from("08:00".to_owned()).unwrap(),
+            end: ClockTime::try_from("08:00".to_owned()).unwrap(),
+            sched: SupportedSched::Rusty,
+            mode: None,
+        };
+        assert!(entry.contains(time(3, 0)));
+        assert_eq!(String::from(entry.start), "08:00");
+    }
+}
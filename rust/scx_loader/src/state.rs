@@ -0,0 +1,135 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::cgroup::SchedCgroup;
+use crate::hooks::Hooks;
+use crate::kernel::KernelVersion;
+use crate::output::LogOutput;
+use crate::topology::TopologyRequirement;
+use crate::SchedMode;
+use crate::SupportedSched;
+
+/// Scheduler launched last by the loader
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SchedState {
+    pub sched: SupportedSched,
+    /// Mode the scheduler was started with, None if it was started with explicit args
+    pub mode: Option<SchedMode>,
+    /// Flags the scheduler was started with
+    pub flags: Vec<String>,
+    /// Seconds since the Unix epoch when the scheduler was started, 0 until it's started
+    pub started_at: u64,
+    /// CPUs the scheduler process is pinned to, None if it isn't pinned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_affinity: Option<Vec<usize>>,
+    /// Commands run around the scheduler process, not part of the reported state
+    #[serde(skip)]
+    pub hooks: Hooks,
+    /// Oldest kernel the scheduler can be started on, None if it isn't checked
+    #[serde(skip)]
+    pub min_kernel: Option<KernelVersion>,
+    /// Refuse to start the scheduler with flags its --help doesn't list
+    #[serde(skip)]
+    pub strict_flags: bool,
+    /// OOM score adjustment applied to the scheduler process
+    #[serde(skip)]
+    pub oom_score_adj: Option<i32>,
+    /// File the output of the scheduler process is written to, None if it's inherited
+    #[serde(skip)]
+    pub log_output: Option<LogOutput>,
+    /// CPU topology the scheduler requires to be started
+    #[serde(skip)]
+    pub topology: TopologyRequirement,
+    /// Environment variables set for the scheduler process
+    #[serde(skip)]
+    pub env: Vec<(String, String)>,
+    /// cgroup the scheduler process is placed in, None if it stays in the one of the loader
+    #[serde(skip)]
+    pub cgroup: Option<SchedCgroup>,
+}
+
+impl SchedState {
+    pub fn new(sched: SupportedSched, mode: Option<SchedMode>, flags: Vec<String>) -> Self {
+        Self {
+            sched,
+            mode,
+            flags,
+            started_at: 0,
+            cpu_affinity: None,
+            hooks: Hooks::default(),
+            min_kernel: None,
+            strict_flags: false,
+            oom_score_adj: None,
+            log_output: None,
+            topology: TopologyRequirement::default(),
+            env: vec![],
+            cgroup: None,
+        }
+    }
+
+    /// Check whether both launch the same process, i.e. everything but the mode and when it was
+    /// started is equal
+    pub fn launch_eq(&self, other: &SchedState) -> bool {
+        let normalized = SchedState {
+            mode: other.mode.clone(),
+            started_at: other.started_at,
+            ..self.clone()
+        };
+        normalized == *other
+    }
+
+    /// Record that the scheduler was started just now
+    pub fn mark_started(&mut self) {
+        self.started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs())
+            .unwrap_or_default();
+    }
+}
+
+/// Serialize the state into JSON, no running scheduler is serialized as null
+pub fn state_to_json(sched_state: Option<&SchedState>) -> Result<String> {
+    serde_json::to_string(&sched_state).context("Failed to serialize scheduler state")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state::*;
+
+    #[test]
+    fn test_sched_state() {
+        let mut sched_state = SchedState::new(
+            SupportedSched::Bpfland,
+            Some(SchedMode::Gaming),
+            vec!["-m".to_owned(), "performance".to_owned()],
+        );
+        assert_eq!(sched_state.started_at, 0);
+
+        sched_state.mark_started();
+        assert!(sched_state.started_at > 0);
+
+        sched_state.started_at = 1700000000;
+        let json = state_to_json(Some(&sched_state)).unwrap();
+        assert_eq!(
+            json,
+            r#"{"sched":"scx_bpfland","mode":"Gaming","flags":["-m","performance"],"started_at":1700000000}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<SchedState>(&json).unwrap(),
+            sched_state
+        );
+        assert_eq!(state_to_json(None).unwrap(), "null");
+    }
+}
@@ -0,0 +1,175 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::fmt::Write;
+
+use anyhow::Context;
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::get_name_from_scx;
+use crate::metrics::MetricsSnapshot;
+use crate::state::SchedState;
+
+/// Status of the running scheduler as printed by --status
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Status {
+    /// Name of the running scheduler, None if none is running
+    pub sched: Option<String>,
+    /// Mode the scheduler was started with, None if it was started with explicit args
+    pub mode: Option<String>,
+    pub flags: Vec<String>,
+    /// Seconds the scheduler is running for
+    pub uptime: Option<u64>,
+    /// Restarts of the running scheduler after it exited or detached unexpectedly
+    pub restarts: u64,
+}
+
+impl Status {
+    /// Build the status from the CurrentState and Metrics of the loader, `now` being the seconds
+    /// since the Unix epoch
+    pub fn new(sched_state: Option<&SchedState>, metrics: &MetricsSnapshot, now: u64) -> Self {
+        let Some(sched_state) = sched_state else {
+            return Self {
+                sched: None,
+                mode: None,
+                flags: vec![],
+                uptime: None,
+                restarts: 0,
+            };
+        };
+        let sched_name = get_name_from_scx(&sched_state.sched);
+        Self {
+            sched: Some(sched_name.to_owned()),
+            mode: sched_state.mode.as_ref().map(ToString::to_string),
+            flags: sched_state.flags.clone(),
+            uptime: (sched_state.started_at != 0)
+                .then(|| now.saturating_sub(sched_state.started_at)),
+            restarts: metrics
+                .scheds
+                .get(sched_name)
+                .map_or(0, |sched_metrics| sched_metrics.restarts),
+        }
+    }
+
+    /// Parse the JSON returned by the CurrentState and Metrics DBUS methods
+    pub fn from_json(state_json: &str, metrics_json: &str, now: u64) -> Result<Self> {
+        let sched_state: Option<SchedState> =
+            serde_json::from_str(state_json).context("Failed to parse scheduler state")?;
+        let metrics: MetricsSnapshot =
+            serde_json::from_str(metrics_json).context("Failed to parse metrics")?;
+        Ok(Self::new(sched_state.as_ref(), &metrics, now))
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string(self).context("Failed to serialize status")
+    }
+
+    /// Format the status as a table of one field per line, "none" if no scheduler is running
+    pub fn to_table(&self) -> String {
+        let Some(sched) = &self.sched else {
+            return format!("{:<10} none\n", "scheduler");
+        };
+        let flags = if self.flags.is_empty() {
+            "-".to_owned()
+        } else {
+            self.flags.join(" ")
+        };
+        let mut table = String::new();
+        for (field, value) in [
+            ("scheduler", sched.clone()),
+            ("mode", self.mode.clone().unwrap_or_else(|| "-".to_owned())),
+            ("flags", flags),
+            (
+                "uptime",
+                self.uptime.map_or_else(|| "-".to_owned(), format_uptime),
+            ),
+            ("restarts", self.restarts.to_string()),
+        ] {
+            let _ = writeln!(table, "{field:<10} {value}");
+        }
+        table
+    }
+}
+
+/// Format the seconds as e.g. "1h 2m 3s", leaving out the leading zero units
+fn format_uptime(uptime: u64) -> String {
+    let (hours, minutes, seconds) = (uptime / 3600, uptime / 60 % 60, uptime % 60);
+    if hours > 0 {
+        format!("{hours}h {minutes}m {seconds}s")
+    } else if minutes > 0 {
+        format!("{minutes}m {seconds}s")
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::Metrics;
+    use crate::status::*;
+    use crate::SchedMode;
+    use crate::SupportedSched;
+
+    #[test]
+    fn test_active_status() {
+        let mut sched_state = SchedState::new(
+            SupportedSched::Lavd,
+            Some(SchedMode::Gaming),
+            vec!["--performance".to_owned()],
+        );
+        sched_state.started_at = 1000;
+        let metrics = Metrics::default();
+        metrics.record_switch(&SupportedSched::Lavd);
+        metrics.record_restart(&SupportedSched::Lavd);
+        metrics.record_restart(&SupportedSched::Bpfland);
+
+        let status = Status::from_json(
+            &crate::state::state_to_json(Some(&sched_state)).unwrap(),
+            &metrics.snapshot().to_json().unwrap(),
+            1000 + 3723,
+        )
+        .unwrap();
+        assert_eq!(
+            status.to_table(),
+            "scheduler  scx_lavd\n\
+             mode       gaming\n\
+             flags      --performance\n\
+             uptime     1h 2m 3s\n\
+             restarts   1\n"
+        );
+        assert_eq!(
+            status.to_json().unwrap(),
+            r#"{"sched":"scx_lavd","mode":"gaming","flags":["--performance"],"uptime":3723,"restarts":1}"#
+        );
+
+        // started with explicit args
+        sched_state.mode = None;
+        sched_state.flags = vec![];
+        let status = Status::new(Some(&sched_state), &MetricsSnapshot::default(), 1042);
+        assert_eq!(
+            status.to_table(),
+            "scheduler  scx_lavd\n\
+             mode       -\n\
+             flags      -\n\
+             uptime     42s\n\
+             restarts   0\n"
+        );
+    }
+
+    #[test]
+    fn test_inactive_status() {
+        let status =
+            Status::from_json("null", &Metrics::default().snapshot().to_json().unwrap(), 0)
+                .unwrap();
+        assert_eq!(status.to_table(), "scheduler  none\n");
+        assert_eq!(
+            status.to_json().unwrap(),
+            r#"{"sched":null,"mode":null,"flags":[],"uptime":null,"restarts":0}"#
+        );
+    }
+}
@@ -0,0 +1,108 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::history::SwitchHistory;
+use crate::rollback::RollbackTracker;
+use crate::state::SchedState;
+
+/// What happens to the running scheduler when the loader shuts down
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum StopBehavior {
+    /// Stop the scheduler before exiting, so the system reverts to the default scheduler
+    #[default]
+    StopScheduler,
+    /// Leave the scheduler attached, e.g. while the loader is restarted for an upgrade
+    LeaveRunning,
+}
+
+impl Config {
+    pub fn get_stop_behavior(&self) -> StopBehavior {
+        self.stop_behavior.unwrap_or_default()
+    }
+}
+
+/// Decide whether the scheduler is stopped when the loader shuts down. Without a running
+/// scheduler the stop still cancels a pending restart
+pub fn stops_on_shutdown(stop_behavior: StopBehavior, sched_running: bool) -> bool {
+    match stop_behavior {
+        StopBehavior::StopScheduler => true,
+        StopBehavior::LeaveRunning => !sched_running,
+    }
+}
+
+/// Forget the running scheduler on a requested stop, so it's neither restarted nor rolled back
+/// to, and record the stop in the history.
+///
+/// Returns the binary and state of the stopped scheduler to run its poststop hooks, None if no
+/// scheduler was running, which makes stopping again a no-op.
+pub fn stop_managed(
+    current_sched: &mut Option<(String, SchedState)>,
+    rollback: &mut RollbackTracker,
+    history: &mut SwitchHistory,
+) -> Option<(String, SchedState)> {
+    rollback.clear();
+    let (sched_bin, stopped_state) = current_sched.take()?;
+    history.record_stop(stopped_state.clone());
+    Some((sched_bin, stopped_state))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stop::*;
+    use crate::SchedMode;
+    use crate::SupportedSched;
+
+    #[test]
+    fn test_stop_managed() {
+        let sched_state = SchedState::new(SupportedSched::Lavd, Some(SchedMode::Gaming), vec![]);
+        let mut current_sched = Some(("scx_lavd".to_owned(), sched_state.clone()));
+        let mut rollback = RollbackTracker::default();
+        rollback.attached(1, "scx_lavd", &sched_state);
+        let mut history = SwitchHistory::new(8, false);
+        history.record(sched_state.clone(), "start");
+
+        assert_eq!(
+            stop_managed(&mut current_sched, &mut rollback, &mut history),
+            Some(("scx_lavd".to_owned(), sched_state.clone()))
+        );
+        assert_eq!(current_sched, None);
+        // nothing to roll back to, so a later failing switch leaves the scheduler stopped
+        assert!(rollback.last_good().is_none());
+        let reasons: Vec<&str> = history
+            .records()
+            .map(|record| record.reason.as_str())
+            .collect();
+        assert_eq!(reasons, vec!["start", "stop"]);
+
+        // stopping again is a no-op
+        assert_eq!(
+            stop_managed(&mut current_sched, &mut rollback, &mut history),
+            None
+        );
+        assert_eq!(history.records().count(), 2);
+    }
+
+    #[test]
+    fn test_stops_on_shutdown() {
+        assert!(stops_on_shutdown(StopBehavior::StopScheduler, true));
+        assert!(stops_on_shutdown(StopBehavior::StopScheduler, false));
+        assert!(!stops_on_shutdown(StopBehavior::LeaveRunning, true));
+        // nothing is left running, a pending restart is cancelled
+        assert!(stops_on_shutdown(StopBehavior::LeaveRunning, false));
+
+        let config: Config = toml::from_str("stop_behavior = \"LeaveRunning\"\n").unwrap();
+        assert_eq!(config.get_stop_behavior(), StopBehavior::LeaveRunning);
+        assert_eq!(
+            Config::default().get_stop_behavior(),
+            StopBehavior::StopScheduler
+        );
+    }
+}
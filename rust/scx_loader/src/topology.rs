@@ -0,0 +1,279 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::config;
+use crate::config::Config;
+use crate::get_name_from_scx;
+use crate::SupportedSched;
+use crate::SUPPORTED_SCHEDS;
+
+/// CPU topology the schedulers are gated on
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Topology {
+    /// Number of CPUs available, like nproc reports
+    pub cpus: usize,
+    /// Whether the CPUs have different capacities, e.g. big.LITTLE or P- and E-cores
+    pub hybrid: bool,
+}
+
+/// Topology a scheduler requires, the default doesn't require anything
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TopologyRequirement {
+    pub min_cpus: Option<usize>,
+    pub require_hybrid: bool,
+}
+
+/// Source of the number of CPUs and whether they're hybrid
+pub trait CpuTopology {
+    fn read(&self) -> Result<Topology>;
+}
+
+/// Reads the CPU topology from sysfs
+#[derive(Debug, Clone)]
+pub struct SysfsCpuTopology {
+    sysfs_path: PathBuf,
+}
+
+impl Default for SysfsCpuTopology {
+    fn default() -> Self {
+        Self::new(Path::new(config::DEFAULT_SYSFS_PATH))
+    }
+}
+
+impl SysfsCpuTopology {
+    /// Read the CPU topology from the sysfs mounted at the given path
+    pub fn new(sysfs_path: &Path) -> Self {
+        Self {
+            sysfs_path: sysfs_path.to_path_buf(),
+        }
+    }
+}
+
+impl CpuTopology for SysfsCpuTopology {
+    fn read(&self) -> Result<Topology> {
+        let cpus = std::thread::available_parallelism()
+            .context("Failed to get the number of CPUs")?
+            .get();
+        Ok(Topology {
+            cpus,
+            hybrid: has_hybrid_pmus(&self.sysfs_path) || has_distinct_capacities(&self.sysfs_path),
+        })
+    }
+}
+
+/// Intel hybrid CPUs have a PMU for each core type
+fn has_hybrid_pmus(sysfs_path: &Path) -> bool {
+    let devices_dir = sysfs_path.join("devices");
+    devices_dir.join("cpu_core").exists() && devices_dir.join("cpu_atom").exists()
+}
+
+/// big.LITTLE CPUs report a different capacity for each core type
+fn has_distinct_capacities(sysfs_path: &Path) -> bool {
+    let Ok(entries) = fs::read_dir(sysfs_path.join("devices/system/cpu")) else {
+        return false;
+    };
+    let capacities: HashSet<String> = entries
+        .filter_map(|entry| fs::read_to_string(entry.ok()?.path().join("cpu_capacity")).ok())
+        .map(|capacity| capacity.trim().to_owned())
+        .collect();
+    capacities.len() > 1
+}
+
+impl Config {
+    /// Get the topology the scheduler requires to be offered and started
+    pub fn get_topology_requirement(&self, scx_sched: &SupportedSched) -> TopologyRequirement {
+        let Some(sched_config) = self.scheds.get(get_name_from_scx(scx_sched)) else {
+            return TopologyRequirement::default();
+        };
+        TopologyRequirement {
+            min_cpus: sched_config.min_cpus,
+            require_hybrid: sched_config.require_hybrid.unwrap_or(false),
+        }
+    }
+
+    /// Get the enabled schedulers which can run on the given topology, the supported ones
+    /// first followed by the custom ones. Without topology nothing is gated on it
+    pub fn available_schedulers(&self, topology: Option<&Topology>) -> Vec<String> {
+        let meets_requirement = |scx_sched: &SupportedSched| {
+            topology.is_none_or(|topology| {
+                check_requirement(&self.get_topology_requirement(scx_sched), topology).is_ok()
+            })
+        };
+        let custom_scheds = self
+            .enabled_schedulers()
+            .into_iter()
+            .filter(|sched_name| config::is_custom_sched(self, sched_name))
+            .filter(|sched_name| meets_requirement(&SupportedSched::from(sched_name.clone())));
+
+        SUPPORTED_SCHEDS
+            .iter()
+            .filter(|scx_sched| self.is_sched_enabled(scx_sched) && meets_requirement(scx_sched))
+            .map(SupportedSched::to_string)
+            .chain(custom_scheds)
+            .collect()
+    }
+}
+
+/// Check that the topology meets the requirement
+pub fn check_requirement(requirement: &TopologyRequirement, topology: &Topology) -> Result<()> {
+    if let Some(min_cpus) = requirement.min_cpus {
+        if topology.cpus < min_cpus {
+            anyhow::bail!("requires at least {min_cpus} CPUs, found {}", topology.cpus);
+        }
+    }
+    if requirement.require_hybrid && !topology.hybrid {
+        anyhow::bail!("requires a hybrid CPU");
+    }
+    Ok(())
+}
+
+/// Check the topology before starting the scheduler, it's only read if anything is required
+pub fn check_topology(
+    source: &impl CpuTopology,
+    sched_bin: &str,
+    requirement: &TopologyRequirement,
+) -> Result<()> {
+    if *requirement == TopologyRequirement::default() {
+        return Ok(());
+    }
+    let topology = source.read()?;
+    check_requirement(requirement, &topology).with_context(|| format!("{sched_bin} can't run"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::parse_config;
+    use crate::topology::*;
+
+    struct FakeCpuTopology(Topology);
+
+    impl CpuTopology for FakeCpuTopology {
+        fn read(&self) -> Result<Topology> {
+            Ok(self.0)
+        }
+    }
+
+    const EIGHT_CORES: Topology = Topology {
+        cpus: 8,
+        hybrid: false,
+    };
+
+    #[test]
+    fn test_min_cpus_excludes_scheduler() {
+        let config = parse_config(
+            r#"
+[scheds.scx_layered]
+min_cpus = 16
+
+[scheds.scx_bpfland]
+min_cpus = 8
+"#,
+        );
+
+        let available = config.available_schedulers(Some(&EIGHT_CORES));
+        assert!(!available.contains(&"scx_layered".to_owned()));
+        assert!(available.contains(&"scx_bpfland".to_owned()));
+        assert!(available.contains(&"scx_lavd".to_owned()));
+
+        let err = check_topology(
+            &FakeCpuTopology(EIGHT_CORES),
+            "scx_layered",
+            &config.get_topology_requirement(&SupportedSched::Layered),
+        )
+        .unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "scx_layered can't run: requires at least 16 CPUs, found 8"
+        );
+
+        // a large enough machine offers it
+        let many_cores = Topology {
+            cpus: 64,
+            hybrid: false,
+        };
+        assert!(config
+            .available_schedulers(Some(&many_cores))
+            .contains(&"scx_layered".to_owned()));
+        // an unknown topology doesn't gate anything
+        assert_eq!(
+            config.available_schedulers(None),
+            config.available_schedulers(Some(&many_cores))
+        );
+    }
+
+    #[test]
+    fn test_require_hybrid() {
+        let config = parse_config(
+            r#"
+[scheds.scx_lavd]
+require_hybrid = true
+"#,
+        );
+        let requirement = config.get_topology_requirement(&SupportedSched::Lavd);
+
+        assert_eq!(
+            format!(
+                "{:#}",
+                check_requirement(&requirement, &EIGHT_CORES).unwrap_err()
+            ),
+            "requires a hybrid CPU"
+        );
+        let hybrid = Topology {
+            cpus: 8,
+            hybrid: true,
+        };
+        assert!(check_requirement(&requirement, &hybrid).is_ok());
+        assert!(!config
+            .available_schedulers(Some(&EIGHT_CORES))
+            .contains(&"scx_lavd".to_owned()));
+
+        // nothing is required, so the topology isn't read
+        struct FailingCpuTopology;
+        impl CpuTopology for FailingCpuTopology {
+            fn read(&self) -> Result<Topology> {
+                anyhow::bail!("no sysfs")
+            }
+        }
+        assert!(check_topology(
+            &FailingCpuTopology,
+            "scx_bpfland",
+            &config.get_topology_requirement(&SupportedSched::Bpfland)
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_sysfs_hybrid() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cpu_dir = tmp.path().join("devices/system/cpu");
+        for (cpu, capacity) in [("cpu0", "1024"), ("cpu1", "1024")] {
+            fs::create_dir_all(cpu_dir.join(cpu)).unwrap();
+            fs::write(cpu_dir.join(cpu).join("cpu_capacity"), capacity).unwrap();
+        }
+        let topology = SysfsCpuTopology::new(tmp.path());
+        assert!(!topology.read().unwrap().hybrid);
+
+        fs::create_dir_all(cpu_dir.join("cpu2")).unwrap();
+        fs::write(cpu_dir.join("cpu2/cpu_capacity"), "512\n").unwrap();
+        assert!(topology.read().unwrap().hybrid);
+
+        // Intel hybrid CPUs are detected by their PMUs
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(tmp.path().join("devices/cpu_core")).unwrap();
+        assert!(!SysfsCpuTopology::new(tmp.path()).read().unwrap().hybrid);
+        fs::create_dir_all(tmp.path().join("devices/cpu_atom")).unwrap();
+        assert!(SysfsCpuTopology::new(tmp.path()).read().unwrap().hybrid);
+    }
+}
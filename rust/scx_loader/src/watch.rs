@@ -0,0 +1,241 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2024 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use nix::sys::inotify::AddWatchFlags;
+use nix::sys::inotify::InitFlags;
+use nix::sys::inotify::Inotify;
+use nix::sys::inotify::WatchDescriptor;
+
+/// How often re-watching a replaced config file is attempted before giving up
+const REWATCH_ATTEMPTS: u32 = 50;
+
+/// Delay between the attempts to re-watch a replaced config file
+const REWATCH_DELAY: Duration = Duration::from_millis(100);
+
+/// Change of the watched file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FsEvent {
+    /// The file was written
+    Modified,
+    /// The file was deleted or renamed, e.g. by an editor saving a new file over it
+    Removed,
+    /// Events which don't change the content
+    Other,
+}
+
+/// Watches a single path and reports what changed on it
+pub trait FsWatcher {
+    /// Watch the given path, replacing the previous watch
+    fn watch(&mut self, path: &Path) -> io::Result<()>;
+    /// Wait for the next event of the watched path
+    fn next_event(&mut self) -> io::Result<FsEvent>;
+}
+
+/// Watches a file with inotify
+pub struct InotifyWatcher {
+    inotify: Inotify,
+    watch_descriptor: Option<WatchDescriptor>,
+    events: VecDeque<FsEvent>,
+}
+
+impl InotifyWatcher {
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            inotify: Inotify::init(InitFlags::IN_CLOEXEC)?,
+            watch_descriptor: None,
+            events: VecDeque::new(),
+        })
+    }
+}
+
+impl FsWatcher for InotifyWatcher {
+    fn watch(&mut self, path: &Path) -> io::Result<()> {
+        if let Some(watch_descriptor) = self.watch_descriptor.take() {
+            // the watch is already gone if the file was removed
+            let _ = self.inotify.rm_watch(watch_descriptor);
+        }
+        let flags = AddWatchFlags::IN_CLOSE_WRITE
+            | AddWatchFlags::IN_DELETE_SELF
+            | AddWatchFlags::IN_MOVE_SELF;
+        self.watch_descriptor = Some(self.inotify.add_watch(path, flags)?);
+        Ok(())
+    }
+
+    fn next_event(&mut self) -> io::Result<FsEvent> {
+        while self.events.is_empty() {
+            for event in self.inotify.read_events()? {
+                // events of the watch of a replaced file
+                if Some(event.wd) != self.watch_descriptor {
+                    continue;
+                }
+                let fs_event = if event.mask.contains(AddWatchFlags::IN_CLOSE_WRITE) {
+                    FsEvent::Modified
+                } else if event
+                    .mask
+                    .intersects(AddWatchFlags::IN_DELETE_SELF | AddWatchFlags::IN_MOVE_SELF)
+                {
+                    FsEvent::Removed
+                } else {
+                    FsEvent::Other
+                };
+                self.events.push_back(fs_event);
+            }
+        }
+        Ok(self.events.pop_front().unwrap_or(FsEvent::Other))
+    }
+}
+
+/// Watches the config file for changes, following it when it's replaced
+pub struct ConfigWatcher<W: FsWatcher> {
+    watcher: W,
+    config_path: PathBuf,
+    rewatch_delay: Duration,
+}
+
+impl<W: FsWatcher> ConfigWatcher<W> {
+    pub fn new(mut watcher: W, config_path: &Path) -> Result<Self> {
+        watcher
+            .watch(config_path)
+            .with_context(|| format!("Failed to watch {}", config_path.display()))?;
+        Ok(Self {
+            watcher,
+            config_path: config_path.to_path_buf(),
+            rewatch_delay: REWATCH_DELAY,
+        })
+    }
+
+    /// Wait until the content of the config file changed
+    pub fn wait_for_change(&mut self) -> Result<()> {
+        loop {
+            match self.watcher.next_event()? {
+                FsEvent::Modified => return Ok(()),
+                FsEvent::Removed => {
+                    // editors commonly save by renaming a new file over the old one, which
+                    // ends the watch of the old file
+                    self.rewatch()?;
+                    return Ok(());
+                }
+                FsEvent::Other => {}
+            }
+        }
+    }
+
+    /// Watch the config path again once a file exists at it
+    fn rewatch(&mut self) -> Result<()> {
+        for _ in 0..REWATCH_ATTEMPTS {
+            match self.watcher.watch(&self.config_path) {
+                Ok(()) => return Ok(()),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    thread::sleep(self.rewatch_delay);
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        anyhow::bail!(
+            "{} was removed, no longer watching it",
+            self.config_path.display()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::watch::*;
+
+    /// Replays the given events, the watch fails while the file is missing
+    struct FakeWatcher {
+        events: VecDeque<FsEvent>,
+        missing_for: u32,
+        watched: Vec<PathBuf>,
+    }
+
+    impl FakeWatcher {
+        fn new(events: &[FsEvent]) -> Self {
+            Self {
+                events: events.iter().copied().collect(),
+                missing_for: 0,
+                watched: vec![],
+            }
+        }
+    }
+
+    impl FsWatcher for FakeWatcher {
+        fn watch(&mut self, path: &Path) -> io::Result<()> {
+            if self.missing_for > 0 {
+                self.missing_for -= 1;
+                return Err(io::Error::from(io::ErrorKind::NotFound));
+            }
+            self.watched.push(path.to_path_buf());
+            Ok(())
+        }
+
+        fn next_event(&mut self) -> io::Result<FsEvent> {
+            self.events
+                .pop_front()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))
+        }
+    }
+
+    fn new_watcher(fake_watcher: FakeWatcher) -> ConfigWatcher<FakeWatcher> {
+        let mut watcher =
+            ConfigWatcher::new(fake_watcher, Path::new("/etc/scx_loader.toml")).unwrap();
+        watcher.rewatch_delay = Duration::ZERO;
+        watcher
+    }
+
+    #[test]
+    fn test_watch_modified() {
+        let mut watcher = new_watcher(FakeWatcher::new(&[
+            FsEvent::Other,
+            FsEvent::Modified,
+            FsEvent::Modified,
+        ]));
+        watcher.wait_for_change().unwrap();
+        watcher.wait_for_change().unwrap();
+        assert!(watcher.wait_for_change().is_err());
+        assert_eq!(watcher.watcher.watched.len(), 1);
+    }
+
+    #[test]
+    fn test_watch_rename_replace() {
+        let mut watcher = new_watcher(FakeWatcher::new(&[FsEvent::Removed, FsEvent::Modified]));
+        // the new file shows up after a few attempts
+        watcher.watcher.missing_for = 2;
+
+        watcher.wait_for_change().unwrap();
+        assert_eq!(
+            watcher.watcher.watched,
+            vec![
+                PathBuf::from("/etc/scx_loader.toml"),
+                PathBuf::from("/etc/scx_loader.toml")
+            ]
+        );
+        // events of the new file are followed
+        watcher.wait_for_change().unwrap();
+    }
+
+    #[test]
+    fn test_watch_removed() {
+        let mut watcher = new_watcher(FakeWatcher::new(&[FsEvent::Removed]));
+        watcher.watcher.missing_for = u32::MAX;
+
+        let err = watcher.wait_for_change().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "/etc/scx_loader.toml was removed, no longer watching it"
+        );
+    }
+}